@@ -0,0 +1,633 @@
+// Launch specs: a declarative description of a pool, validated with the same
+// rules as `initialize_pool` before any transaction is sent, and comparable
+// with a pool that is already on-chain.
+
+use anchor_lang::AnchorDeserialize;
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use ido_pool::{
+    ErrorCode, GateKind, Lottery, ParticipationGate, ProceedsTranches, StakeTier, StakeTiers,
+    Tranche, MAX_STAKE_TIERS, MAX_TRANCHES,
+};
+use ido_pool_client::{Pool, PoolSchedule};
+use serde::Deserialize;
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Keypair;
+use solana_sdk::signer::Signer;
+use spl_token::state::{Account as TokenAccount, Mint};
+use spl_token_metadata::state::Metadata;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{format_amount, parse_amount, Context, Result};
+
+// Pool parameters ready to be sent, whether they come from the `init`
+// arguments or from a spec file.
+pub struct Launch {
+    pub usdc_mint: Pubkey,
+    pub watermelon_mint: Pubkey,
+    pub creator_watermelon: Pubkey,
+    // Receives the proceeds, the authority's associated account when unset.
+    pub creator_usdc: Option<Pubkey>,
+    pub num_ido_tokens: u64,
+    pub schedule: PoolSchedule,
+    pub sale_symbol: String,
+    pub referrals: Option<Referrals>,
+    pub kyc_verifier: Option<Pubkey>,
+    pub gate: ParticipationGate,
+    pub stake_tiers: StakeTiers,
+    pub lottery: Lottery,
+    pub tranches: ProceedsTranches,
+}
+
+pub struct Referrals {
+    pub bps: u16,
+    pub watermelon: u64,
+}
+
+impl Launch {
+    pub fn validate(&self, now: i64) -> Result<()> {
+        ido_pool::validate_pool_params(self.num_ido_tokens, &self.schedule, now)
+            .map_err(describe)?;
+        ido_pool::redeemable_token_name(&self.sale_symbol).map_err(describe)?;
+        self.gate.validate().map_err(describe)?;
+        self.stake_tiers
+            .validate(&self.schedule)
+            .map_err(describe)?;
+        if self.lottery.enabled() && self.stake_tiers.enabled() {
+            return Err(describe(ErrorCode::InvalidParam).into());
+        }
+        self.lottery.validate(&self.schedule).map_err(describe)?;
+        self.tranches.validate(&self.schedule).map_err(describe)?;
+        if let Some(referrals) = &self.referrals {
+            if referrals.bps > 10_000 {
+                return Err(describe(ErrorCode::InvalidParam).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn describe(err: ErrorCode) -> String {
+    format!("{:?}: {}", err, err)
+}
+
+// On-disk format of a launch, TOML or JSON depending on the file extension.
+// Token amounts are decimal strings in UI units, times are ISO-8601.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LaunchSpec {
+    pub usdc_mint: String,
+    pub watermelon_mint: String,
+    pub watermelon_account: String,
+    pub watermelon_amount: String,
+    pub symbol: String,
+    pub usdc_account: Option<String>,
+    pub authority: Option<String>,
+    pub start_ido: String,
+    pub end_deposits: String,
+    pub end_ido: Option<String>,
+    pub withdraw_melon: String,
+    pub referrals: Option<ReferralSpec>,
+    pub kyc_verifier: Option<String>,
+    pub gate: Option<GateSpec>,
+    pub stake_tiers: Option<StakeTiersSpec>,
+    pub lottery: Option<LotterySpec>,
+    pub tranches: Option<Vec<TrancheSpec>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReferralSpec {
+    pub bps: u16,
+    pub watermelon_amount: Option<String>,
+}
+
+// `kind` is "token" or "collection", `min_amount` is in UI units of the
+// token and only used by token gates.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GateSpec {
+    pub kind: String,
+    pub key: String,
+    pub min_amount: Option<String>,
+}
+
+// `guaranteed_end` is ISO-8601 in specs.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StakeTiersSpec {
+    pub staking_program: String,
+    pub stake_mint: String,
+    pub guaranteed_end: String,
+    pub tiers: Vec<TierSpec>,
+}
+
+// `min_stake` is in UI units of the stake mint, `allocation` in 💵.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TierSpec {
+    pub min_stake: String,
+    pub allocation: String,
+}
+
+// `ticket_usdc` is in 💵, `commitment` the base58 `lottery_commitment` of
+// the draw secret.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LotterySpec {
+    pub ticket_usdc: String,
+    pub winning_tickets: u64,
+    pub commitment: String,
+}
+
+// `release` is ISO-8601 in specs, `bps` the share of the raise released.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrancheSpec {
+    pub release: String,
+    pub bps: u16,
+}
+
+// Builds the proceeds tranches of a pool from `(release_ts, bps)` pairs,
+// shared by `launch` and `configure-tranches`.
+pub fn parse_tranches(tranches: &[(i64, u16)]) -> Result<ProceedsTranches> {
+    if tranches.len() > MAX_TRANCHES {
+        return Err(format!("at most {} tranches are supported", MAX_TRANCHES).into());
+    }
+    let mut proceeds_tranches = ProceedsTranches::default();
+    for (tranche, (release_ts, bps)) in proceeds_tranches.tranches.iter_mut().zip(tranches) {
+        *tranche = Tranche {
+            release_ts: *release_ts,
+            bps: *bps,
+        };
+    }
+    Ok(proceeds_tranches)
+}
+
+// Builds the lottery of a pool, shared by `launch` and `configure-lottery`.
+pub fn parse_lottery(
+    ticket_usdc: &str,
+    winning_tickets: u64,
+    commitment: [u8; 32],
+    usdc_decimals: u8,
+) -> Result<Lottery> {
+    Ok(Lottery {
+        ticket_usdc: parse_amount(ticket_usdc, usdc_decimals)?,
+        winning_tickets,
+        commitment,
+        ..Lottery::default()
+    })
+}
+
+// Builds the stake tiers of a pool, shared by `launch` and `configure-tiers`.
+pub fn parse_stake_tiers(
+    ctx: &Context,
+    staking_program: Pubkey,
+    stake_mint: Pubkey,
+    guaranteed_end_ts: i64,
+    tiers: &[TierSpec],
+    usdc_decimals: u8,
+) -> Result<StakeTiers> {
+    if tiers.len() > MAX_STAKE_TIERS {
+        return Err(format!("at most {} tiers are supported", MAX_STAKE_TIERS).into());
+    }
+    let stake_decimals = ctx.rpc.get_packed::<Mint>(&stake_mint)?.decimals;
+    let mut stake_tiers = StakeTiers {
+        staking_program,
+        stake_mint,
+        guaranteed_end_ts,
+        ..StakeTiers::default()
+    };
+    for (tier, spec) in stake_tiers.tiers.iter_mut().zip(tiers) {
+        *tier = StakeTier {
+            min_stake: parse_amount(&spec.min_stake, stake_decimals)?,
+            allocation: parse_amount(&spec.allocation, usdc_decimals)?,
+        };
+    }
+    Ok(stake_tiers)
+}
+
+// Builds a participation gate from its textual form, shared by `launch` and
+// `configure-gate`.
+pub fn parse_gate(
+    ctx: &Context,
+    kind: &str,
+    key: Option<&str>,
+    min_amount: Option<&str>,
+) -> Result<ParticipationGate> {
+    let key = match key {
+        Some(key) => Pubkey::from_str(key)?,
+        None => Pubkey::default(),
+    };
+    let kind = match kind {
+        "none" => GateKind::None,
+        "token" => GateKind::Token,
+        "collection" => GateKind::Collection,
+        _ => return Err(format!("unknown gate kind {}", kind).into()),
+    };
+    let min_amount = match (kind, min_amount) {
+        (GateKind::Token, Some(amount)) => {
+            let decimals = ctx.rpc.get_packed::<Mint>(&key)?.decimals;
+            parse_amount(amount, decimals)?
+        }
+        _ => 0,
+    };
+    Ok(ParticipationGate {
+        kind,
+        key,
+        min_amount,
+    })
+}
+
+impl LaunchSpec {
+    pub fn load(path: &str) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Ok(serde_json::from_str(&contents)?),
+            _ => Ok(toml::from_str(&contents)?),
+        }
+    }
+
+    pub fn authority(&self) -> Result<Option<Pubkey>> {
+        match &self.authority {
+            Some(authority) => Ok(Some(Pubkey::from_str(authority)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn resolve(&self, ctx: &Context) -> Result<Launch> {
+        let usdc_mint = Pubkey::from_str(&self.usdc_mint)?;
+        let watermelon_mint = Pubkey::from_str(&self.watermelon_mint)?;
+        let decimals = ctx.rpc.get_packed::<Mint>(&watermelon_mint)?.decimals;
+        let end_deposits_ts = parse_time(&self.end_deposits)?;
+        let referrals = match &self.referrals {
+            Some(referrals) => Some(Referrals {
+                bps: referrals.bps,
+                watermelon: match &referrals.watermelon_amount {
+                    Some(amount) => parse_amount(amount, decimals)?,
+                    None => 0,
+                },
+            }),
+            None => None,
+        };
+        let usdc_decimals = ctx.rpc.get_packed::<Mint>(&usdc_mint)?.decimals;
+        let stake_tiers = match &self.stake_tiers {
+            Some(spec) => parse_stake_tiers(
+                ctx,
+                Pubkey::from_str(&spec.staking_program)?,
+                Pubkey::from_str(&spec.stake_mint)?,
+                parse_time(&spec.guaranteed_end)?,
+                &spec.tiers,
+                usdc_decimals,
+            )?,
+            None => StakeTiers::default(),
+        };
+        let lottery = match &self.lottery {
+            Some(spec) => parse_lottery(
+                &spec.ticket_usdc,
+                spec.winning_tickets,
+                Hash::from_str(&spec.commitment)?.to_bytes(),
+                usdc_decimals,
+            )?,
+            None => Lottery::default(),
+        };
+        let tranches = match &self.tranches {
+            Some(spec) => parse_tranches(
+                &spec
+                    .iter()
+                    .map(|tranche| Ok((parse_time(&tranche.release)?, tranche.bps)))
+                    .collect::<Result<Vec<_>>>()?,
+            )?,
+            None => ProceedsTranches::default(),
+        };
+        Ok(Launch {
+            usdc_mint,
+            watermelon_mint,
+            creator_watermelon: Pubkey::from_str(&self.watermelon_account)?,
+            creator_usdc: match &self.usdc_account {
+                Some(account) => Some(Pubkey::from_str(account)?),
+                None => None,
+            },
+            num_ido_tokens: parse_amount(&self.watermelon_amount, decimals)?,
+            schedule: PoolSchedule {
+                start_ido_ts: parse_time(&self.start_ido)?,
+                end_deposits_ts,
+                end_ido_ts: match &self.end_ido {
+                    Some(end_ido) => parse_time(end_ido)?,
+                    None => end_deposits_ts,
+                },
+                withdraw_melon_ts: parse_time(&self.withdraw_melon)?,
+            },
+            sale_symbol: self.symbol.clone(),
+            referrals,
+            kyc_verifier: match &self.kyc_verifier {
+                Some(verifier) => Some(Pubkey::from_str(verifier)?),
+                None => None,
+            },
+            gate: match &self.gate {
+                Some(gate) => {
+                    parse_gate(ctx, &gate.kind, Some(&gate.key), gate.min_amount.as_deref())?
+                }
+                None => ParticipationGate::default(),
+            },
+            stake_tiers,
+            lottery,
+            tranches,
+        })
+    }
+}
+
+fn parse_time(time: &str) -> Result<i64> {
+    DateTime::parse_from_rfc3339(time)
+        .map(|time| time.timestamp())
+        .map_err(|err| format!("invalid time {}: {}", time, err).into())
+}
+
+fn format_time(ts: i64) -> String {
+    match Utc.timestamp_opt(ts, 0).single() {
+        Some(time) => time.to_rfc3339_opts(SecondsFormat::Secs, true),
+        None => ts.to_string(),
+    }
+}
+
+pub fn format_gate(gate: &ParticipationGate) -> String {
+    match gate.kind {
+        GateKind::None => "none".to_string(),
+        GateKind::Token => format!("token {} >= {}", gate.key, gate.min_amount),
+        GateKind::Collection => format!("collection {}", gate.key),
+    }
+}
+
+pub fn format_stake_tiers(stake_tiers: &StakeTiers) -> String {
+    if !stake_tiers.enabled() {
+        return "none".to_string();
+    }
+    let tiers = stake_tiers
+        .tiers
+        .iter()
+        .take_while(|tier| tier.min_stake > 0)
+        .map(|tier| format!("{}:{}", tier.min_stake, tier.allocation))
+        .collect::<Vec<_>>();
+    format!(
+        "{} {} until {} {}",
+        stake_tiers.staking_program,
+        stake_tiers.stake_mint,
+        format_time(stake_tiers.guaranteed_end_ts),
+        tiers.join(",")
+    )
+}
+
+pub fn format_lottery(lottery: &Lottery) -> String {
+    if !lottery.enabled() {
+        return "none".to_string();
+    }
+    format!(
+        "{} winning tickets at {} committed to {}",
+        lottery.winning_tickets,
+        lottery.ticket_usdc,
+        Hash::new(&lottery.commitment)
+    )
+}
+
+pub fn format_tranches(tranches: &ProceedsTranches) -> String {
+    if !tranches.enabled() {
+        return "none".to_string();
+    }
+    tranches
+        .tranches
+        .iter()
+        .take_while(|tranche| tranche.bps > 0)
+        .map(|tranche| format!("{}:{}", format_time(tranche.release_ts), tranche.bps))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// Creates the pool with its referral, KYC, gate, stake tier, lottery and
+// tranche configuration. The vaults, the redeemable mint and its metadata are created
+// by `initialize_pool`.
+pub fn create_pool(ctx: &Context, launch: &Launch, authority: Option<&Keypair>) -> Result<Pubkey> {
+    let authority_pubkey = authority
+        .map(Signer::pubkey)
+        .unwrap_or_else(|| ctx.wallet.pubkey());
+    let watermelon = ctx.rpc.get_packed::<Mint>(&launch.watermelon_mint)?;
+    println!("Decimals: {}", watermelon.decimals);
+
+    let pool_account = Keypair::new();
+    let redeemable_mint = ido_pool_client::redeemable_mint_address(&pool_account.pubkey());
+    let pool_watermelon = ido_pool_client::pool_watermelon_address(&pool_account.pubkey());
+    let pool_usdc = ido_pool_client::pool_usdc_address(&pool_account.pubkey());
+    let payer = ctx.wallet.pubkey();
+    let creator_usdc = match launch.creator_usdc {
+        Some(account) => account,
+        None => ctx.ensure_ata(&authority_pubkey, &launch.usdc_mint)?,
+    };
+
+    let mut ixs = vec![ido_pool_client::initialize_pool(
+        &ido_pool_client::InitializePoolAccounts {
+            pool_account: pool_account.pubkey(),
+            usdc_mint: launch.usdc_mint,
+            watermelon_mint: launch.watermelon_mint,
+            distribution_authority: authority_pubkey,
+            payer,
+            creator_watermelon: launch.creator_watermelon,
+            creator_usdc,
+        },
+        launch.num_ido_tokens,
+        launch.schedule,
+        &launch.sale_symbol,
+    )];
+    if let Some(referrals) = &launch.referrals {
+        ixs.push(ido_pool_client::configure_referrals(
+            &pool_account.pubkey(),
+            &pool_watermelon,
+            &authority_pubkey,
+            &payer,
+            &launch.creator_watermelon,
+            referrals.bps,
+            referrals.watermelon,
+        ));
+    }
+    if let Some(kyc_verifier) = &launch.kyc_verifier {
+        ixs.push(ido_pool_client::configure_kyc(
+            &pool_account.pubkey(),
+            &authority_pubkey,
+            &payer,
+            kyc_verifier,
+        ));
+    }
+    if launch.gate.kind != GateKind::None {
+        ixs.push(ido_pool_client::configure_gate(
+            &pool_account.pubkey(),
+            &authority_pubkey,
+            &payer,
+            launch.gate,
+        ));
+    }
+    if launch.stake_tiers.enabled() {
+        ixs.push(ido_pool_client::configure_stake_tiers(
+            &pool_account.pubkey(),
+            &authority_pubkey,
+            &payer,
+            launch.stake_tiers,
+        ));
+    }
+    if launch.lottery.enabled() {
+        ixs.push(ido_pool_client::configure_lottery(
+            &pool_account.pubkey(),
+            &authority_pubkey,
+            &payer,
+            launch.lottery.ticket_usdc,
+            launch.lottery.winning_tickets,
+            launch.lottery.commitment,
+        ));
+    }
+    if launch.tranches.enabled() {
+        ixs.push(ido_pool_client::configure_tranches(
+            &pool_account.pubkey(),
+            &authority_pubkey,
+            &payer,
+            launch.tranches.tranches,
+        ));
+    }
+    let mut signers = vec![&pool_account];
+    if let Some(authority) = authority {
+        signers.push(authority);
+    }
+    ctx.send(&ixs, &signers)?;
+
+    println!(
+        "🏦 IDO pool initialized with {} tokens",
+        format_amount(launch.num_ido_tokens, watermelon.decimals)
+    );
+    println!("Pool Account: {}", pool_account.pubkey());
+    println!("Pool Authority: {}", authority_pubkey);
+    println!("Redeem Mint: {}", redeemable_mint);
+    println!("🍉 Account: {}", pool_watermelon);
+    println!("💵 Account: {}", pool_usdc);
+    println!("Proceeds Account: {}", creator_usdc);
+    Ok(pool_account.pubkey())
+}
+
+// Prints the spec next to the on-chain pool and fails if they differ.
+pub fn diff(ctx: &Context, spec: &LaunchSpec, pool: &Pool) -> Result<()> {
+    let launch = spec.resolve(ctx)?;
+    let account = &pool.account;
+    let pool_usdc = ctx.rpc.get_packed::<TokenAccount>(&account.pool_usdc)?;
+    let referrals = launch.referrals.as_ref();
+
+    let mut rows = vec![
+        (
+            "usdcMint",
+            launch.usdc_mint.to_string(),
+            pool_usdc.mint.to_string(),
+        ),
+        (
+            "watermelonMint",
+            launch.watermelon_mint.to_string(),
+            account.watermelon_mint.to_string(),
+        ),
+        (
+            "numIdoTokens",
+            launch.num_ido_tokens.to_string(),
+            account.num_ido_tokens.to_string(),
+        ),
+        (
+            "startIdoTs",
+            format_time(launch.schedule.start_ido_ts),
+            format_time(account.schedule.start_ido_ts),
+        ),
+        (
+            "endDepositsTs",
+            format_time(launch.schedule.end_deposits_ts),
+            format_time(account.schedule.end_deposits_ts),
+        ),
+        (
+            "endIdoTs",
+            format_time(launch.schedule.end_ido_ts),
+            format_time(account.schedule.end_ido_ts),
+        ),
+        (
+            "withdrawMelonTs",
+            format_time(launch.schedule.withdraw_melon_ts),
+            format_time(account.schedule.withdraw_melon_ts),
+        ),
+        (
+            "referralBps",
+            referrals.map_or(0, |r| r.bps).to_string(),
+            account.referral_bps.to_string(),
+        ),
+        (
+            "referralWatermelon",
+            referrals.map_or(0, |r| r.watermelon).to_string(),
+            account.referral_watermelon.to_string(),
+        ),
+    ];
+    rows.push((
+        "kycVerifier",
+        launch.kyc_verifier.unwrap_or_default().to_string(),
+        account.kyc_verifier.to_string(),
+    ));
+    rows.push((
+        "gate",
+        format_gate(&launch.gate),
+        format_gate(&account.gate),
+    ));
+    rows.push((
+        "stakeTiers",
+        format_stake_tiers(&launch.stake_tiers),
+        format_stake_tiers(&account.stake_tiers),
+    ));
+    rows.push((
+        "lottery",
+        format_lottery(&launch.lottery),
+        format_lottery(&account.lottery),
+    ));
+    rows.push((
+        "tranches",
+        format_tranches(&launch.tranches),
+        format_tranches(&account.tranches),
+    ));
+    if let Some(creator_usdc) = launch.creator_usdc {
+        rows.push((
+            "creatorUsdc",
+            creator_usdc.to_string(),
+            account.creator_usdc.to_string(),
+        ));
+    }
+    let metadata = ido_pool_client::redeemable_metadata_address(&account.redeemable_mint);
+    let metadata = ctx.rpc.get_required_account_data(&metadata)?;
+    let metadata = Metadata::deserialize(&mut metadata.as_slice())?;
+    rows.push((
+        "redeemableSymbol",
+        ido_pool::redeemable_token_name(&launch.sale_symbol)
+            .map(|(_, symbol)| symbol)
+            .unwrap_or_default(),
+        metadata.data.symbol.trim_end_matches('\0').to_string(),
+    ));
+    if let Some(authority) = spec.authority()? {
+        rows.push((
+            "distributionAuthority",
+            authority.to_string(),
+            account.distribution_authority.to_string(),
+        ));
+    }
+
+    println!("  {:<22} {:<46} on-chain", "field", "spec");
+    let mut differences = 0;
+    for (name, expected, actual) in rows {
+        let marker = if expected == actual {
+            " "
+        } else {
+            differences += 1;
+            "*"
+        };
+        println!("{} {:<22} {:<46} {}", marker, name, expected, actual);
+    }
+    if differences > 0 {
+        return Err(format!("{} field(s) differ from the spec", differences).into());
+    }
+    println!("pool matches the spec");
+    Ok(())
+}
@@ -0,0 +1,1315 @@
+//! Command line interface for the ido-pool program.
+//!
+//! The RPC url and the wallet keypair are read from the Solana CLI config
+//! (`solana config set ...`) unless overridden with `--url` / `--keypair`.
+
+use anchor_lang::AccountDeserialize;
+use clap::{crate_description, crate_name, App, AppSettings, Arg, ArgMatches, SubCommand};
+use ido_pool::{GateKind, Lottery, ParticipationGate, ProceedsTranches, StakeTiers, UserPosition};
+use ido_pool_client::{Pool, PoolSchedule};
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signature};
+use solana_sdk::signer::Signer;
+use solana_sdk::transaction::Transaction;
+use spl_associated_token_account::{create_associated_token_account, get_associated_token_address};
+use spl_token::state::{Account as TokenAccount, Mint};
+use std::error::Error;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod launch;
+mod multisig;
+mod rpc;
+
+use launch::{Launch, LaunchSpec, TierSpec};
+use multisig::Multisig;
+use rpc::Rpc;
+
+pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+struct Context {
+    rpc: Rpc,
+    wallet: Keypair,
+    multisig: Option<Multisig>,
+    dry_run: bool,
+}
+
+impl Context {
+    fn from_matches(matches: &ArgMatches) -> Result<Self> {
+        let config = match matches.value_of("config") {
+            Some(path) => solana_cli_config::Config::load(path)?,
+            None => solana_cli_config::CONFIG_FILE
+                .as_ref()
+                .and_then(|path| solana_cli_config::Config::load(path).ok())
+                .unwrap_or_default(),
+        };
+        let url = matches
+            .value_of("url")
+            .map(str::to_string)
+            .unwrap_or(config.json_rpc_url);
+        let keypair_path = matches
+            .value_of("keypair")
+            .map(str::to_string)
+            .unwrap_or(config.keypair_path);
+        let wallet = read_keypair(&keypair_path)?;
+        let commitment = if config.commitment.is_empty() {
+            "confirmed".to_string()
+        } else {
+            config.commitment
+        };
+        let rpc = Rpc::new(url, commitment);
+
+        let multisig = match matches.value_of("multisig") {
+            Some(address) => Some(Multisig::load(
+                &rpc,
+                pubkey_of(matches, "multisig_program")?,
+                Pubkey::from_str(address)?,
+            )?),
+            None => None,
+        };
+
+        Ok(Context {
+            rpc,
+            wallet,
+            multisig,
+            dry_run: matches.is_present("dry_run"),
+        })
+    }
+
+    // Distribution authority used for admin instructions.
+    fn authority(&self) -> Pubkey {
+        match &self.multisig {
+            Some(multisig) => multisig.signer,
+            None => self.wallet.pubkey(),
+        }
+    }
+
+    // Pools moved by `migrate_pool` are still found at the address they were
+    // created at.
+    fn load_pool(&self, address: Pubkey) -> Result<Pool> {
+        let (address, data) = match self.rpc.get_account_data(&address)? {
+            Some(data) => (address, data),
+            None => {
+                let migrated = ido_pool_client::migrated_pool_address(&address);
+                (migrated, self.rpc.get_required_account_data(&migrated)?)
+            }
+        };
+        Ok(Pool::from_account_data(address, &data)?)
+    }
+
+    // Associated token account of `owner`, created on the fly if missing.
+    fn ensure_ata(&self, owner: &Pubkey, mint: &Pubkey) -> Result<Pubkey> {
+        let address = get_associated_token_address(owner, mint);
+        if self.rpc.get_account_data(&address)?.is_none() {
+            println!("creating associated token account {}", address);
+            self.send(
+                &[create_associated_token_account(
+                    &self.wallet.pubkey(),
+                    owner,
+                    mint,
+                )],
+                &[],
+            )?;
+        }
+        Ok(address)
+    }
+
+    // Signs `ixs` with the wallet and `signers` and sends them in one
+    // transaction, or only prints them on a dry run.
+    fn send(&self, ixs: &[Instruction], signers: &[&Keypair]) -> Result<()> {
+        if self.dry_run {
+            ixs.iter().for_each(print_instruction);
+            println!("dry run");
+            return Ok(());
+        }
+        let mut all_signers = vec![&self.wallet];
+        all_signers.extend_from_slice(signers);
+        let tx = Transaction::new_signed_with_payer(
+            ixs,
+            Some(&self.wallet.pubkey()),
+            &all_signers,
+            self.rpc.get_latest_blockhash()?,
+        );
+        println!("txid: {}", self.rpc.send_and_confirm(&tx)?);
+        Ok(())
+    }
+
+    // Sends an instruction signed by the distribution authority, wrapping it
+    // into a multisig proposal when `--multisig` is set.
+    fn send_admin(&self, pool: Option<&Pool>, ix: Instruction) -> Result<()> {
+        if let Some(pool) = pool {
+            if pool.account.distribution_authority != self.authority() {
+                return Err(format!(
+                    "pool authority is {}, not {}",
+                    pool.account.distribution_authority,
+                    self.authority()
+                )
+                .into());
+            }
+        }
+        match &self.multisig {
+            Some(multisig) => {
+                let proposal = Keypair::new();
+                if self.dry_run {
+                    print_instruction(&ix);
+                    println!("dry run");
+                    return Ok(());
+                }
+                let ixs = multisig.propose(&self.rpc, &self.wallet.pubkey(), &proposal, &ix)?;
+                self.send(&ixs, &[&proposal])?;
+                println!("multisig transaction: {}", proposal.pubkey());
+                Ok(())
+            }
+            None => self.send(&[ix], &[]),
+        }
+    }
+}
+
+fn main() {
+    let matches = app().get_matches();
+    if let Err(err) = run(&matches) {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn app<'a, 'b>() -> App<'a, 'b> {
+    let pool_account = Arg::with_name("pool_account")
+        .required(true)
+        .help("the token sale pool account 🏦");
+    // Proofs taken by both bids and lottery tickets.
+    let participation_args = [
+        Arg::with_name("kyc_signature")
+            .long("kyc_signature")
+            .takes_value(true)
+            .requires_all(&["kyc_expiry", "kyc_allocation"])
+            .help("the KYC verifier's signature of the attestation, needed by pools with a verifier"),
+        Arg::with_name("kyc_expiry")
+            .long("kyc_expiry")
+            .takes_value(true)
+            .help("the unix time at which the KYC attestation expires"),
+        Arg::with_name("kyc_allocation")
+            .long("kyc_allocation")
+            .takes_value(true)
+            .help("the 💵 allocation granted by the KYC attestation, in minimum unit"),
+        Arg::with_name("nft_mint")
+            .long("nft_mint")
+            .takes_value(true)
+            .help("the mint of the NFT proving membership of the collection, needed by collection gated pools"),
+    ];
+    App::new(crate_name!())
+        .about(crate_description!())
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .short("C")
+                .global(true)
+                .takes_value(true)
+                .help("Solana CLI config file, defaults to the one used by `solana`"),
+        )
+        .arg(
+            Arg::with_name("url")
+                .long("url")
+                .short("u")
+                .global(true)
+                .takes_value(true)
+                .help("RPC url, overrides the config file"),
+        )
+        .arg(
+            Arg::with_name("keypair")
+                .long("keypair")
+                .short("k")
+                .global(true)
+                .takes_value(true)
+                .help("wallet keypair, overrides the config file"),
+        )
+        .arg(
+            Arg::with_name("multisig")
+                .long("multisig")
+                .global(true)
+                .takes_value(true)
+                .help("propose admin instructions to this serum multisig instead of sending them"),
+        )
+        .arg(
+            Arg::with_name("multisig_program")
+                .long("multisig-program")
+                .global(true)
+                .takes_value(true)
+                .default_value(multisig::DEFAULT_PROGRAM_ID)
+                .help("serum multisig program id"),
+        )
+        .arg(
+            Arg::with_name("dry_run")
+                .long("dry-run")
+                .global(true)
+                .help("print instructions instead of sending them"),
+        )
+        .subcommand(
+            SubCommand::with_name("init")
+                .about("initialize IDO pool")
+                .arg(
+                    Arg::with_name("usdc_mint")
+                        .required(true)
+                        .help("the mint of the token sale bids 💵"),
+                )
+                .arg(
+                    Arg::with_name("watermelon_mint")
+                        .required(true)
+                        .help("the mint of the token for sale 🍉"),
+                )
+                .arg(
+                    Arg::with_name("watermelon_account")
+                        .required(true)
+                        .help("the account supplying the token for sale 🍉"),
+                )
+                .arg(
+                    Arg::with_name("watermelon_amount")
+                        .required(true)
+                        .help("the amount of tokens offered in this sale 🍉"),
+                )
+                .arg(
+                    Arg::with_name("symbol")
+                        .required(true)
+                        .help("the symbol of the token for sale, names the redeemable token"),
+                )
+                .arg(
+                    Arg::with_name("authority")
+                        .long("authority")
+                        .takes_value(true)
+                        .help("distribution authority keypair, defaults to the wallet"),
+                )
+                .arg(
+                    Arg::with_name("usdc_account")
+                        .long("usdc_account")
+                        .takes_value(true)
+                        .help("the 💵 token account receiving the proceeds, defaults to the authority's associated account"),
+                )
+                .arg(
+                    Arg::with_name("start_time")
+                        .long("start_time")
+                        .takes_value(true)
+                        .help("the unix time at which the token sale is starting [default: in 60s]"),
+                )
+                .arg(
+                    Arg::with_name("deposit_duration")
+                        .long("deposit_duration")
+                        .takes_value(true)
+                        .default_value("86400")
+                        .help("the number of seconds users can deposit into the pool"),
+                )
+                .arg(
+                    Arg::with_name("cancel_duration")
+                        .long("cancel_duration")
+                        .takes_value(true)
+                        .default_value("86400")
+                        .help("the number of seconds users can withdraw from the pool to cancel their bid"),
+                )
+                .arg(
+                    Arg::with_name("withdraw_ts")
+                        .long("withdraw_ts")
+                        .takes_value(true)
+                        .help("the timestamp users can withdraw watermelon from pool after ido over [default: in 3 days]"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("launch")
+                .about("validate a launch spec and create the pool it describes")
+                .arg(
+                    Arg::with_name("spec")
+                        .required(true)
+                        .help("TOML or JSON launch spec"),
+                )
+                .arg(
+                    Arg::with_name("authority")
+                        .long("authority")
+                        .takes_value(true)
+                        .help("distribution authority keypair, defaults to the wallet"),
+                )
+                .arg(
+                    Arg::with_name("check")
+                        .long("check")
+                        .help("only validate the spec"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("compare a launch spec with an existing pool")
+                .arg(
+                    Arg::with_name("spec")
+                        .required(true)
+                        .help("TOML or JSON launch spec"),
+                )
+                .arg(pool_account.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("inspect")
+                .about("inspect pool config")
+                .arg(pool_account.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("bid")
+                .about("place bid in IDO sale")
+                .arg(pool_account.clone())
+                .arg(
+                    Arg::with_name("usdc_account")
+                        .required(true)
+                        .help("the account supplying the token sale bids 💵"),
+                )
+                .arg(
+                    Arg::with_name("usdc_amount")
+                        .required(true)
+                        .help("the amount of tokens bid for this sale 💵"),
+                )
+                .arg(
+                    Arg::with_name("redeemable_account")
+                        .help("the account receiving the redeemable pool token, defaults to the wallet's associated account"),
+                )
+                .arg(
+                    Arg::with_name("referrer")
+                        .long("referrer")
+                        .takes_value(true)
+                        .help("the wallet that referred this bid"),
+                )
+                .args(&participation_args),
+        )
+        .subcommand(
+            SubCommand::with_name("ticket")
+                .about("buy a lottery ticket, then claim it if it won or get the 💵 back")
+                .arg(pool_account.clone())
+                .arg(
+                    Arg::with_name("action")
+                        .required(true)
+                        .possible_values(&["buy", "claim", "refund"]),
+                )
+                .arg(
+                    Arg::with_name("usdc_account")
+                        .long("usdc_account")
+                        .takes_value(true)
+                        .help("the account paying for the ticket and receiving refunds, defaults to the wallet's associated account"),
+                )
+                .args(&participation_args),
+        )
+        .subcommand(
+            SubCommand::with_name("redeem")
+                .about("exchange redeemable tokens for 🍉")
+                .arg(pool_account.clone())
+                .arg(
+                    Arg::with_name("redeemable_account")
+                        .help("the account holding the redeemable pool token, defaults to the wallet's associated account"),
+                )
+                .arg(
+                    Arg::with_name("watermelon_account")
+                        .long("watermelon_account")
+                        .takes_value(true)
+                        .help("the account receiving 🍉, defaults to the wallet's associated account"),
+                )
+                .arg(
+                    Arg::with_name("amount")
+                        .long("amount")
+                        .takes_value(true)
+                        .help("redeemable tokens to exchange, defaults to the whole balance"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("modify-times")
+                .about("reschedule the pool")
+                .arg(pool_account.clone())
+                .arg(Arg::with_name("start_ido").required(true))
+                .arg(Arg::with_name("end_deposits").required(true))
+                .arg(Arg::with_name("end_ido").required(true))
+                .arg(Arg::with_name("withdraw_melon").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("withdraw-usdc")
+                .about("withdraw usdc")
+                .arg(pool_account.clone())
+                .arg(
+                    Arg::with_name("amount")
+                        .long("amount")
+                        .takes_value(true)
+                        .help("token amount in minimum unit, defaults to everything withdrawable"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("withdraw-watermelon")
+                .about("withdraw watermelon")
+                .arg(pool_account.clone())
+                .arg(
+                    Arg::with_name("amount")
+                        .long("amount")
+                        .takes_value(true)
+                        .help("token amount in minimum unit, defaults to everything the pool allows withdrawing"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("propose-proceeds")
+                .about("propose new accounts receiving the proceeds and unsold watermelon")
+                .arg(pool_account.clone())
+                .arg(
+                    Arg::with_name("usdc_account")
+                        .required(true)
+                        .help("the 💵 token account receiving the proceeds"),
+                )
+                .arg(
+                    Arg::with_name("watermelon_account")
+                        .required(true)
+                        .help("the 🍉 token account receiving unsold tokens"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("configure-kyc")
+                .about("set the key attesting depositors passed KYC, before the sale starts")
+                .arg(pool_account.clone())
+                .arg(
+                    Arg::with_name("verifier")
+                        .required(true)
+                        .help("the KYC verifier public key, 11111111111111111111111111111111 lets anyone deposit"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("configure-gate")
+                .about("set the holdings depositors need, before the sale starts")
+                .arg(pool_account.clone())
+                .arg(
+                    Arg::with_name("kind")
+                        .required(true)
+                        .possible_values(&["none", "token", "collection"])
+                        .help("what depositors have to hold"),
+                )
+                .arg(
+                    Arg::with_name("key")
+                        .required_ifs(&[("kind", "token"), ("kind", "collection")])
+                        .help("the token mint, or the mint of the collection NFT"),
+                )
+                .arg(
+                    Arg::with_name("min_amount")
+                        .long("min_amount")
+                        .takes_value(true)
+                        .required_if("kind", "token")
+                        .help("the amount of tokens depositors have to hold"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("configure-tiers")
+                .about("set the stake tiers of guaranteed allocations, before the sale starts")
+                .arg(pool_account.clone())
+                .arg(
+                    Arg::with_name("staking_program")
+                        .required_unless("clear")
+                        .help("the program holding the stakes, it must lock them until the guaranteed round ends"),
+                )
+                .arg(
+                    Arg::with_name("stake_mint")
+                        .required_unless("clear")
+                        .help("the mint of the staked token"),
+                )
+                .arg(
+                    Arg::with_name("guaranteed_end")
+                        .required_unless("clear")
+                        .help("unix time at which the guaranteed round ends and deposits open to everyone"),
+                )
+                .arg(
+                    Arg::with_name("tier")
+                        .long("tier")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .required_unless("clear")
+                        .help("a tier as <min_stake>:<usdc_allocation>, by increasing stake"),
+                )
+                .arg(
+                    Arg::with_name("clear")
+                        .long("clear")
+                        .help("remove the tiers, deposits are open to everyone"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("register-stake")
+                .about("snapshot the tier of your stake before the sale starts")
+                .arg(pool_account.clone())
+                .arg(
+                    Arg::with_name("stake_vault")
+                        .help("the token account holding the stake, defaults to the associated account of the stake authority"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("configure-lottery")
+                .about("allocate the sale by lottery, before the sale starts")
+                .arg(pool_account.clone())
+                .arg(
+                    Arg::with_name("ticket_usdc")
+                        .required_unless("clear")
+                        .help("the price of a ticket 💵"),
+                )
+                .arg(
+                    Arg::with_name("winning_tickets")
+                        .required_unless("clear")
+                        .help("how many tickets win"),
+                )
+                .arg(
+                    Arg::with_name("commitment")
+                        .long("commitment")
+                        .takes_value(true)
+                        .help("the commitment to the draw secret, a new secret is generated and printed when missing"),
+                )
+                .arg(
+                    Arg::with_name("clear")
+                        .long("clear")
+                        .help("remove the lottery, deposits are first come first served"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("draw-lottery")
+                .about("pick the draw slot once deposits are over, then run again after it to reveal the secret and draw")
+                .arg(pool_account.clone())
+                .arg(
+                    Arg::with_name("secret")
+                        .required(true)
+                        .help("the secret printed by configure-lottery"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("configure-tranches")
+                .about("release the 💵 raised in tranches contributors can vote to halt, before the sale starts")
+                .arg(pool_account.clone())
+                .arg(
+                    Arg::with_name("tranche")
+                        .long("tranche")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .required_unless("clear")
+                        .help("a tranche as <unix_release_time>:<bps>, by release time"),
+                )
+                .arg(
+                    Arg::with_name("clear")
+                        .long("clear")
+                        .help("remove the tranches, the 💵 raised is released when claims open"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("block")
+                .about("block a wallet from depositing and claiming in every pool")
+                .arg(
+                    Arg::with_name("wallet")
+                        .required(true)
+                        .help("the wallet to block"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("unblock")
+                .about("lift the block of a wallet")
+                .arg(
+                    Arg::with_name("wallet")
+                        .required(true)
+                        .help("the wallet to unblock"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("apply-proceeds")
+                .about("apply the proposed proceeds accounts once the timelock has passed")
+                .arg(pool_account.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("cancel-proceeds")
+                .about("drop the proposed proceeds accounts")
+                .arg(pool_account),
+        )
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let ctx = Context::from_matches(matches)?;
+    match matches.subcommand() {
+        ("init", Some(args)) => init(&ctx, args),
+        ("launch", Some(args)) => launch(&ctx, args),
+        ("diff", Some(args)) => diff(&ctx, args),
+        ("inspect", Some(args)) => inspect(&ctx, args),
+        ("bid", Some(args)) => bid(&ctx, args),
+        ("ticket", Some(args)) => ticket(&ctx, args),
+        ("redeem", Some(args)) => redeem(&ctx, args),
+        ("modify-times", Some(args)) => modify_times(&ctx, args),
+        ("withdraw-usdc", Some(args)) => withdraw_usdc(&ctx, args),
+        ("withdraw-watermelon", Some(args)) => withdraw_watermelon(&ctx, args),
+        ("propose-proceeds", Some(args)) => propose_proceeds(&ctx, args),
+        ("configure-kyc", Some(args)) => configure_kyc(&ctx, args),
+        ("configure-gate", Some(args)) => configure_gate(&ctx, args),
+        ("configure-tiers", Some(args)) => configure_tiers(&ctx, args),
+        ("register-stake", Some(args)) => register_stake(&ctx, args),
+        ("configure-lottery", Some(args)) => configure_lottery(&ctx, args),
+        ("draw-lottery", Some(args)) => draw_lottery(&ctx, args),
+        ("configure-tranches", Some(args)) => configure_tranches(&ctx, args),
+        ("block", Some(args)) => block(&ctx, args),
+        ("unblock", Some(args)) => unblock(&ctx, args),
+        ("apply-proceeds", Some(args)) => apply_proceeds(&ctx, args),
+        ("cancel-proceeds", Some(args)) => cancel_proceeds(&ctx, args),
+        _ => unreachable!(),
+    }
+}
+
+fn init(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    if ctx.multisig.is_some() {
+        // The pool account and the deployer have to sign `initialize_pool`,
+        // which a multisig proposal cannot provide.
+        return Err(
+            "init cannot be proposed to a multisig, run it with the deployer wallet".into(),
+        );
+    }
+    let authority = match args.value_of("authority") {
+        Some(path) => Some(read_keypair(path)?),
+        None => None,
+    };
+
+    let now = unix_timestamp();
+    let start = parse_or(args, "start_time", now + 60)?;
+    let end_deposits = start + parse_or(args, "deposit_duration", 0)?;
+    let grace_period = parse_or(args, "cancel_duration", 0)?;
+    let withdraw_ts = parse_or(args, "withdraw_ts", now + 3 * 24 * 60 * 60)?;
+    let watermelon_mint = pubkey_of(args, "watermelon_mint")?;
+    let decimals = ctx.rpc.get_packed::<Mint>(&watermelon_mint)?.decimals;
+    let launch = Launch {
+        usdc_mint: pubkey_of(args, "usdc_mint")?,
+        watermelon_mint,
+        creator_watermelon: pubkey_of(args, "watermelon_account")?,
+        creator_usdc: match args.value_of("usdc_account") {
+            Some(account) => Some(Pubkey::from_str(account)?),
+            None => None,
+        },
+        num_ido_tokens: parse_amount(args.value_of("watermelon_amount").unwrap(), decimals)?,
+        schedule: PoolSchedule::new(
+            start,
+            end_deposits,
+            if grace_period > 0 {
+                Some(grace_period)
+            } else {
+                None
+            },
+            withdraw_ts,
+        ),
+        sale_symbol: args.value_of("symbol").unwrap().to_string(),
+        referrals: None,
+        kyc_verifier: None,
+        gate: ParticipationGate::default(),
+        stake_tiers: StakeTiers::default(),
+        lottery: Lottery::default(),
+        tranches: ProceedsTranches::default(),
+    };
+    launch.validate(now)?;
+    launch::create_pool(ctx, &launch, authority.as_ref())?;
+    Ok(())
+}
+
+fn launch(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    if ctx.multisig.is_some() {
+        return Err(
+            "launch cannot be proposed to a multisig, run it with the deployer wallet".into(),
+        );
+    }
+    let spec = LaunchSpec::load(args.value_of("spec").unwrap())?;
+    let authority = match args.value_of("authority") {
+        Some(path) => Some(read_keypair(path)?),
+        None => None,
+    };
+    let signer = authority
+        .as_ref()
+        .map(Signer::pubkey)
+        .unwrap_or_else(|| ctx.wallet.pubkey());
+    if let Some(expected) = spec.authority()? {
+        if expected != signer {
+            return Err(format!(
+                "spec authority is {}, but the authority keypair is {}",
+                expected, signer
+            )
+            .into());
+        }
+    }
+
+    let launch = spec.resolve(ctx)?;
+    launch.validate(unix_timestamp())?;
+    println!("spec is valid");
+    if args.is_present("check") {
+        return Ok(());
+    }
+    launch::create_pool(ctx, &launch, authority.as_ref())?;
+    Ok(())
+}
+
+fn diff(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    let spec = LaunchSpec::load(args.value_of("spec").unwrap())?;
+    let pool = ctx.load_pool(pubkey_of(args, "pool_account")?)?;
+    launch::diff(ctx, &spec, &pool)
+}
+
+fn inspect(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    let pool = ctx.load_pool(pubkey_of(args, "pool_account")?)?;
+    let account = &pool.account;
+    let now = unix_timestamp();
+    let field = |name: &str, value: String| println!("{:>26} {}", name, value);
+
+    field("redeemableMint", account.redeemable_mint.to_string());
+    field("poolWatermelon", account.pool_watermelon.to_string());
+    field("watermelonMint", account.watermelon_mint.to_string());
+    field("poolUsdc", account.pool_usdc.to_string());
+    field(
+        "distributionAuthority",
+        account.distribution_authority.to_string(),
+    );
+    field("poolSigner", pool.pool_signer().to_string());
+    field("nonce", account.nonce.to_string());
+    field("numIdoTokens", account.num_ido_tokens.to_string());
+    field("startIdoTs", account.schedule.start_ido_ts.to_string());
+    field(
+        "endDepositsTs",
+        account.schedule.end_deposits_ts.to_string(),
+    );
+    field("endIdoTs", account.schedule.end_ido_ts.to_string());
+    field(
+        "withdrawMelonTs",
+        account.schedule.withdraw_melon_ts.to_string(),
+    );
+    field(
+        "originalWithdrawMelonTs",
+        account.original_withdraw_melon_ts.to_string(),
+    );
+    field(
+        "scheduleModifications",
+        account.schedule_modifications.to_string(),
+    );
+    field("status", format!("{:?}", account.status));
+    field(
+        "totalUsdcDeposited",
+        account.total_usdc_deposited.to_string(),
+    );
+    field("referralBps", account.referral_bps.to_string());
+    field(
+        "referralWatermelon",
+        account.referral_watermelon.to_string(),
+    );
+    field("totalReferredUsdc", account.total_referred_usdc.to_string());
+    field("referralUsdcPaid", account.referral_usdc_paid.to_string());
+    field(
+        "referralWatermelonPaid",
+        account.referral_watermelon_paid.to_string(),
+    );
+    field("kycVerifier", account.kyc_verifier.to_string());
+    field("gate", launch::format_gate(&account.gate));
+    field(
+        "stakeTiers",
+        launch::format_stake_tiers(&account.stake_tiers),
+    );
+    field("lottery", launch::format_lottery(&account.lottery));
+    if account.lottery.enabled() {
+        let lottery = &account.lottery;
+        field("lotteryTickets", lottery.tickets.to_string());
+        field("lotteryEscrowedUsdc", lottery.escrowed_usdc.to_string());
+        field("lotteryDrawSlot", account.lottery_draw_slot.to_string());
+        field("lotteryDrawn", lottery.drawn.to_string());
+    }
+    field("tranches", launch::format_tranches(&account.tranches));
+    if account.tranches.halted {
+        field("haltedTranche", account.tranches.halted_tranche.to_string());
+    }
+    let stats = &account.stats;
+    field("depositors", stats.depositors.to_string());
+    field("usdcWithdrawn", stats.usdc_withdrawn.to_string());
+    field("redeemableBurned", stats.redeemable_burned.to_string());
+    field(
+        "watermelonDistributed",
+        stats.watermelon_distributed.to_string(),
+    );
+    field(
+        "creatorUsdcWithdrawn",
+        stats.creator_usdc_withdrawn.to_string(),
+    );
+    field(
+        "creatorWatermelonWithdrawn",
+        stats.creator_watermelon_withdrawn.to_string(),
+    );
+    field("now", now.to_string());
+    field("currentStatus", format!("{:?}", pool.status(now)));
+    Ok(())
+}
+
+fn bid(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    let pool = ctx.load_pool(pubkey_of(args, "pool_account")?)?;
+    let user = ctx.wallet.pubkey();
+    let user_usdc = pubkey_of(args, "usdc_account")?;
+    let user_redeemable = match args.value_of("redeemable_account") {
+        Some(account) => Pubkey::from_str(account)?,
+        None => pool.user_redeemable(&user),
+    };
+    let referrer = match args.value_of("referrer") {
+        Some(referrer) => Some(Pubkey::from_str(referrer)?),
+        None => None,
+    };
+
+    let decimals = ctx
+        .rpc
+        .get_packed::<Mint>(&pool.account.redeemable_mint)?
+        .decimals;
+    let bid_amount = parse_amount(args.value_of("usdc_amount").unwrap(), decimals)?;
+    // The program creates the redeemable account on the first deposit.
+    let current_bid = match ctx.rpc.get_account_data(&user_redeemable)? {
+        Some(data) => TokenAccount::unpack(&data)?.amount,
+        None => 0,
+    };
+
+    if current_bid < bid_amount {
+        let deposit_amount = bid_amount - current_bid;
+        println!(
+            "increasing bid by {} 💵",
+            format_amount(deposit_amount, decimals)
+        );
+        let mut ixs = Vec::new();
+        // Positions created before stake tiers have to be migrated first, the
+        // stash they go through is only recreated as a position in a later
+        // transaction.
+        let user_position = pool.user_position(&user);
+        if let Some(data) = ctx.rpc.get_account_data(&user_position)? {
+            if data.len() < UserPosition::space() {
+                println!("migrating position {}", user_position);
+                ctx.send(
+                    &[ido_pool_client::migrate_position(&user_position, &user)],
+                    &[],
+                )?;
+            }
+        }
+        let position_stash = ido_pool_client::position_stash_address(&user_position);
+        if ctx.rpc.get_account_data(&position_stash)?.is_some() {
+            ixs.push(ido_pool_client::migrate_position(&user_position, &user));
+        }
+        ixs.extend(kyc_attestation(&pool, &user, args)?);
+        ixs.push(pool.deposit(
+            &user,
+            &user_usdc,
+            &user_redeemable,
+            deposit_amount,
+            &gate_proof(&pool, &user, args)?,
+            referrer.as_ref(),
+        ));
+        ctx.send(&ixs, &[])
+    } else if current_bid > bid_amount {
+        let withdraw_amount = current_bid - bid_amount;
+        println!(
+            "decreasing bid by {} 💵",
+            format_amount(withdraw_amount, decimals)
+        );
+        ctx.send(
+            &[pool.refund(&user, &user_usdc, &user_redeemable, withdraw_amount)],
+            &[],
+        )
+    } else {
+        println!("bid unchanged 💎");
+        Ok(())
+    }
+}
+
+// KYC attestation instruction for `user`'s deposit or ticket, when the pool
+// has a verifier.
+fn kyc_attestation(pool: &Pool, user: &Pubkey, args: &ArgMatches) -> Result<Option<Instruction>> {
+    let signature = match args.value_of("kyc_signature") {
+        Some(signature) => Signature::from_str(signature)?,
+        None if pool.account.kyc_verifier != Pubkey::default() => {
+            return Err("this pool needs a KYC attestation, pass --kyc_signature".into())
+        }
+        None => return Ok(None),
+    };
+    let message = ido_pool_client::kyc_attestation_message(
+        &pool.origin(),
+        user,
+        args.value_of("kyc_expiry").unwrap().parse()?,
+        args.value_of("kyc_allocation").unwrap().parse()?,
+    );
+    let mut signature_bytes = [0u8; 64];
+    signature_bytes.copy_from_slice(signature.as_ref());
+    Ok(Some(ido_pool_client::kyc_attestation(
+        &pool.account.kyc_verifier,
+        &signature_bytes,
+        &message,
+    )))
+}
+
+fn gate_proof(pool: &Pool, user: &Pubkey, args: &ArgMatches) -> Result<Vec<Pubkey>> {
+    let nft_mint = match args.value_of("nft_mint") {
+        Some(mint) => Some(Pubkey::from_str(mint)?),
+        None => None,
+    };
+    if pool.account.gate.kind == GateKind::Collection && nft_mint.is_none() {
+        return Err("this pool is gated on an NFT collection, pass --nft_mint".into());
+    }
+    Ok(pool.gate_proof(user, nft_mint.as_ref()))
+}
+
+fn ticket(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    let pool = ctx.load_pool(pubkey_of(args, "pool_account")?)?;
+    if !pool.account.lottery.enabled() {
+        return Err("this pool has no lottery, use bid".into());
+    }
+    let user = ctx.wallet.pubkey();
+    let user_usdc = match args.value_of("usdc_account") {
+        Some(account) => Pubkey::from_str(account)?,
+        None => {
+            let pool_usdc = ctx
+                .rpc
+                .get_packed::<TokenAccount>(&pool.account.pool_usdc)?;
+            get_associated_token_address(&user, &pool_usdc.mint)
+        }
+    };
+    match args.value_of("action").unwrap() {
+        "buy" => {
+            let mut ixs = Vec::new();
+            ixs.extend(kyc_attestation(&pool, &user, args)?);
+            ixs.push(pool.buy_ticket(&user, &user_usdc, &gate_proof(&pool, &user, args)?));
+            ctx.send(&ixs, &[])
+        }
+        "claim" => ctx.send(
+            &[pool.claim_ticket(&user, &pool.user_redeemable(&user))],
+            &[],
+        ),
+        _ => ctx.send(&[pool.refund_ticket(&user, &user_usdc)], &[]),
+    }
+}
+
+fn redeem(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    let pool = ctx.load_pool(pubkey_of(args, "pool_account")?)?;
+    let user = ctx.wallet.pubkey();
+    let user_redeemable = match args.value_of("redeemable_account") {
+        Some(account) => Pubkey::from_str(account)?,
+        None => pool.user_redeemable(&user),
+    };
+    let user_watermelon = match args.value_of("watermelon_account") {
+        Some(account) => Pubkey::from_str(account)?,
+        None => pool.user_watermelon(&user),
+    };
+    let amount = match args.value_of("amount") {
+        Some(amount) => amount.parse()?,
+        None => ctx.rpc.get_packed::<TokenAccount>(&user_redeemable)?.amount,
+    };
+    ctx.send(
+        &[pool.redeem(&user, &user_watermelon, &user_redeemable, amount)],
+        &[],
+    )
+}
+
+fn modify_times(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    let pool = ctx.load_pool(pubkey_of(args, "pool_account")?)?;
+    let schedule = PoolSchedule {
+        start_ido_ts: args.value_of("start_ido").unwrap().parse()?,
+        end_deposits_ts: args.value_of("end_deposits").unwrap().parse()?,
+        end_ido_ts: args.value_of("end_ido").unwrap().parse()?,
+        withdraw_melon_ts: args.value_of("withdraw_melon").unwrap().parse()?,
+    };
+    println!("{:?}", schedule);
+    let ix = pool.modify_ido_time(&ctx.wallet.pubkey(), schedule);
+    ctx.send_admin(Some(&pool), ix)
+}
+
+fn withdraw_usdc(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    let pool = ctx.load_pool(pubkey_of(args, "pool_account")?)?;
+    let pool_usdc = ctx
+        .rpc
+        .get_packed::<TokenAccount>(&pool.account.pool_usdc)?;
+    let amount = match args.value_of("amount") {
+        Some(amount) => amount.parse()?,
+        None => pool_usdc
+            .amount
+            .saturating_sub(pool.account.reserved_usdc())
+            .min(pool.account.releasable_usdc(unix_timestamp())),
+    };
+    println!("receiver: {}", pool.account.creator_usdc);
+    let ix = pool.withdraw_proceeds(&ctx.wallet.pubkey(), amount);
+    ctx.send_admin(Some(&pool), ix)
+}
+
+fn withdraw_watermelon(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    let pool = ctx.load_pool(pubkey_of(args, "pool_account")?)?;
+    let pool_watermelon = ctx
+        .rpc
+        .get_packed::<TokenAccount>(&pool.account.pool_watermelon)?;
+    let amount = match args.value_of("amount") {
+        Some(amount) => amount.parse()?,
+        None => {
+            let redeemable = ctx.rpc.get_packed::<Mint>(&pool.account.redeemable_mint)?;
+            pool.account.withdrawable_watermelon(
+                pool_watermelon.amount,
+                redeemable.supply,
+                unix_timestamp(),
+            )
+        }
+    };
+    println!("receiver: {}", pool.account.creator_watermelon);
+    let ix = pool.withdraw_watermelon(&ctx.wallet.pubkey(), amount);
+    ctx.send_admin(Some(&pool), ix)
+}
+
+fn propose_proceeds(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    let pool = ctx.load_pool(pubkey_of(args, "pool_account")?)?;
+    let ix = pool.propose_proceeds_accounts(
+        &ctx.wallet.pubkey(),
+        &pubkey_of(args, "usdc_account")?,
+        &pubkey_of(args, "watermelon_account")?,
+    );
+    println!(
+        "can be applied in {} hours",
+        ido_pool::PROCEEDS_TIMELOCK_SEC / 3600
+    );
+    ctx.send_admin(Some(&pool), ix)
+}
+
+fn configure_kyc(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    let pool = ctx.load_pool(pubkey_of(args, "pool_account")?)?;
+    let ix = pool.configure_kyc(&ctx.wallet.pubkey(), &pubkey_of(args, "verifier")?);
+    ctx.send_admin(Some(&pool), ix)
+}
+
+fn configure_gate(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    let pool = ctx.load_pool(pubkey_of(args, "pool_account")?)?;
+    let gate = launch::parse_gate(
+        ctx,
+        args.value_of("kind").unwrap(),
+        args.value_of("key"),
+        args.value_of("min_amount"),
+    )?;
+    gate.validate()
+        .map_err(|err| format!("{:?}: {}", err, err))?;
+    let ix = pool.configure_gate(&ctx.wallet.pubkey(), gate);
+    ctx.send_admin(Some(&pool), ix)
+}
+
+fn configure_tiers(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    let pool = ctx.load_pool(pubkey_of(args, "pool_account")?)?;
+    let stake_tiers = if args.is_present("clear") {
+        StakeTiers::default()
+    } else {
+        let tiers = args
+            .values_of("tier")
+            .unwrap()
+            .map(|tier| match tier.split_once(':') {
+                Some((min_stake, allocation)) => Ok(TierSpec {
+                    min_stake: min_stake.to_string(),
+                    allocation: allocation.to_string(),
+                }),
+                None => Err(format!(
+                    "invalid tier {}, expected <min_stake>:<allocation>",
+                    tier
+                )),
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let usdc_decimals = ctx
+            .rpc
+            .get_packed::<Mint>(&pool.account.redeemable_mint)?
+            .decimals;
+        launch::parse_stake_tiers(
+            ctx,
+            pubkey_of(args, "staking_program")?,
+            pubkey_of(args, "stake_mint")?,
+            args.value_of("guaranteed_end").unwrap().parse()?,
+            &tiers,
+            usdc_decimals,
+        )?
+    };
+    stake_tiers
+        .validate(&pool.account.schedule)
+        .map_err(|err| format!("{:?}: {}", err, err))?;
+    let ix = pool.configure_stake_tiers(&ctx.wallet.pubkey(), stake_tiers);
+    ctx.send_admin(Some(&pool), ix)
+}
+
+fn configure_tranches(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    let pool = ctx.load_pool(pubkey_of(args, "pool_account")?)?;
+    let tranches = if args.is_present("clear") {
+        ProceedsTranches::default()
+    } else {
+        let tranches = args
+            .values_of("tranche")
+            .unwrap()
+            .map(|tranche| match tranche.split_once(':') {
+                Some((release_ts, bps)) => Ok((release_ts.parse()?, bps.parse()?)),
+                None => Err(
+                    format!("invalid tranche {}, expected <release_time>:<bps>", tranche).into(),
+                ),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        launch::parse_tranches(&tranches)?
+    };
+    tranches
+        .validate(&pool.account.schedule)
+        .map_err(|err| format!("{:?}: {}", err, err))?;
+    let ix = pool.configure_tranches(&ctx.wallet.pubkey(), tranches.tranches);
+    ctx.send_admin(Some(&pool), ix)
+}
+
+fn register_stake(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    let pool = ctx.load_pool(pubkey_of(args, "pool_account")?)?;
+    let user = ctx.wallet.pubkey();
+    let stake_vault = match args.value_of("stake_vault") {
+        Some(account) => Pubkey::from_str(account)?,
+        None => pool.stake_vault(&user),
+    };
+    ctx.send(&[pool.register_stake(&user, &stake_vault)], &[])?;
+    let data = ctx
+        .rpc
+        .get_required_account_data(&pool.user_position(&user))?;
+    let position = UserPosition::try_deserialize(&mut data.as_slice())?;
+    let decimals = ctx
+        .rpc
+        .get_packed::<Mint>(&pool.account.redeemable_mint)?
+        .decimals;
+    println!(
+        "registered in tier {} with a guaranteed allocation of {} 💵",
+        position.stake_tier,
+        format_amount(position.stake_allocation, decimals)
+    );
+    Ok(())
+}
+
+fn configure_lottery(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    let pool = ctx.load_pool(pubkey_of(args, "pool_account")?)?;
+    let lottery = if args.is_present("clear") {
+        Lottery::default()
+    } else {
+        let usdc_decimals = ctx
+            .rpc
+            .get_packed::<Mint>(&pool.account.redeemable_mint)?
+            .decimals;
+        let commitment = match args.value_of("commitment") {
+            Some(commitment) => Hash::from_str(commitment)?.to_bytes(),
+            None => {
+                let secret = Keypair::new().secret().to_bytes();
+                println!(
+                    "draw secret, keep it until the lottery is drawn: {}",
+                    Hash::new(&secret)
+                );
+                ido_pool_client::lottery_commitment(&secret)
+            }
+        };
+        launch::parse_lottery(
+            args.value_of("ticket_usdc").unwrap(),
+            args.value_of("winning_tickets").unwrap().parse()?,
+            commitment,
+            usdc_decimals,
+        )?
+    };
+    let ix = pool.configure_lottery(
+        &ctx.wallet.pubkey(),
+        lottery.ticket_usdc,
+        lottery.winning_tickets,
+        lottery.commitment,
+    );
+    ctx.send_admin(Some(&pool), ix)
+}
+
+fn draw_lottery(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    let pool = ctx.load_pool(pubkey_of(args, "pool_account")?)?;
+    let secret = Hash::from_str(args.value_of("secret").unwrap())?.to_bytes();
+    if ido_pool_client::lottery_commitment(&secret) != pool.account.lottery.commitment {
+        return Err("the secret does not match the pool's commitment".into());
+    }
+    if pool.account.lottery_draw_slot == 0 {
+        ctx.send_admin(Some(&pool), pool.commit_lottery_draw())?;
+        let draw_slot = ctx.load_pool(pool.address)?.account.lottery_draw_slot;
+        if draw_slot != 0 {
+            println!(
+                "draw slot {}, run draw-lottery again once it has passed and within {} slots",
+                draw_slot,
+                solana_sdk::slot_hashes::MAX_ENTRIES
+            );
+        }
+        return Ok(());
+    }
+    ctx.send_admin(Some(&pool), pool.draw_lottery(secret))
+}
+
+fn block(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    let wallet = pubkey_of(args, "wallet")?;
+    let entry = ido_pool_client::blocklist_entry_address(&wallet);
+    if ctx.rpc.get_account_data(&entry)?.is_some() {
+        return Err(format!("{} is already blocked", wallet).into());
+    }
+    ctx.send_admin(
+        None,
+        ido_pool_client::block_address(&ctx.wallet.pubkey(), &wallet),
+    )
+}
+
+fn unblock(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    let wallet = pubkey_of(args, "wallet")?;
+    ctx.send_admin(
+        None,
+        ido_pool_client::unblock_address(&ctx.wallet.pubkey(), &wallet),
+    )
+}
+
+fn apply_proceeds(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    let pool = ctx.load_pool(pubkey_of(args, "pool_account")?)?;
+    let ix = pool.apply_proceeds_accounts(&ctx.wallet.pubkey());
+    ctx.send_admin(Some(&pool), ix)
+}
+
+fn cancel_proceeds(ctx: &Context, args: &ArgMatches) -> Result<()> {
+    let pool = ctx.load_pool(pubkey_of(args, "pool_account")?)?;
+    let ix = pool.cancel_proceeds_accounts(&ctx.wallet.pubkey());
+    ctx.send_admin(Some(&pool), ix)
+}
+
+fn print_instruction(ix: &Instruction) {
+    println!("program: {}", ix.program_id);
+    for (i, meta) in ix.accounts.iter().enumerate() {
+        println!(
+            "{:>3} {:<45} w/s? {} {}",
+            i, meta.pubkey, meta.is_writable, meta.is_signer
+        );
+    }
+    println!("instructionBase64: {}", base64::encode(&ix.data));
+}
+
+fn read_keypair(path: &str) -> Result<Keypair> {
+    read_keypair_file(path).map_err(|err| format!("reading keypair {}: {}", path, err).into())
+}
+
+// Only called for required arguments or arguments with a default value.
+fn pubkey_of(args: &ArgMatches, name: &str) -> Result<Pubkey> {
+    let value = args.value_of(name).unwrap();
+    Pubkey::from_str(value)
+        .map_err(|_| format!("{} is not a valid public key: {}", name, value).into())
+}
+
+fn parse_or(args: &ArgMatches, name: &str, default: i64) -> Result<i64> {
+    match args.value_of(name) {
+        Some(value) => Ok(value.parse()?),
+        None => Ok(default),
+    }
+}
+
+fn unix_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+// Converts a decimal token amount such as "12.5" into base units.
+fn parse_amount(amount: &str, decimals: u8) -> Result<u64> {
+    let (whole, fraction) = match amount.find('.') {
+        Some(i) => (&amount[..i], &amount[i + 1..]),
+        None => (amount, ""),
+    };
+    if fraction.len() > decimals as usize {
+        return Err(format!("{} has more than {} decimals", amount, decimals).into());
+    }
+    let padded = format!("{}{:0<width$}", whole, fraction, width = decimals as usize);
+    Ok(padded.parse()?)
+}
+
+fn format_amount(amount: u64, decimals: u8) -> String {
+    let unit = 10u64.pow(decimals as u32);
+    format!(
+        "{}.{:0>width$}",
+        amount / unit,
+        amount % unit,
+        width = decimals as usize
+    )
+}
@@ -0,0 +1,114 @@
+// Wraps admin instructions into proposals of the serum multisig program, so
+// pools whose distribution authority is a multisig signer can be managed from
+// the cli. Owners approve and execute the proposal with the multisig tooling.
+
+use anchor_lang::prelude::borsh;
+use anchor_lang::{AnchorDeserialize, AnchorSerialize};
+use solana_sdk::hash::hash;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Keypair;
+use solana_sdk::signer::Signer;
+use solana_sdk::{system_instruction, sysvar};
+
+use crate::rpc::Rpc;
+use crate::Result;
+
+pub const DEFAULT_PROGRAM_ID: &str = "A9HAbnCwoD6f2NkZobKFf6buJoN9gUVVvX5PoUnDHS6u";
+
+#[derive(AnchorDeserialize)]
+struct MultisigAccount {
+    owners: Vec<Pubkey>,
+    _threshold: u64,
+    nonce: u8,
+    _owner_set_seqno: u32,
+}
+
+#[derive(AnchorSerialize)]
+struct TransactionAccount {
+    pubkey: Pubkey,
+    is_signer: bool,
+    is_writable: bool,
+}
+
+pub struct Multisig {
+    pub program_id: Pubkey,
+    pub address: Pubkey,
+    pub signer: Pubkey,
+    owners: usize,
+}
+
+impl Multisig {
+    pub fn load(rpc: &Rpc, program_id: Pubkey, address: Pubkey) -> Result<Self> {
+        let data = rpc.get_required_account_data(&address)?;
+        let account = MultisigAccount::deserialize(&mut &data[8..])?;
+        let signer =
+            Pubkey::create_program_address(&[address.as_ref(), &[account.nonce]], &program_id)?;
+        Ok(Multisig {
+            program_id,
+            address,
+            signer,
+            owners: account.owners.len(),
+        })
+    }
+
+    // Returns the instructions creating a proposal that executes `ix` once
+    // approved. `proposal` has to sign next to the proposer.
+    pub fn propose(
+        &self,
+        rpc: &Rpc,
+        proposer: &Pubkey,
+        proposal: &Keypair,
+        ix: &Instruction,
+    ) -> Result<Vec<Instruction>> {
+        let accounts: Vec<TransactionAccount> = ix
+            .accounts
+            .iter()
+            .map(|meta| TransactionAccount {
+                pubkey: meta.pubkey,
+                is_signer: meta.is_signer,
+                is_writable: meta.is_writable,
+            })
+            .collect();
+
+        // discriminator, multisig, program_id, accounts, data, signers,
+        // did_execute and owner_set_seqno.
+        let space = 8
+            + 32
+            + 32
+            + 4
+            + accounts.len() * (32 + 1 + 1)
+            + 4
+            + ix.data.len()
+            + 4
+            + self.owners
+            + 1
+            + 4;
+        let lamports = rpc.get_minimum_balance_for_rent_exemption(space)?;
+
+        let mut data = hash(b"global:create_transaction").to_bytes()[..8].to_vec();
+        ix.program_id.serialize(&mut data)?;
+        accounts.serialize(&mut data)?;
+        ix.data.serialize(&mut data)?;
+
+        Ok(vec![
+            system_instruction::create_account(
+                proposer,
+                &proposal.pubkey(),
+                lamports,
+                space as u64,
+                &self.program_id,
+            ),
+            Instruction {
+                program_id: self.program_id,
+                accounts: vec![
+                    AccountMeta::new_readonly(self.address, false),
+                    AccountMeta::new(proposal.pubkey(), true),
+                    AccountMeta::new_readonly(*proposer, true),
+                    AccountMeta::new_readonly(sysvar::rent::ID, false),
+                ],
+                data,
+            },
+        ])
+    }
+}
@@ -0,0 +1,121 @@
+// Minimal blocking JSON-RPC client, covering only the calls the cli needs.
+
+use serde_json::{json, Value};
+use solana_sdk::hash::Hash;
+use solana_sdk::program_pack::{IsInitialized, Pack};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::Transaction;
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time::Duration;
+
+use crate::Result;
+
+// How long `send_and_confirm` waits for a transaction before giving up.
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+const CONFIRM_POLL: Duration = Duration::from_millis(500);
+
+pub struct Rpc {
+    url: String,
+    commitment: String,
+}
+
+impl Rpc {
+    pub fn new(url: String, commitment: String) -> Self {
+        Rpc { url, commitment }
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+        let mut response: Value = ureq::post(&self.url).send_json(request)?.into_json()?;
+        if let Some(error) = response.get("error") {
+            return Err(format!("{} failed: {}", method, error).into());
+        }
+        Ok(response["result"].take())
+    }
+
+    pub fn get_account_data(&self, address: &Pubkey) -> Result<Option<Vec<u8>>> {
+        let result = self.call(
+            "getAccountInfo",
+            json!([
+                address.to_string(),
+                {"encoding": "base64", "commitment": self.commitment}
+            ]),
+        )?;
+        match result["value"]["data"][0].as_str() {
+            Some(data) => Ok(Some(base64::decode(data)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_required_account_data(&self, address: &Pubkey) -> Result<Vec<u8>> {
+        self.get_account_data(address)?
+            .ok_or_else(|| format!("account {} not found", address).into())
+    }
+
+    pub fn get_packed<T: Pack + IsInitialized>(&self, address: &Pubkey) -> Result<T> {
+        Ok(T::unpack(&self.get_required_account_data(address)?)?)
+    }
+
+    pub fn get_minimum_balance_for_rent_exemption(&self, len: usize) -> Result<u64> {
+        self.call("getMinimumBalanceForRentExemption", json!([len]))?
+            .as_u64()
+            .ok_or_else(|| "invalid rent exemption response".into())
+    }
+
+    pub fn get_latest_blockhash(&self) -> Result<Hash> {
+        let result = self.call(
+            "getLatestBlockhash",
+            json!([{"commitment": self.commitment}]),
+        )?;
+        let blockhash = result["value"]["blockhash"]
+            .as_str()
+            .ok_or("invalid blockhash response")?;
+        Ok(Hash::from_str(blockhash)?)
+    }
+
+    pub fn send_and_confirm(&self, tx: &Transaction) -> Result<String> {
+        let tx = base64::encode(bincode::serialize(tx)?);
+        let signature = self.call(
+            "sendTransaction",
+            json!([tx, {"encoding": "base64", "preflightCommitment": self.commitment}]),
+        )?;
+        let signature = signature
+            .as_str()
+            .ok_or("invalid sendTransaction response")?
+            .to_string();
+
+        let mut waited = Duration::from_secs(0);
+        while waited < CONFIRM_TIMEOUT {
+            let statuses = self.call("getSignatureStatuses", json!([[signature]]))?;
+            let status = &statuses["value"][0];
+            if !status.is_null() {
+                if !status["err"].is_null() {
+                    return Err(
+                        format!("transaction {} failed: {}", signature, status["err"]).into(),
+                    );
+                }
+                let confirmation = status["confirmationStatus"].as_str().unwrap_or_default();
+                if commitment_level(confirmation) >= commitment_level(&self.commitment) {
+                    return Ok(signature);
+                }
+            }
+            sleep(CONFIRM_POLL);
+            waited += CONFIRM_POLL;
+        }
+        Err(format!("transaction {} was not confirmed in time", signature).into())
+    }
+}
+
+fn commitment_level(commitment: &str) -> u8 {
+    match commitment {
+        "finalized" => 2,
+        "confirmed" => 1,
+        _ => 0,
+    }
+}
@@ -0,0 +1,1099 @@
+//! Rust client for the ido-pool program.
+//!
+//! Builds every `ido_pool` instruction with its accounts derived (including
+//! the `pool_signer` PDA and its bump) and decodes on-chain `PoolAccount`s.
+//! Returned `Instruction`s are ready to be signed and sent by the caller.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::{ed25519_program, system_program, sysvar};
+use anchor_lang::InstructionData;
+use anchor_spl::associated_token::{self, get_associated_token_address};
+use anchor_spl::token;
+
+pub use ido_pool::{
+    blocklist_entry_address, kyc_attestation_message, lottery_commitment, lottery_rank,
+    stake_authority_address, BlocklistEntry, GateKind, HaltVote, Lottery, LotteryTicket,
+    ParticipationGate, PayoutQuote, PoolAccount, PoolSchedule, PoolStats, PoolStatus,
+    ProceedsTranches, Referrer, StakeTier, StakeTiers, Tranche, UserPosition, ID as PROGRAM_ID,
+    LOTTERY_DRAW_DELAY_SLOTS, MAX_STAKE_TIERS, MAX_TRANCHES, POOL_ACCOUNT_VERSION,
+};
+
+// Signer PDA owning the pool token accounts and the redeemable mint.
+pub fn pool_signer(watermelon_mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[watermelon_mint.as_ref()], &PROGRAM_ID)
+}
+
+// Address `migrate_pool` moves a pool created at `origin` to when its
+// account is too small for the current layout.
+pub fn migrated_pool_address(origin: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"pool".as_ref(), origin.as_ref()], &PROGRAM_ID).0
+}
+
+// The PDAs below are derived from the address a pool was created at, see
+// `Pool::origin`.
+
+// Redeemable mint created by `initialize_pool` for each pool.
+pub fn redeemable_mint_address(pool: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"redeemable_mint".as_ref(), pool.as_ref()], &PROGRAM_ID).0
+}
+
+// 💵 and 🍉 vaults created by `initialize_pool` for each pool.
+pub fn pool_usdc_address(pool: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"pool_usdc".as_ref(), pool.as_ref()], &PROGRAM_ID).0
+}
+
+pub fn pool_watermelon_address(pool: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"pool_watermelon".as_ref(), pool.as_ref()], &PROGRAM_ID).0
+}
+
+// Token metadata account naming the redeemable token of `redeemable_mint`,
+// or of any other mint.
+pub fn redeemable_metadata_address(redeemable_mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"metadata".as_ref(),
+            spl_token_metadata::ID.as_ref(),
+            redeemable_mint.as_ref(),
+        ],
+        &spl_token_metadata::ID,
+    )
+    .0
+}
+
+// Pending change of the proceeds accounts of a pool.
+pub fn proceeds_change_address(pool: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"proceeds_change".as_ref(), pool.as_ref()], &PROGRAM_ID).0
+}
+
+// Halt vote on `tranche` of the proceeds of a pool, created by its first
+// voter.
+pub fn halt_vote_address(pool: &Pubkey, tranche: u8) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"halt_vote".as_ref(), pool.as_ref(), &[tranche]],
+        &PROGRAM_ID,
+    )
+    .0
+}
+
+pub fn user_position_address(pool: &Pubkey, user: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"position".as_ref(), pool.as_ref(), user.as_ref()],
+        &PROGRAM_ID,
+    )
+    .0
+}
+
+// Holds a position between the two steps of `migrate_position`.
+pub fn position_stash_address(user_position: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"position_stash".as_ref(), user_position.as_ref()],
+        &PROGRAM_ID,
+    )
+    .0
+}
+
+pub fn referrer_address(pool: &Pubkey, referrer: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"referrer".as_ref(), pool.as_ref(), referrer.as_ref()],
+        &PROGRAM_ID,
+    )
+    .0
+}
+
+// Lottery ticket of `user`, a user holds at most one per pool.
+pub fn lottery_ticket_address(pool: &Pubkey, user: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"ticket".as_ref(), pool.as_ref(), user.as_ref()],
+        &PROGRAM_ID,
+    )
+    .0
+}
+
+fn instruction(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+// Accounts needed to create a pool. The vaults and the redeemable mint are
+// created by the program at addresses derived from `pool_account`.
+pub struct InitializePoolAccounts {
+    pub pool_account: Pubkey,
+    pub usdc_mint: Pubkey,
+    pub watermelon_mint: Pubkey,
+    pub distribution_authority: Pubkey,
+    pub payer: Pubkey,
+    pub creator_watermelon: Pubkey,
+    // Where the raise can be withdrawn to, the 🍉 withdrawals go back to
+    // `creator_watermelon`.
+    pub creator_usdc: Pubkey,
+}
+
+// `pool_account` has to sign the transaction as it is created by the program.
+// The redeemable token is named after `sale_symbol`, see
+// `ido_pool::redeemable_token_name`.
+pub fn initialize_pool(
+    accounts: &InitializePoolAccounts,
+    num_ido_tokens: u64,
+    schedule: PoolSchedule,
+    sale_symbol: &str,
+) -> Instruction {
+    let (pool_signer, nonce) = pool_signer(&accounts.watermelon_mint);
+    let redeemable_mint = redeemable_mint_address(&accounts.pool_account);
+    instruction(
+        ido_pool::accounts::InitializePool {
+            pool_account: accounts.pool_account,
+            pool_signer,
+            usdc_mint: accounts.usdc_mint,
+            redeemable_mint,
+            redeemable_metadata: redeemable_metadata_address(&redeemable_mint),
+            watermelon_mint: accounts.watermelon_mint,
+            pool_watermelon: pool_watermelon_address(&accounts.pool_account),
+            pool_usdc: pool_usdc_address(&accounts.pool_account),
+            distribution_authority: accounts.distribution_authority,
+            payer: accounts.payer,
+            creator_watermelon: accounts.creator_watermelon,
+            creator_usdc: accounts.creator_usdc,
+            token_program: token::ID,
+            token_metadata_program: spl_token_metadata::ID,
+            rent: sysvar::rent::ID,
+            clock: sysvar::clock::ID,
+            system_program: system_program::ID,
+        },
+        ido_pool::instruction::InitializePool {
+            num_ido_tokens,
+            nonce,
+            schedule,
+            sale_symbol: sale_symbol.to_string(),
+        },
+    )
+}
+
+// Referrals can only be configured before the sale starts, so this is also
+// available for pools that are being created in the same transaction.
+pub fn configure_referrals(
+    pool_account: &Pubkey,
+    pool_watermelon: &Pubkey,
+    distribution_authority: &Pubkey,
+    payer: &Pubkey,
+    creator_watermelon: &Pubkey,
+    referral_bps: u16,
+    referral_watermelon: u64,
+) -> Instruction {
+    instruction(
+        ido_pool::accounts::ConfigureReferrals {
+            pool_account: *pool_account,
+            pool_watermelon: *pool_watermelon,
+            distribution_authority: *distribution_authority,
+            payer: *payer,
+            creator_watermelon: *creator_watermelon,
+            token_program: token::ID,
+            clock: sysvar::clock::ID,
+        },
+        ido_pool::instruction::ConfigureReferrals {
+            referral_bps,
+            referral_watermelon,
+        },
+    )
+}
+
+// Sets the KYC verifier of a pool, see `configure_referrals` for why this is
+// not only a `Pool` method.
+pub fn configure_kyc(
+    pool_account: &Pubkey,
+    distribution_authority: &Pubkey,
+    payer: &Pubkey,
+    kyc_verifier: &Pubkey,
+) -> Instruction {
+    instruction(
+        ido_pool::accounts::ConfigureKyc {
+            pool_account: *pool_account,
+            distribution_authority: *distribution_authority,
+            payer: *payer,
+            clock: sysvar::clock::ID,
+        },
+        ido_pool::instruction::ConfigureKyc {
+            kyc_verifier: *kyc_verifier,
+        },
+    )
+}
+
+// Sets the participation gate of a pool, see `configure_referrals` for why
+// this is not only a `Pool` method.
+pub fn configure_gate(
+    pool_account: &Pubkey,
+    distribution_authority: &Pubkey,
+    payer: &Pubkey,
+    gate: ParticipationGate,
+) -> Instruction {
+    instruction(
+        ido_pool::accounts::ConfigureGate {
+            pool_account: *pool_account,
+            distribution_authority: *distribution_authority,
+            payer: *payer,
+            clock: sysvar::clock::ID,
+        },
+        ido_pool::instruction::ConfigureGate { gate },
+    )
+}
+
+// Sets the stake tiers of a pool, see `configure_referrals` for why this is
+// not only a `Pool` method.
+pub fn configure_stake_tiers(
+    pool_account: &Pubkey,
+    distribution_authority: &Pubkey,
+    payer: &Pubkey,
+    stake_tiers: StakeTiers,
+) -> Instruction {
+    instruction(
+        ido_pool::accounts::ConfigureStakeTiers {
+            pool_account: *pool_account,
+            distribution_authority: *distribution_authority,
+            payer: *payer,
+            clock: sysvar::clock::ID,
+        },
+        ido_pool::instruction::ConfigureStakeTiers { stake_tiers },
+    )
+}
+
+// Sets the release schedule of the proceeds of a pool, see
+// `configure_referrals` for why this is not only a `Pool` method.
+pub fn configure_tranches(
+    pool_account: &Pubkey,
+    distribution_authority: &Pubkey,
+    payer: &Pubkey,
+    tranches: [Tranche; MAX_TRANCHES],
+) -> Instruction {
+    instruction(
+        ido_pool::accounts::ConfigureTranches {
+            pool_account: *pool_account,
+            distribution_authority: *distribution_authority,
+            payer: *payer,
+            clock: sysvar::clock::ID,
+        },
+        ido_pool::instruction::ConfigureTranches { tranches },
+    )
+}
+
+// Sets the lottery of a pool, see `configure_referrals` for why this is not
+// only a `Pool` method. `commitment` is the `lottery_commitment` of the secret
+// revealed by `Pool::draw_lottery`.
+pub fn configure_lottery(
+    pool_account: &Pubkey,
+    distribution_authority: &Pubkey,
+    payer: &Pubkey,
+    ticket_usdc: u64,
+    winning_tickets: u64,
+    commitment: [u8; 32],
+) -> Instruction {
+    instruction(
+        ido_pool::accounts::ConfigureLottery {
+            pool_account: *pool_account,
+            distribution_authority: *distribution_authority,
+            payer: *payer,
+            clock: sysvar::clock::ID,
+        },
+        ido_pool::instruction::ConfigureLottery {
+            ticket_usdc,
+            winning_tickets,
+            commitment,
+        },
+    )
+}
+
+// Moves a position created before stake tiers were added to the current
+// layout, anyone can pay for it. Positions are `user_position_address`
+// accounts shorter than `UserPosition::space()`. It takes two transactions
+// with this instruction: the first stashes the position, the second, sent
+// while `position_stash_address` exists, recreates it.
+pub fn migrate_position(user_position: &Pubkey, payer: &Pubkey) -> Instruction {
+    instruction(
+        ido_pool::accounts::MigratePosition {
+            user_position: *user_position,
+            position_stash: position_stash_address(user_position),
+            payer: *payer,
+            rent: sysvar::rent::ID,
+            system_program: system_program::ID,
+        },
+        ido_pool::instruction::MigratePosition {},
+    )
+}
+
+// Ed25519 program instruction carrying a KYC attestation, i.e. the verifier's
+// `signature` of `kyc_attestation_message`. It has to come right before the
+// deposit in the same transaction.
+pub fn kyc_attestation(verifier: &Pubkey, signature: &[u8; 64], message: &[u8]) -> Instruction {
+    // One signature, everything in this instruction's data: the offsets are
+    // followed by the public key, the signature and the message.
+    let public_key_offset: u16 = 2 + 14;
+    let signature_offset = public_key_offset + 32;
+    let message_offset = signature_offset + 64;
+    let mut data = vec![1, 0];
+    for offset in [
+        signature_offset,
+        u16::MAX,
+        public_key_offset,
+        u16::MAX,
+        message_offset,
+        message.len() as u16,
+        u16::MAX,
+    ] {
+        data.extend_from_slice(&offset.to_le_bytes());
+    }
+    data.extend_from_slice(verifier.as_ref());
+    data.extend_from_slice(signature);
+    data.extend_from_slice(message);
+    Instruction {
+        program_id: ed25519_program::ID,
+        accounts: vec![],
+        data,
+    }
+}
+
+// Blocks `wallet` from depositing and claiming in every pool, `payer` has to
+// be the deployer.
+pub fn block_address(payer: &Pubkey, wallet: &Pubkey) -> Instruction {
+    instruction(
+        ido_pool::accounts::BlockAddress {
+            blocklist_entry: blocklist_entry_address(wallet),
+            wallet: *wallet,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        ido_pool::instruction::BlockAddress {},
+    )
+}
+
+pub fn unblock_address(payer: &Pubkey, wallet: &Pubkey) -> Instruction {
+    instruction(
+        ido_pool::accounts::UnblockAddress {
+            blocklist_entry: blocklist_entry_address(wallet),
+            wallet: *wallet,
+            payer: *payer,
+        },
+        ido_pool::instruction::UnblockAddress {},
+    )
+}
+
+// Token accounts of a user paid out by `Pool::distribute`.
+pub struct DistributionRecipient {
+    pub user: Pubkey,
+    pub user_redeemable: Pubkey,
+    pub user_watermelon: Pubkey,
+}
+
+// A decoded pool together with its address. Every instruction touching an
+// existing pool is built from here so the pool's own accounts never have to be
+// passed around by hand.
+pub struct Pool {
+    pub address: Pubkey,
+    pub account: PoolAccount,
+    data_len: usize,
+}
+
+impl Pool {
+    pub fn new(address: Pubkey, account: PoolAccount) -> Self {
+        Pool {
+            address,
+            account,
+            data_len: PoolAccount::space(),
+        }
+    }
+
+    // Decodes the raw data of a pool account, discriminator included. Pools
+    // that still need `migrate` are decoded too, check `needs_migration`.
+    pub fn from_account_data(
+        address: Pubkey,
+        data: &[u8],
+    ) -> std::result::Result<Self, ProgramError> {
+        let account = PoolAccount::try_deserialize_any_version(data)?;
+        Ok(Pool {
+            address,
+            account,
+            data_len: data.len(),
+        })
+    }
+
+    pub fn needs_migration(&self) -> bool {
+        self.account.version < POOL_ACCOUNT_VERSION
+    }
+
+    // Address the pool was created at, which its other accounts are derived
+    // from. Pools that predate the field were created where they are.
+    pub fn origin(&self) -> Pubkey {
+        if self.account.origin == Pubkey::default() {
+            self.address
+        } else {
+            self.account.origin
+        }
+    }
+
+    // Address of the pool once `migrate` is done, a new one if the account
+    // is too small for the current layout.
+    pub fn migrated_address(&self) -> Pubkey {
+        if self.data_len < PoolAccount::space() {
+            migrated_pool_address(&self.origin())
+        } else {
+            self.address
+        }
+    }
+
+    pub fn pool_signer(&self) -> Pubkey {
+        pool_signer(&self.account.watermelon_mint).0
+    }
+
+    pub fn halt_vote(&self, tranche: u8) -> Pubkey {
+        halt_vote_address(&self.origin(), tranche)
+    }
+
+    pub fn user_position(&self, user: &Pubkey) -> Pubkey {
+        user_position_address(&self.origin(), user)
+    }
+
+    pub fn referrer(&self, referrer: &Pubkey) -> Pubkey {
+        referrer_address(&self.origin(), referrer)
+    }
+
+    pub fn status(&self, now: i64) -> PoolStatus {
+        self.account.current_status(now)
+    }
+
+    // Associated token accounts of `user`, `deposit` and `redeem` create them
+    // if they do not exist yet.
+    pub fn user_redeemable(&self, user: &Pubkey) -> Pubkey {
+        get_associated_token_address(user, &self.account.redeemable_mint)
+    }
+
+    pub fn user_watermelon(&self, user: &Pubkey) -> Pubkey {
+        get_associated_token_address(user, &self.account.watermelon_mint)
+    }
+
+    // Accounts proving `user` passes the gate of the pool, to be passed to
+    // `deposit`: the user's associated account of the gate token, or of
+    // `nft_mint` followed by the mint and its metadata for collection gates.
+    pub fn gate_proof(&self, user: &Pubkey, nft_mint: Option<&Pubkey>) -> Vec<Pubkey> {
+        let gate = &self.account.gate;
+        match (gate.kind, nft_mint) {
+            (GateKind::Token, _) => vec![get_associated_token_address(user, &gate.key)],
+            (GateKind::Collection, Some(nft_mint)) => vec![
+                get_associated_token_address(user, nft_mint),
+                *nft_mint,
+                redeemable_metadata_address(nft_mint),
+            ],
+            _ => vec![],
+        }
+    }
+
+    // Exchanges `amount` USDC for redeemable tokens, optionally crediting the
+    // deposit to `referrer` (the referrer's wallet, not its `Referrer` PDA).
+    // Gated pools need the accounts of `gate_proof`, pools with a KYC
+    // verifier need a `kyc_attestation` right before it.
+    pub fn deposit(
+        &self,
+        user: &Pubkey,
+        user_usdc: &Pubkey,
+        user_redeemable: &Pubkey,
+        amount: u64,
+        gate_proof: &[Pubkey],
+        referrer: Option<&Pubkey>,
+    ) -> Instruction {
+        let mut ix = instruction(
+            ido_pool::accounts::ExchangeUsdcForRedeemable {
+                pool_account: self.address,
+                pool_signer: self.pool_signer(),
+                redeemable_mint: self.account.redeemable_mint,
+                pool_usdc: self.account.pool_usdc,
+                user_authority: *user,
+                user_usdc: *user_usdc,
+                user_redeemable: *user_redeemable,
+                user_position: self.user_position(user),
+                token_program: token::ID,
+                clock: sysvar::clock::ID,
+                system_program: system_program::ID,
+                associated_token_program: associated_token::ID,
+                rent: sysvar::rent::ID,
+                instructions: sysvar::instructions::ID,
+                blocklist_entry: blocklist_entry_address(user),
+            },
+            ido_pool::instruction::ExchangeUsdcForRedeemable { amount },
+        );
+        ix.accounts.extend(
+            gate_proof
+                .iter()
+                .map(|account| AccountMeta::new_readonly(*account, false)),
+        );
+        if let Some(referrer) = referrer {
+            ix.accounts
+                .push(AccountMeta::new(self.referrer(referrer), false));
+        }
+        ix
+    }
+
+    // Burns redeemable tokens for USDC, only accepted once the pool is cancelled.
+    pub fn refund(
+        &self,
+        user: &Pubkey,
+        user_usdc: &Pubkey,
+        user_redeemable: &Pubkey,
+        amount: u64,
+    ) -> Instruction {
+        instruction(
+            ido_pool::accounts::ExchangeRedeemableForUsdc {
+                pool_account: self.address,
+                pool_signer: self.pool_signer(),
+                redeemable_mint: self.account.redeemable_mint,
+                pool_usdc: self.account.pool_usdc,
+                user_authority: *user,
+                user_usdc: *user_usdc,
+                user_redeemable: *user_redeemable,
+                token_program: token::ID,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::ExchangeRedeemableForUsdc { amount },
+        )
+    }
+
+    // Burns redeemable tokens for watermelon once the pool is claimable.
+    pub fn redeem(
+        &self,
+        user: &Pubkey,
+        user_watermelon: &Pubkey,
+        user_redeemable: &Pubkey,
+        amount: u64,
+    ) -> Instruction {
+        instruction(
+            ido_pool::accounts::ExchangeRedeemableForWatermelon {
+                pool_account: self.address,
+                pool_signer: self.pool_signer(),
+                redeemable_mint: self.account.redeemable_mint,
+                pool_watermelon: self.account.pool_watermelon,
+                watermelon_mint: self.account.watermelon_mint,
+                user_authority: *user,
+                user_watermelon: *user_watermelon,
+                user_redeemable: *user_redeemable,
+                token_program: token::ID,
+                clock: sysvar::clock::ID,
+                system_program: system_program::ID,
+                associated_token_program: associated_token::ID,
+                rent: sysvar::rent::ID,
+                blocklist_entry: blocklist_entry_address(user),
+            },
+            ido_pool::instruction::ExchangeRedeemableForWatermelon { amount },
+        )
+    }
+
+    // Pushes watermelon to users that have not claimed, anybody can send it.
+    // `cranker_watermelon` receives the crank reward. Keep batches small
+    // enough to fit in a transaction.
+    pub fn distribute(
+        &self,
+        cranker_watermelon: &Pubkey,
+        recipients: &[DistributionRecipient],
+    ) -> Instruction {
+        let mut ix = instruction(
+            ido_pool::accounts::DistributeWatermelon {
+                pool_account: self.address,
+                pool_signer: self.pool_signer(),
+                redeemable_mint: self.account.redeemable_mint,
+                pool_watermelon: self.account.pool_watermelon,
+                cranker_watermelon: *cranker_watermelon,
+                token_program: token::ID,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::DistributeWatermelon {},
+        );
+        for recipient in recipients {
+            ix.accounts.extend([
+                AccountMeta::new_readonly(self.user_position(&recipient.user), false),
+                AccountMeta::new(recipient.user_redeemable, false),
+                AccountMeta::new(recipient.user_watermelon, false),
+                AccountMeta::new_readonly(blocklist_entry_address(&recipient.user), false),
+            ]);
+        }
+        ix
+    }
+
+    // Quote instructions move no tokens, simulate them and decode the return
+    // data with `PayoutQuote::try_from_slice`.
+    pub fn quote_redemption(&self, amount: u64) -> Instruction {
+        instruction(
+            self.quote_accounts(),
+            ido_pool::instruction::QuoteRedemption { amount },
+        )
+    }
+
+    pub fn quote_deposit(&self, amount: u64) -> Instruction {
+        instruction(
+            self.quote_accounts(),
+            ido_pool::instruction::QuoteDeposit { amount },
+        )
+    }
+
+    fn quote_accounts(&self) -> ido_pool::accounts::QuotePayout {
+        ido_pool::accounts::QuotePayout {
+            pool_account: self.address,
+            redeemable_mint: self.account.redeemable_mint,
+            pool_watermelon: self.account.pool_watermelon,
+            clock: sysvar::clock::ID,
+        }
+    }
+
+    // Proceeds can only be withdrawn to the pool's `creator_usdc`.
+    pub fn withdraw_proceeds(&self, payer: &Pubkey, amount: u64) -> Instruction {
+        instruction(
+            ido_pool::accounts::WithdrawPoolUsdc {
+                pool_account: self.address,
+                pool_signer: self.pool_signer(),
+                pool_usdc: self.account.pool_usdc,
+                distribution_authority: self.account.distribution_authority,
+                payer: *payer,
+                creator_usdc: self.account.creator_usdc,
+                token_program: token::ID,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::WithdrawPoolUsdc { amount },
+        )
+    }
+
+    pub fn withdraw_watermelon(&self, payer: &Pubkey, amount: u64) -> Instruction {
+        instruction(
+            ido_pool::accounts::WithdrawPoolWatermelon {
+                pool_account: self.address,
+                pool_signer: self.pool_signer(),
+                redeemable_mint: self.account.redeemable_mint,
+                pool_watermelon: self.account.pool_watermelon,
+                distribution_authority: self.account.distribution_authority,
+                payer: *payer,
+                creator_watermelon: self.account.creator_watermelon,
+                token_program: token::ID,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::WithdrawPoolWatermelon { amount },
+        )
+    }
+
+    // Moves the creator's withdrawals to new accounts once
+    // `apply_proceeds_accounts` is sent, `PROCEEDS_TIMELOCK_SEC` later.
+    pub fn propose_proceeds_accounts(
+        &self,
+        payer: &Pubkey,
+        creator_usdc: &Pubkey,
+        creator_watermelon: &Pubkey,
+    ) -> Instruction {
+        instruction(
+            ido_pool::accounts::ProposeProceedsAccounts {
+                pool_account: self.address,
+                proceeds_change: proceeds_change_address(&self.origin()),
+                pool_usdc: self.account.pool_usdc,
+                creator_usdc: *creator_usdc,
+                creator_watermelon: *creator_watermelon,
+                distribution_authority: self.account.distribution_authority,
+                payer: *payer,
+                rent: sysvar::rent::ID,
+                clock: sysvar::clock::ID,
+                system_program: system_program::ID,
+            },
+            ido_pool::instruction::ProposeProceedsAccounts {},
+        )
+    }
+
+    pub fn apply_proceeds_accounts(&self, payer: &Pubkey) -> Instruction {
+        instruction(
+            ido_pool::accounts::ApplyProceedsAccounts {
+                pool_account: self.address,
+                proceeds_change: proceeds_change_address(&self.origin()),
+                distribution_authority: self.account.distribution_authority,
+                payer: *payer,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::ApplyProceedsAccounts {},
+        )
+    }
+
+    pub fn cancel_proceeds_accounts(&self, payer: &Pubkey) -> Instruction {
+        instruction(
+            ido_pool::accounts::CancelProceedsAccounts {
+                pool_account: self.address,
+                proceeds_change: proceeds_change_address(&self.origin()),
+                distribution_authority: self.account.distribution_authority,
+                payer: *payer,
+            },
+            ido_pool::instruction::CancelProceedsAccounts {},
+        )
+    }
+
+    pub fn modify_ido_time(&self, payer: &Pubkey, schedule: PoolSchedule) -> Instruction {
+        instruction(
+            ido_pool::accounts::ModifyIdoTime {
+                pool_account: self.address,
+                distribution_authority: self.account.distribution_authority,
+                payer: *payer,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::ModifyIdoTime { schedule },
+        )
+    }
+
+    pub fn configure_referrals(
+        &self,
+        payer: &Pubkey,
+        creator_watermelon: &Pubkey,
+        referral_bps: u16,
+        referral_watermelon: u64,
+    ) -> Instruction {
+        configure_referrals(
+            &self.address,
+            &self.account.pool_watermelon,
+            &self.account.distribution_authority,
+            payer,
+            creator_watermelon,
+            referral_bps,
+            referral_watermelon,
+        )
+    }
+
+    pub fn configure_kyc(&self, payer: &Pubkey, kyc_verifier: &Pubkey) -> Instruction {
+        configure_kyc(
+            &self.address,
+            &self.account.distribution_authority,
+            payer,
+            kyc_verifier,
+        )
+    }
+
+    pub fn configure_gate(&self, payer: &Pubkey, gate: ParticipationGate) -> Instruction {
+        configure_gate(
+            &self.address,
+            &self.account.distribution_authority,
+            payer,
+            gate,
+        )
+    }
+
+    pub fn configure_stake_tiers(&self, payer: &Pubkey, stake_tiers: StakeTiers) -> Instruction {
+        configure_stake_tiers(
+            &self.address,
+            &self.account.distribution_authority,
+            payer,
+            stake_tiers,
+        )
+    }
+
+    pub fn configure_tranches(
+        &self,
+        payer: &Pubkey,
+        tranches: [Tranche; MAX_TRANCHES],
+    ) -> Instruction {
+        configure_tranches(
+            &self.address,
+            &self.account.distribution_authority,
+            payer,
+            tranches,
+        )
+    }
+
+    // Token account holding `user`'s stake when the staking program keeps it
+    // in the associated account of its stake authority.
+    pub fn stake_vault(&self, user: &Pubkey) -> Pubkey {
+        let stake_tiers = &self.account.stake_tiers;
+        get_associated_token_address(
+            &stake_authority_address(&stake_tiers.staking_program, user),
+            &stake_tiers.stake_mint,
+        )
+    }
+
+    // Snapshots the tier of the stake held in `stake_vault` into the user's
+    // position, before the sale starts.
+    pub fn register_stake(&self, user: &Pubkey, stake_vault: &Pubkey) -> Instruction {
+        instruction(
+            ido_pool::accounts::RegisterStake {
+                pool_account: self.address,
+                user_authority: *user,
+                user_position: self.user_position(user),
+                stake_vault: *stake_vault,
+                clock: sysvar::clock::ID,
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+            },
+            ido_pool::instruction::RegisterStake {},
+        )
+    }
+
+    pub fn configure_lottery(
+        &self,
+        payer: &Pubkey,
+        ticket_usdc: u64,
+        winning_tickets: u64,
+        commitment: [u8; 32],
+    ) -> Instruction {
+        configure_lottery(
+            &self.address,
+            &self.account.distribution_authority,
+            payer,
+            ticket_usdc,
+            winning_tickets,
+            commitment,
+        )
+    }
+
+    pub fn ticket(&self, user: &Pubkey) -> Pubkey {
+        lottery_ticket_address(&self.origin(), user)
+    }
+
+    // Escrows `ticket_usdc` for a lottery ticket, deposits of lottery pools
+    // only go through here. Takes the same gate proof and KYC attestation as
+    // `deposit`.
+    pub fn buy_ticket(
+        &self,
+        user: &Pubkey,
+        user_usdc: &Pubkey,
+        gate_proof: &[Pubkey],
+    ) -> Instruction {
+        let mut ix = instruction(
+            ido_pool::accounts::BuyTicket {
+                pool_account: self.address,
+                pool_usdc: self.account.pool_usdc,
+                user_authority: *user,
+                user_usdc: *user_usdc,
+                ticket: self.ticket(user),
+                token_program: token::ID,
+                clock: sysvar::clock::ID,
+                system_program: system_program::ID,
+                instructions: sysvar::instructions::ID,
+                blocklist_entry: blocklist_entry_address(user),
+            },
+            ido_pool::instruction::BuyTicket {},
+        );
+        ix.accounts.extend(
+            gate_proof
+                .iter()
+                .map(|account| AccountMeta::new_readonly(*account, false)),
+        );
+        ix
+    }
+
+    // Picks the slot whose hash seeds the draw once deposits are over, only
+    // the distribution authority signs it.
+    pub fn commit_lottery_draw(&self) -> Instruction {
+        instruction(
+            ido_pool::accounts::CommitLotteryDraw {
+                pool_account: self.address,
+                distribution_authority: self.account.distribution_authority,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::CommitLotteryDraw {},
+        )
+    }
+
+    // Reveals the secret committed to by `configure_lottery` once the slot
+    // picked by `commit_lottery_draw` has passed, only the distribution
+    // authority signs it.
+    pub fn draw_lottery(&self, secret: [u8; 32]) -> Instruction {
+        instruction(
+            ido_pool::accounts::DrawLottery {
+                pool_account: self.address,
+                distribution_authority: self.account.distribution_authority,
+                slot_hashes: sysvar::slot_hashes::ID,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::DrawLottery { secret },
+        )
+    }
+
+    // Turns a winning ticket into redeemable tokens during the grace period
+    // and settling, closing the ticket.
+    pub fn claim_ticket(&self, user: &Pubkey, user_redeemable: &Pubkey) -> Instruction {
+        instruction(
+            ido_pool::accounts::ClaimTicket {
+                pool_account: self.address,
+                pool_signer: self.pool_signer(),
+                redeemable_mint: self.account.redeemable_mint,
+                user_authority: *user,
+                user_redeemable: *user_redeemable,
+                user_position: self.user_position(user),
+                ticket: self.ticket(user),
+                token_program: token::ID,
+                clock: sysvar::clock::ID,
+                system_program: system_program::ID,
+                associated_token_program: associated_token::ID,
+                rent: sysvar::rent::ID,
+                blocklist_entry: blocklist_entry_address(user),
+            },
+            ido_pool::instruction::ClaimTicket {},
+        )
+    }
+
+    // Returns the escrow of a losing ticket, or of any ticket once the pool
+    // is claimable or cancelled.
+    pub fn refund_ticket(&self, user: &Pubkey, user_usdc: &Pubkey) -> Instruction {
+        instruction(
+            ido_pool::accounts::RefundTicket {
+                pool_account: self.address,
+                pool_signer: self.pool_signer(),
+                pool_usdc: self.account.pool_usdc,
+                user_authority: *user,
+                user_usdc: *user_usdc,
+                ticket: self.ticket(user),
+                token_program: token::ID,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::RefundTicket {},
+        )
+    }
+
+    pub fn register_referrer(&self, referrer: &Pubkey) -> Instruction {
+        instruction(
+            ido_pool::accounts::RegisterReferrer {
+                pool_account: self.address,
+                referrer: self.referrer(referrer),
+                referrer_authority: *referrer,
+                clock: sysvar::clock::ID,
+                system_program: system_program::ID,
+            },
+            ido_pool::instruction::RegisterReferrer {},
+        )
+    }
+
+    pub fn claim_referral_reward(
+        &self,
+        referrer: &Pubkey,
+        referrer_usdc: &Pubkey,
+        referrer_watermelon: &Pubkey,
+    ) -> Instruction {
+        instruction(
+            ido_pool::accounts::ClaimReferralReward {
+                pool_account: self.address,
+                pool_signer: self.pool_signer(),
+                referrer: self.referrer(referrer),
+                referrer_authority: *referrer,
+                pool_usdc: self.account.pool_usdc,
+                pool_watermelon: self.account.pool_watermelon,
+                referrer_usdc: *referrer_usdc,
+                referrer_watermelon: *referrer_watermelon,
+                token_program: token::ID,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::ClaimReferralReward {},
+        )
+    }
+
+    // `user` pays for the vote account when they are the first to vote on
+    // `tranche`.
+    pub fn vote_halt(&self, user: &Pubkey, tranche: u8) -> Instruction {
+        instruction(
+            ido_pool::accounts::VoteHalt {
+                pool_account: self.address,
+                halt_vote: self.halt_vote(tranche),
+                user_position: self.user_position(user),
+                user_authority: *user,
+                clock: sysvar::clock::ID,
+                system_program: system_program::ID,
+            },
+            ido_pool::instruction::VoteHalt { tranche },
+        )
+    }
+
+    // Burns as many of `user_redeemable` as the USDC refunded, their
+    // watermelon goes back to the creator.
+    pub fn claim_halt_refund(
+        &self,
+        user: &Pubkey,
+        user_usdc: &Pubkey,
+        user_redeemable: &Pubkey,
+    ) -> Instruction {
+        instruction(
+            ido_pool::accounts::ClaimHaltRefund {
+                pool_account: self.address,
+                pool_signer: self.pool_signer(),
+                user_position: self.user_position(user),
+                redeemable_mint: self.account.redeemable_mint,
+                pool_usdc: self.account.pool_usdc,
+                pool_watermelon: self.account.pool_watermelon,
+                user_authority: *user,
+                user_usdc: *user_usdc,
+                user_redeemable: *user_redeemable,
+                creator_watermelon: self.account.creator_watermelon,
+                token_program: token::ID,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::ClaimHaltRefund {},
+        )
+    }
+
+    pub fn advance_status(&self) -> Instruction {
+        instruction(
+            ido_pool::accounts::AdvancePoolStatus {
+                pool_account: self.address,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::AdvancePoolStatus {},
+        )
+    }
+
+    pub fn cancel(&self, payer: &Pubkey) -> Instruction {
+        instruction(
+            ido_pool::accounts::CancelPool {
+                pool_account: self.address,
+                distribution_authority: self.account.distribution_authority,
+                payer: *payer,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::CancelPool {},
+        )
+    }
+
+    pub fn close(&self, payer: &Pubkey) -> Instruction {
+        instruction(
+            ido_pool::accounts::ClosePool {
+                pool_account: self.address,
+                redeemable_mint: self.account.redeemable_mint,
+                pool_usdc: self.account.pool_usdc,
+                distribution_authority: self.account.distribution_authority,
+                payer: *payer,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::ClosePool {},
+        )
+    }
+
+    // Brings a pool created by an older program version to the current
+    // layout, at `migrated_address`. `payer` pays the rent of the bigger
+    // account and of the vaults if the pool predates them, and gets back the
+    // rent of the accounts closed. Pools only record the USDC mint
+    // through their vault, so it is passed in. The creator accounts become
+    // the proceeds accounts of pools that have none yet, and have to be the
+    // recorded ones otherwise.
+    pub fn migrate(
+        &self,
+        payer: &Pubkey,
+        usdc_mint: &Pubkey,
+        creator_usdc: &Pubkey,
+        creator_watermelon: &Pubkey,
+    ) -> Instruction {
+        instruction(
+            ido_pool::accounts::MigratePool {
+                pool_account: self.address,
+                origin: self.origin(),
+                migrated_pool: self.migrated_address(),
+                pool_signer: self.pool_signer(),
+                usdc_mint: *usdc_mint,
+                watermelon_mint: self.account.watermelon_mint,
+                pool_watermelon: pool_watermelon_address(&self.origin()),
+                pool_usdc: pool_usdc_address(&self.origin()),
+                current_pool_watermelon: self.account.pool_watermelon,
+                current_pool_usdc: self.account.pool_usdc,
+                creator_usdc: *creator_usdc,
+                creator_watermelon: *creator_watermelon,
+                distribution_authority: self.account.distribution_authority,
+                payer: *payer,
+                token_program: token::ID,
+                rent: sysvar::rent::ID,
+                system_program: system_program::ID,
+            },
+            ido_pool::instruction::MigratePool {},
+        )
+    }
+}
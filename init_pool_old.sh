@@ -1 +1 @@
-source ./set_env_mainnet.sh && node cli/index.js init $USDC_MINT $AURY_MINT $AURY_ACCOUNT $IDO_AMOUNT $AUTHORITY --start_time $IDO_START_TS --deposit_duration $IDO_DEPOSIT_PERIOD_SEC --cancel_duration $IDO_CANCEL_PERIOD_SEC --withdraw_ts $IDO_WITHDRAW_TS
\ No newline at end of file
+source ./set_env_mainnet.sh && ido-pool-cli init $USDC_MINT $AURY_MINT $AURY_ACCOUNT $IDO_AMOUNT --authority $AUTHORITY_KEYPAIR --start_time $IDO_START_TS --deposit_duration $IDO_DEPOSIT_PERIOD_SEC --cancel_duration $IDO_CANCEL_PERIOD_SEC --withdraw_ts $IDO_WITHDRAW_TS
\ No newline at end of file
@@ -0,0 +1,220 @@
+// Random sequences of deposits, redemptions and admin withdrawals against a
+// single pool. Whatever the order and size of the redemptions, the tokens
+// burned must never be paid more than their pro-rata share of the watermelon
+// sold, and no user may receive more than their share plus rounding dust.
+//
+//     cargo +nightly fuzz run deposit_redeem
+#![no_main]
+
+#[allow(dead_code)]
+#[path = "../../tests/program_test/mod.rs"]
+mod program_test;
+
+use anchor_lang::prelude::Pubkey;
+use arbitrary::Arbitrary;
+use ido_pool_client::{InitializePoolAccounts, Pool, PoolAccount, PoolSchedule};
+use libfuzzer_sys::fuzz_target;
+use program_test::{ProgramTest, LAMPORTS_PER_SOL};
+use std::str::FromStr;
+
+const USERS: usize = 4;
+// Up to 10M watermelon (9 decimals) for sale, users hold 10M USDC (6
+// decimals) each.
+const MAX_IDO_TOKENS: u64 = 10_000_000_000_000_000;
+const USER_USDC: u64 = 10_000_000_000_000;
+
+#[derive(Arbitrary, Debug)]
+struct Sale {
+    num_ido_tokens: u64,
+    ops: Vec<Op>,
+}
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Deposit { user: u8, amount: u64 },
+    Redeem { user: u8, amount: u64 },
+    WithdrawUsdc { amount: u64 },
+    WithdrawWatermelon { amount: u64 },
+    // Moves the clock into the next phase of the pool.
+    NextPhase,
+}
+
+struct User {
+    authority: Pubkey,
+    usdc: Pubkey,
+    redeemable: Pubkey,
+    watermelon: Pubkey,
+    deposited: u64,
+}
+
+struct Env {
+    test: ProgramTest,
+    deployer: Pubkey,
+    pool: Pool,
+    users: Vec<User>,
+    phases: Vec<i64>,
+    redemptions: u64,
+}
+
+impl Env {
+    fn new(num_ido_tokens: u64) -> Self {
+        let mut test = ProgramTest::new();
+        let deployer = Pubkey::from_str(ido_pool::ALLOWED_DEPLOYER).unwrap();
+        test.fund(&deployer, 10 * LAMPORTS_PER_SOL);
+
+        let usdc_mint = test.create_mint(&Pubkey::new_unique(), 6);
+        let watermelon_mint = test.create_mint(&Pubkey::new_unique(), 9);
+        let creator_usdc = test.create_token_account(&usdc_mint, &deployer, 0);
+        let creator_watermelon =
+            test.create_token_account(&watermelon_mint, &deployer, num_ido_tokens);
+
+        let start_ido_ts = test.now() + 60;
+        let schedule = PoolSchedule::new(
+            start_ido_ts,
+            start_ido_ts + 60,
+            Some(60),
+            start_ido_ts + 180,
+        );
+        let pool_account = Pubkey::new_unique();
+        let redeemable_mint = ido_pool_client::redeemable_mint_address(&pool_account);
+        let ix = ido_pool_client::initialize_pool(
+            &InitializePoolAccounts {
+                pool_account,
+                usdc_mint,
+                watermelon_mint,
+                distribution_authority: deployer,
+                payer: deployer,
+                creator_watermelon,
+                creator_usdc,
+            },
+            num_ido_tokens,
+            schedule,
+            "melon",
+        );
+        test.process(ix, &[&pool_account, &deployer]).unwrap();
+        let account: PoolAccount = test.account(&pool_account);
+
+        let users = (0..USERS)
+            .map(|_| {
+                let authority = Pubkey::new_unique();
+                test.fund(&authority, LAMPORTS_PER_SOL);
+                User {
+                    authority,
+                    usdc: test.create_token_account(&usdc_mint, &authority, USER_USDC),
+                    redeemable: test.create_token_account(&redeemable_mint, &authority, 0),
+                    watermelon: test.create_token_account(&watermelon_mint, &authority, 0),
+                    deposited: 0,
+                }
+            })
+            .collect();
+
+        Env {
+            test,
+            deployer,
+            pool: Pool::new(pool_account, account),
+            users,
+            // First second of Depositing, Grace, Settling and Claimable.
+            phases: vec![
+                schedule.withdraw_melon_ts + 1,
+                schedule.end_ido_ts,
+                schedule.end_deposits_ts,
+                schedule.start_ido_ts + 1,
+            ],
+            redemptions: 0,
+        }
+    }
+
+    // Program errors are expected, only state changes are checked.
+    fn apply(&mut self, op: &Op) {
+        match *op {
+            Op::Deposit { user, amount } => {
+                let index = user as usize % USERS;
+                let user = &self.users[index];
+                let amount = amount % (self.test.balance(&user.usdc) + 1);
+                let ix = self.pool.deposit(
+                    &user.authority,
+                    &user.usdc,
+                    &user.redeemable,
+                    amount,
+                    &[],
+                    None,
+                );
+                if self.test.process(ix, &[&user.authority]).is_ok() {
+                    self.users[index].deposited += amount;
+                }
+            }
+            Op::Redeem { user, amount } => {
+                let user = &self.users[user as usize % USERS];
+                let amount = amount % (self.test.balance(&user.redeemable) + 1);
+                let ix =
+                    self.pool
+                        .redeem(&user.authority, &user.watermelon, &user.redeemable, amount);
+                if self.test.process(ix, &[&user.authority]).is_ok() {
+                    self.redemptions += 1;
+                }
+            }
+            Op::WithdrawUsdc { amount } => {
+                let amount = amount % (self.test.balance(&self.pool.account.pool_usdc) + 1);
+                let ix = self.pool.withdraw_proceeds(&self.deployer, amount);
+                let _ = self.test.process(ix, &[&self.deployer]);
+            }
+            Op::WithdrawWatermelon { amount } => {
+                let amount = amount % (self.test.balance(&self.pool.account.pool_watermelon) + 1);
+                let ix = self.pool.withdraw_watermelon(&self.deployer, amount);
+                let _ = self.test.process(ix, &[&self.deployer]);
+            }
+            Op::NextPhase => {
+                if let Some(ts) = self.phases.pop() {
+                    self.test.warp_to(ts);
+                }
+            }
+        }
+    }
+
+    fn check_invariants(&self) {
+        let num_ido_tokens = self.pool.account.num_ido_tokens as u128;
+        let total_deposited: u128 = self.users.iter().map(|u| u.deposited as u128).sum();
+        let pro_rata = |deposited: u128| match total_deposited {
+            0 => 0,
+            total => deposited * num_ido_tokens / total,
+        };
+        let (mut total_paid, mut total_redeemed) = (0, 0);
+        for user in &self.users {
+            let paid = self.test.balance(&user.watermelon) as u128;
+            total_paid += paid;
+            total_redeemed += user.deposited as u128 - self.test.balance(&user.redeemable) as u128;
+            // Redemptions are computed from the live balances of the pool and
+            // round down. The fraction of a token each one leaves behind stays
+            // in the pool, where it raises the share of every redeemable token
+            // still out, so a user can end up with the dust of every
+            // redemption so far on top of their pro-rata share.
+            assert!(
+                paid <= pro_rata(user.deposited as u128) + self.redemptions as u128,
+                "user paid {} for a pro-rata share of {}",
+                paid,
+                pro_rata(user.deposited as u128)
+            );
+        }
+        // That dust never leaves the pool with the tokens burned though:
+        // together they are paid at most their pro-rata share, below the
+        // floor price less.
+        assert!(
+            total_paid <= pro_rata(total_redeemed),
+            "paid {} for a pro-rata share of {}",
+            total_paid,
+            pro_rata(total_redeemed)
+        );
+        // And what is not paid out is in the pool or back with the creator.
+        let kept = self.test.balance(&self.pool.account.pool_watermelon) as u128
+            + self.test.balance(&self.pool.account.creator_watermelon) as u128;
+        assert_eq!(total_paid + kept, num_ido_tokens);
+    }
+}
+
+fuzz_target!(|sale: Sale| {
+    let mut env = Env::new(1 + sale.num_ido_tokens % MAX_IDO_TOKENS);
+    for op in &sale.ops {
+        env.apply(op);
+        env.check_invariants();
+    }
+});
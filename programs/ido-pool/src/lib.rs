@@ -2,41 +2,79 @@
 //! https://docs.mango.markets/litepaper#token-sale.
 
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::ed25519_program;
+use anchor_lang::solana_program::hash::hashv;
+use anchor_lang::solana_program::program::{invoke, invoke_signed, set_return_data};
 use anchor_lang::solana_program::program_option::COption;
-use anchor_spl::token::{self, Burn, Mint, MintTo, TokenAccount, Transfer};
+use anchor_lang::solana_program::system_instruction;
+use anchor_lang::solana_program::sysvar::instructions as sysvar_instructions;
+use anchor_lang::solana_program::sysvar::slot_hashes;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{self, Approve, Burn, CloseAccount, Mint, MintTo, TokenAccount, Transfer};
 use std::str::FromStr;
 
 declare_id!("7TZqAhcqsPHoZ7sDAeUpDj5woKDDFMYGuX1bgoBsfGPv");
 
 // Update this with the address you want to be able to deploy pools
-const ALLOWED_DEPLOYER: &str = "8DXSNpVJ5xHX7B49kCQVxMgQ2xPALEaZxN1H1sLFEebX";
+pub const ALLOWED_DEPLOYER: &str = "8DXSNpVJ5xHX7B49kCQVxMgQ2xPALEaZxN1H1sLFEebX";
+
+// Share of the total deposited USDC (in basis points) that has to vote for a
+// halt before the unreleased tranches are cancelled and refunded to
+// contributors.
+const HALT_QUORUM_BPS: u64 = 5_000;
+
+// Rescheduling rules for `modify_ido_time`: timestamps can only be moved with
+// this much notice, and claims can never open later than this after the
+// originally announced `withdraw_melon_ts`.
+const MIN_RESCHEDULE_NOTICE_SEC: i64 = 24 * 60 * 60;
+const MAX_SCHEDULE_DELAY_SEC: i64 = 7 * 24 * 60 * 60;
+
+// How long after `withdraw_melon_ts` users can count on their watermelon
+// being in the pool. Until then the creator can only withdraw unsold tokens.
+pub const CLAIM_PERIOD_SEC: i64 = 180 * 24 * 60 * 60;
+
+// Delay between proposing new proceeds accounts and being able to apply
+// them, so contributors notice a redirected raise before it happens.
+pub const PROCEEDS_TIMELOCK_SEC: i64 = 2 * 24 * 60 * 60;
+
+// Share of the watermelon pushed to a user by `distribute_watermelon` (in
+// basis points) that goes to whoever cranked it.
+pub const CRANK_REWARD_BPS: u64 = 10;
+
+// Current `PoolAccount` layout version, pools on older versions have to go
+// through `migrate_pool` before they can be used again.
+pub const POOL_ACCOUNT_VERSION: u8 = 9;
+
+// Shortest time lottery pools leave between the end of deposits and claims
+// opening, for the draw and for winners to claim their tickets.
+pub const MIN_LOTTERY_WINDOW_SEC: i64 = 24 * 60 * 60;
+
+// Slots between `commit_lottery_draw` and the slot whose hash seeds the
+// draw, so that nobody knows the hash when the slot is picked.
+pub const LOTTERY_DRAW_DELAY_SLOTS: u64 = 32;
+
+// Number of entries in a `StakeTiers` table.
+pub const MAX_STAKE_TIERS: usize = 4;
+
+// Number of entries in a `ProceedsTranches` table.
+pub const MAX_TRANCHES: usize = 4;
 
 #[program]
 pub mod ido_pool {
     use super::*;
 
-    #[access_control(InitializePool::accounts(&ctx, nonce) future_start_time(&ctx, start_ido_ts))]
+    #[access_control(InitializePool::accounts(&ctx, nonce))]
     pub fn initialize_pool(
         ctx: Context<InitializePool>,
         num_ido_tokens: u64,
         nonce: u8,
-        start_ido_ts: i64,
-        end_deposits_ts: i64,
-        end_ido_ts: i64,
-        withdraw_melon_ts: i64,
+        schedule: PoolSchedule,
+        sale_symbol: String,
     ) -> Result<()> {
         // let num_ido_tokens_u64 = num_ido_tokens.parse::<u64>().unwrap();
 
         // msg!("Number of IDO Tokens {:?}", num_ido_tokens_u64);
-        if !(start_ido_ts < end_deposits_ts
-            && end_deposits_ts <= end_ido_ts
-            && end_ido_ts <= withdraw_melon_ts)
-        {
-            return Err(ErrorCode::SeqTimes.into());
-        }
-        if num_ido_tokens == 0 {
-            return Err(ErrorCode::InvalidParam.into());
-        }
+        validate_pool_params(num_ido_tokens, &schedule, ctx.accounts.clock.unix_timestamp)?;
 
         let pool_account = &mut ctx.accounts.pool_account;
         if Pubkey::from_str(ALLOWED_DEPLOYER).unwrap() != *ctx.accounts.payer.to_account_info().key
@@ -47,13 +85,16 @@ pub mod ido_pool {
         pool_account.pool_watermelon = *ctx.accounts.pool_watermelon.to_account_info().key;
         pool_account.watermelon_mint = ctx.accounts.pool_watermelon.mint;
         pool_account.pool_usdc = *ctx.accounts.pool_usdc.to_account_info().key;
+        pool_account.creator_usdc = *ctx.accounts.creator_usdc.to_account_info().key;
+        pool_account.creator_watermelon = *ctx.accounts.creator_watermelon.to_account_info().key;
         pool_account.distribution_authority = *ctx.accounts.distribution_authority.key;
         pool_account.nonce = nonce;
         pool_account.num_ido_tokens = num_ido_tokens;
-        pool_account.start_ido_ts = start_ido_ts;
-        pool_account.end_deposits_ts = end_deposits_ts;
-        pool_account.end_ido_ts = end_ido_ts;
-        pool_account.withdraw_melon_ts = withdraw_melon_ts;
+        pool_account.schedule = schedule;
+        pool_account.status = PoolStatus::Scheduled;
+        pool_account.original_withdraw_melon_ts = schedule.withdraw_melon_ts;
+        pool_account.version = POOL_ACCOUNT_VERSION;
+        pool_account.origin = *pool_account.to_account_info().key;
 
         // Transfer Watermelon from creator to pool account.
         let cpi_accounts = Transfer {
@@ -65,35 +106,97 @@ pub mod ido_pool {
         let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
         token::transfer(cpi_ctx, num_ido_tokens)?;
 
+        // Name the redeemable token after the sale so wallets can show it.
+        let (name, symbol) = redeemable_token_name(&sale_symbol)?;
+        let ix = spl_token_metadata::instruction::create_metadata_accounts(
+            spl_token_metadata::ID,
+            *ctx.accounts.redeemable_metadata.key,
+            *ctx.accounts.redeemable_mint.to_account_info().key,
+            *ctx.accounts.pool_signer.key,
+            *ctx.accounts.payer.key,
+            *ctx.accounts.distribution_authority.key,
+            name,
+            symbol,
+            String::new(),
+            None,
+            0,
+            true,
+            true,
+        );
+        let seeds = &[
+            ctx.accounts.watermelon_mint.to_account_info().key.as_ref(),
+            &[nonce],
+        ];
+        invoke_signed(
+            &ix,
+            &[
+                ctx.accounts.redeemable_metadata.clone(),
+                ctx.accounts.redeemable_mint.to_account_info(),
+                ctx.accounts.pool_signer.clone(),
+                ctx.accounts.payer.clone(),
+                ctx.accounts.distribution_authority.clone(),
+                ctx.accounts.system_program.to_account_info(),
+                ctx.accounts.rent.to_account_info(),
+                ctx.accounts.token_metadata_program.clone(),
+            ],
+            &[&seeds[..]],
+        )?;
+
         Ok(())
     }
 
-    pub fn modify_ido_time(
-        ctx: Context<ModifyIdoTime>,
-        start_ido_ts: i64,
-        end_deposits_ts: i64,
-        end_ido_ts: i64,
-        withdraw_melon_ts: i64,
-    ) -> Result<()> {
-        if !(start_ido_ts < end_deposits_ts
-            && end_deposits_ts < end_ido_ts
-            && end_ido_ts < withdraw_melon_ts)
-        {
-            return Err(ErrorCode::SeqTimes.into());
-        }
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[
+            PoolStatus::Scheduled,
+            PoolStatus::Depositing,
+            PoolStatus::Grace,
+            PoolStatus::Settling
+        ]
+    ))]
+    pub fn modify_ido_time(ctx: Context<ModifyIdoTime>, schedule: PoolSchedule) -> Result<()> {
+        schedule.validate()?;
         if Pubkey::from_str(ALLOWED_DEPLOYER).unwrap() != *ctx.accounts.payer.to_account_info().key
         {
             return Err(ErrorCode::InvalidParam.into());
         }
         let pool_account = &mut ctx.accounts.pool_account;
-        pool_account.start_ido_ts = start_ido_ts;
-        pool_account.end_deposits_ts = end_deposits_ts;
-        pool_account.end_ido_ts = end_ido_ts;
-        pool_account.withdraw_melon_ts = withdraw_melon_ts;
+        let now = ctx.accounts.clock.unix_timestamp;
+        let current = pool_account.schedule;
+        for (current_ts, new_ts) in [
+            (current.start_ido_ts, schedule.start_ido_ts),
+            (current.end_deposits_ts, schedule.end_deposits_ts),
+            (current.end_ido_ts, schedule.end_ido_ts),
+            (current.withdraw_melon_ts, schedule.withdraw_melon_ts),
+        ] {
+            reschedule_allowed(current_ts, new_ts, now)?;
+        }
+        if schedule.withdraw_melon_ts - pool_account.original_withdraw_melon_ts
+            > MAX_SCHEDULE_DELAY_SEC
+        {
+            return Err(ErrorCode::MaxDelayExceeded.into());
+        }
+        // Claims cannot open after the first tranche is released, the
+        // guaranteed round has to stay within the deposit window and
+        // lotteries need time to be drawn.
+        pool_account.tranches.validate(&schedule)?;
+        pool_account.stake_tiers.validate(&schedule)?;
+        pool_account.lottery.validate(&schedule)?;
+
+        pool_account.schedule = schedule;
+        pool_account.schedule_modifications = pool_account
+            .schedule_modifications
+            .checked_add(1)
+            .ok_or(ErrorCode::InvalidParam)?;
         Ok(())
     }
 
-    #[access_control(unrestricted_phase(&ctx))]
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Depositing]
+    ))]
     pub fn exchange_usdc_for_redeemable(
         ctx: Context<ExchangeUsdcForRedeemable>,
         amount: u64,
@@ -101,11 +204,51 @@ pub mod ido_pool {
         if amount == 0 {
             return Err(ErrorCode::InvalidParam.into());
         }
+        if ctx.accounts.pool_account.lottery.enabled() {
+            return Err(ErrorCode::DepositsThroughLottery.into());
+        }
+        if is_blocked(&ctx.accounts.blocklist_entry, ctx.program_id) {
+            return Err(ErrorCode::AddressBlocked.into());
+        }
         // While token::transfer will check this, we prefer a verbose err msg.
         if ctx.accounts.user_usdc.amount < amount {
             return Err(ErrorCode::LowUsdc.into());
         }
 
+        // Gated pools take the proof of the user's holdings as the first
+        // remaining accounts, before the optional referrer.
+        let proof_accounts = ctx.accounts.pool_account.gate.kind.proof_accounts();
+        if ctx.remaining_accounts.len() < proof_accounts {
+            return Err(ErrorCode::GateNotSatisfied.into());
+        }
+        let (gate_proofs, remaining_accounts) = ctx.remaining_accounts.split_at(proof_accounts);
+        check_participation(
+            &ctx.accounts.pool_account,
+            ctx.accounts.user_authority.key,
+            gate_proofs,
+            &ctx.accounts.instructions,
+            ctx.accounts.user_position.usdc_deposited,
+            amount,
+            ctx.accounts.clock.unix_timestamp,
+        )?;
+
+        // The guaranteed round of tiered pools is reserved to users that
+        // registered their stake, up to the allocation of their tier.
+        let stake_tiers = &ctx.accounts.pool_account.stake_tiers;
+        if stake_tiers.in_guaranteed_round(ctx.accounts.clock.unix_timestamp) {
+            let user_position = &ctx.accounts.user_position;
+            if user_position.stake_tier == 0 {
+                return Err(ErrorCode::NoStakeTier.into());
+            }
+            let deposited = user_position
+                .usdc_deposited
+                .checked_add(amount)
+                .ok_or(ErrorCode::InvalidParam)?;
+            if deposited > user_position.stake_allocation {
+                return Err(ErrorCode::TierAllocationExceeded.into());
+            }
+        }
+
         // Transfer user's USDC to pool USDC account.
         let cpi_accounts = Transfer {
             from: ctx.accounts.user_usdc.to_account_info(),
@@ -116,67 +259,111 @@ pub mod ido_pool {
         let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
         token::transfer(cpi_ctx, amount)?;
 
-        // Mint Redeemable to user Redeemable account.
+        issue_redeemable(
+            &ctx.accounts.pool_account,
+            &ctx.accounts.pool_signer,
+            &ctx.accounts.redeemable_mint,
+            &ctx.accounts.user_redeemable,
+            &ctx.accounts.user_authority,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+        record_deposit(
+            &mut ctx.accounts.pool_account,
+            &mut ctx.accounts.user_position,
+            ctx.accounts.user_authority.key,
+            amount,
+        )?;
+        let pool_account = &mut ctx.accounts.pool_account;
+
+        // An optional referrer account can be passed after the gate proofs to
+        // credit the deposit to them.
+        if let Some(referrer_info) = remaining_accounts.first() {
+            let mut referrer: Account<Referrer> = Account::try_from(referrer_info)?;
+            if !referrer_info.is_writable
+                || referrer.pool_account != pool_account.origin
+                || referrer.authority == *ctx.accounts.user_authority.key
+            {
+                return Err(ErrorCode::InvalidReferrer.into());
+            }
+            referrer.referred_usdc = referrer
+                .referred_usdc
+                .checked_add(amount)
+                .ok_or(ErrorCode::InvalidParam)?;
+            referrer.exit(ctx.program_id)?;
+            pool_account.total_referred_usdc = pool_account
+                .total_referred_usdc
+                .checked_add(amount)
+                .ok_or(ErrorCode::InvalidParam)?;
+        }
+
+        Ok(())
+    }
+
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Depositing, PoolStatus::Grace, PoolStatus::Cancelled]
+    ))]
+    pub fn exchange_redeemable_for_usdc(
+        ctx: Context<ExchangeRedeemableForUsdc>,
+        amount: u64,
+    ) -> Result<()> {
+        // Deposits are final unless the pool gets cancelled.
+        if ctx.accounts.pool_account.status != PoolStatus::Cancelled {
+            return Err(ErrorCode::UsdcWithdrawNotAllowed.into());
+        }
+        if amount == 0 {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        // While token::burn will check this, we prefer a verbose err msg.
+        if ctx.accounts.user_redeemable.amount < amount {
+            return Err(ErrorCode::LowRedeemable.into());
+        }
+
+        // Burn the user's redeemable tokens.
+        let cpi_accounts = Burn {
+            mint: ctx.accounts.redeemable_mint.to_account_info(),
+            to: ctx.accounts.user_redeemable.to_account_info(),
+            authority: ctx.accounts.user_authority.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.clone();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::burn(cpi_ctx, amount)?;
+
+        // Transfer USDC from pool account to user.
         let seeds = &[
             ctx.accounts.pool_account.watermelon_mint.as_ref(),
             &[ctx.accounts.pool_account.nonce],
         ];
         let signer = &[&seeds[..]];
-        let cpi_accounts = MintTo {
-            mint: ctx.accounts.redeemable_mint.to_account_info(),
-            to: ctx.accounts.user_redeemable.to_account_info(),
-            authority: ctx.accounts.pool_signer.clone(),
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.pool_usdc.to_account_info(),
+            to: ctx.accounts.user_usdc.to_account_info(),
+            authority: ctx.accounts.pool_signer.to_account_info(),
         };
         let cpi_program = ctx.accounts.token_program.clone();
         let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-        token::mint_to(cpi_ctx, amount)?;
+        token::transfer(cpi_ctx, amount)?;
+
+        let stats = &mut ctx.accounts.pool_account.stats;
+        stats.redeemable_burned = stats
+            .redeemable_burned
+            .checked_add(amount)
+            .ok_or(ErrorCode::InvalidParam)?;
+        stats.usdc_withdrawn = stats
+            .usdc_withdrawn
+            .checked_add(amount)
+            .ok_or(ErrorCode::InvalidParam)?;
 
         Ok(())
     }
 
-    #[access_control(withdraw_only_phase(&ctx))]
-    pub fn exchange_redeemable_for_usdc(
-        ctx: Context<ExchangeRedeemableForUsdc>,
-        _amount: u64,
-    ) -> Result<()> {
-        return Err(ErrorCode::UsdcWithdrawNotAllowed.into());
-        // if amount == 0 {
-        //     return Err(ErrorCode::InvalidParam.into());
-        // }
-        // // While token::burn will check this, we prefer a verbose err msg.
-        // if ctx.accounts.user_redeemable.amount < amount {
-        //     return Err(ErrorCode::LowRedeemable.into());
-        // }
-
-        // // Burn the user's redeemable tokens.
-        // let cpi_accounts = Burn {
-        //     mint: ctx.accounts.redeemable_mint.to_account_info(),
-        //     to: ctx.accounts.user_redeemable.to_account_info(),
-        //     authority: ctx.accounts.user_authority.to_account_info(),
-        // };
-        // let cpi_program = ctx.accounts.token_program.clone();
-        // let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        // token::burn(cpi_ctx, amount)?;
-
-        // // Transfer USDC from pool account to user.
-        // let seeds = &[
-        //     ctx.accounts.pool_account.watermelon_mint.as_ref(),
-        //     &[ctx.accounts.pool_account.nonce],
-        // ];
-        // let signer = &[&seeds[..]];
-        // let cpi_accounts = Transfer {
-        //     from: ctx.accounts.pool_usdc.to_account_info(),
-        //     to: ctx.accounts.user_usdc.to_account_info(),
-        //     authority: ctx.accounts.pool_signer.to_account_info(),
-        // };
-        // let cpi_program = ctx.accounts.token_program.clone();
-        // let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-        // token::transfer(cpi_ctx, amount)?;
-
-        // Ok(())
-    }
-
-    #[access_control(ido_over(&ctx.accounts.pool_account, &ctx.accounts.clock))]
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Claimable]
+    ))]
     pub fn exchange_redeemable_for_watermelon(
         ctx: Context<ExchangeRedeemableForWatermelon>,
         amount: u64,
@@ -185,6 +372,9 @@ pub mod ido_pool {
         if amount == 0 {
             return Err(ErrorCode::InvalidParam.into());
         }
+        if is_blocked(&ctx.accounts.blocklist_entry, ctx.program_id) {
+            return Err(ErrorCode::AddressBlocked.into());
+        }
         // While token::burn will check this, we prefer a verbose err msg.
         if ctx.accounts.user_redeemable.amount < amount {
             return Err(ErrorCode::LowRedeemable.into());
@@ -197,33 +387,13 @@ pub mod ido_pool {
         //     "redeemable_mint.supply: {:?}",
         //     ctx.accounts.redeemable_mint.supply
         // );
-        let real_pool_supply = ctx.accounts.pool_watermelon.amount;
-        let real_redeemable_supply = ctx.accounts.redeemable_mint.supply * u64::pow(10, 3);
-        // msg!("real_pool_supply: {:?}", real_pool_supply);
-        // msg!("real_redeemable_supply: {:?}", real_redeemable_supply);
-        let token_price: f64 =
-            (real_redeemable_supply as f64 / real_pool_supply as f64) * f64::powf(10.0, 9.0);
-        // The token multiple will be token price / 0.50;
-        // If token multiple is >= 1, then we don't have to distribute a fraction of the tokens
-        let floor_price = 0.50 * f64::powf(10.0, 9.0);
-        // msg!("floor_price: {:?}", floor_price);
-        let token_multiple = token_price / floor_price;
-        // msg!("token_multiple: {:?}", token_multiple);
-        // msg!("token_price: {:?}", token_price);
-        // Calculate watermelon tokens due.
-        let mut watermelon_amount = (amount as u128)
-            .checked_mul(ctx.accounts.pool_watermelon.amount as u128)
-            .unwrap()
-            .checked_div(ctx.accounts.redeemable_mint.supply as u128)
-            .unwrap();
-        let mut _new_watermelon_amount: u128 = 0;
-        // msg!("watermelon_amount: {:?}", watermelon_amount);
-        // msg!("token_price < 0.50: {:?}", token_price < 0.50);
-        if token_price < floor_price {
-            // msg!("token_price is less than 0.50000000, should not distribute all tokens");
-            watermelon_amount = ((watermelon_amount as f64) * token_multiple) as u128;
-            // msg!("new watermelon_amount: {:?}", watermelon_amount);
-        }
+        // Watermelon reserved for referrers is not part of the sale.
+        let sale_watermelon = ctx
+            .accounts
+            .pool_account
+            .sale_watermelon(ctx.accounts.pool_watermelon.amount);
+        let watermelon_amount =
+            redemption_amount(amount, ctx.accounts.redeemable_mint.supply, sale_watermelon);
 
         // Burn the user's redeemable tokens.
         let cpi_accounts = Burn {
@@ -249,17 +419,51 @@ pub mod ido_pool {
         let cpi_program = ctx.accounts.token_program.clone();
         let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
 
-        token::transfer(cpi_ctx, watermelon_amount as u64)?;
+        token::transfer(cpi_ctx, watermelon_amount)?;
+
+        let stats = &mut ctx.accounts.pool_account.stats;
+        stats.redeemable_burned = stats
+            .redeemable_burned
+            .checked_add(amount)
+            .ok_or(ErrorCode::InvalidParam)?;
+        stats.watermelon_distributed = stats
+            .watermelon_distributed
+            .checked_add(watermelon_amount)
+            .ok_or(ErrorCode::InvalidParam)?;
 
         Ok(())
     }
 
-    #[access_control(ido_over(&ctx.accounts.pool_account, &ctx.accounts.clock))]
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Claimable]
+    ))]
     pub fn withdraw_pool_usdc(ctx: Context<WithdrawPoolUsdc>, amount: u64) -> Result<()> {
         if Pubkey::from_str(ALLOWED_DEPLOYER).unwrap() != *ctx.accounts.payer.to_account_info().key
         {
             return Err(ErrorCode::InvalidParam.into());
         }
+        // USDC referral bonuses stay in the pool until they are claimed, so
+        // do lottery tickets until they are claimed or refunded.
+        let pool_account = &ctx.accounts.pool_account;
+        let available = ctx
+            .accounts
+            .pool_usdc
+            .amount
+            .saturating_sub(pool_account.reserved_usdc());
+        if amount > available {
+            return Err(ErrorCode::ReservedForReferrals.into());
+        }
+        // The rest of the raise is released tranche by tranche, tranches
+        // cancelled by a halt vote never are.
+        if amount > pool_account.releasable_usdc(ctx.accounts.clock.unix_timestamp) {
+            return Err(if pool_account.tranches.halted {
+                ErrorCode::ProceedsHalted.into()
+            } else {
+                ErrorCode::ProceedsNotReleased.into()
+            });
+        }
         // Transfer total USDC from pool account to creator account.
         let seeds = &[
             ctx.accounts.pool_account.watermelon_mint.as_ref(),
@@ -275,10 +479,134 @@ pub mod ido_pool {
         let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
         token::transfer(cpi_ctx, amount)?;
 
+        let stats = &mut ctx.accounts.pool_account.stats;
+        stats.creator_usdc_withdrawn = stats
+            .creator_usdc_withdrawn
+            .checked_add(amount)
+            .ok_or(ErrorCode::InvalidParam)?;
+
+        Ok(())
+    }
+
+    // Pushes watermelon to users that have not claimed, taking
+    // (user_position, user_redeemable, user_watermelon, blocklist_entry)
+    // groups as remaining accounts. The delegated redeemable tokens are
+    // burned like in `exchange_redeemable_for_watermelon` and the cranker
+    // keeps `CRANK_REWARD_BPS` of every payout. Users with nothing delegated
+    // to the pool anymore are skipped, so batches can safely be retried, and
+    // so are blocked users. Cranking during a halt vote is fine, halt refunds
+    // do not depend on the redeemable tokens left.
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Claimable]
+    ))]
+    pub fn distribute_watermelon<'info>(
+        ctx: Context<'_, '_, '_, 'info, DistributeWatermelon<'info>>,
+    ) -> Result<()> {
+        if ctx.remaining_accounts.len() % 4 != 0 {
+            return Err(ErrorCode::InvalidDistribution.into());
+        }
+        let pool_key = ctx.accounts.pool_account.origin;
+        let seeds = &[
+            ctx.accounts.pool_account.watermelon_mint.as_ref(),
+            &[ctx.accounts.pool_account.nonce],
+        ];
+        let signer = &[&seeds[..]];
+
+        // Balances are tracked locally, every payout has to see the burns and
+        // transfers of the previous ones.
+        let initial_redeemable_supply = ctx.accounts.redeemable_mint.supply;
+        let initial_pool_watermelon = ctx.accounts.pool_watermelon.amount;
+        let mut redeemable_supply = initial_redeemable_supply;
+        let mut pool_watermelon = initial_pool_watermelon;
+        let mut crank_reward: u64 = 0;
+        for user_accounts in ctx.remaining_accounts.chunks(4) {
+            let user_position: Account<UserPosition> = Account::try_from(&user_accounts[0])?;
+            let user_redeemable: Account<TokenAccount> = Account::try_from(&user_accounts[1])?;
+            let user_watermelon: Account<TokenAccount> = Account::try_from(&user_accounts[2])?;
+            // The token accounts are written to, read-only ones would only
+            // fail in the token program.
+            if !user_accounts[1].is_writable
+                || !user_accounts[2].is_writable
+                || user_position.pool_account != pool_key
+                || user_redeemable.owner != user_position.owner
+                || user_redeemable.mint != ctx.accounts.pool_account.redeemable_mint
+                || user_watermelon.owner != user_position.owner
+                || *user_accounts[3].key != blocklist_entry_address(&user_position.owner)
+            {
+                return Err(ErrorCode::InvalidDistribution.into());
+            }
+            if is_blocked(&user_accounts[3], ctx.program_id) {
+                continue;
+            }
+            if user_redeemable.delegate != COption::Some(*ctx.accounts.pool_signer.key) {
+                continue;
+            }
+            let amount = user_redeemable.amount.min(user_redeemable.delegated_amount);
+            if amount == 0 {
+                continue;
+            }
+
+            let sale_watermelon = ctx.accounts.pool_account.sale_watermelon(pool_watermelon);
+            let watermelon_amount = redemption_amount(amount, redeemable_supply, sale_watermelon);
+            let reward = ((watermelon_amount as u128) * (CRANK_REWARD_BPS as u128) / 10_000) as u64;
+
+            // Burn the user's redeemable tokens as their delegate.
+            let cpi_accounts = Burn {
+                mint: ctx.accounts.redeemable_mint.to_account_info(),
+                to: user_accounts[1].clone(),
+                authority: ctx.accounts.pool_signer.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.clone();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::burn(cpi_ctx, amount)?;
+
+            // Transfer Watermelon from pool account to user.
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.pool_watermelon.to_account_info(),
+                to: user_accounts[2].clone(),
+                authority: ctx.accounts.pool_signer.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.clone();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::transfer(cpi_ctx, watermelon_amount - reward)?;
+
+            redeemable_supply -= amount;
+            pool_watermelon -= watermelon_amount;
+            crank_reward += reward;
+        }
+
+        // Transfer the rewards of the whole batch to the cranker.
+        if crank_reward > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.pool_watermelon.to_account_info(),
+                to: ctx.accounts.cranker_watermelon.to_account_info(),
+                authority: ctx.accounts.pool_signer.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.clone();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::transfer(cpi_ctx, crank_reward)?;
+        }
+
+        let stats = &mut ctx.accounts.pool_account.stats;
+        stats.redeemable_burned = stats
+            .redeemable_burned
+            .checked_add(initial_redeemable_supply - redeemable_supply)
+            .ok_or(ErrorCode::InvalidParam)?;
+        stats.watermelon_distributed = stats
+            .watermelon_distributed
+            .checked_add(initial_pool_watermelon - pool_watermelon)
+            .ok_or(ErrorCode::InvalidParam)?;
+
         Ok(())
     }
 
-    // #[access_control(ido_over(&ctx.accounts.pool_account, &ctx.accounts.clock))]
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Claimable, PoolStatus::Cancelled, PoolStatus::Closed]
+    ))]
     pub fn withdraw_pool_watermelon(
         ctx: Context<WithdrawPoolWatermelon>,
         amount: u64,
@@ -287,6 +615,14 @@ pub mod ido_pool {
         {
             return Err(ErrorCode::InvalidParam.into());
         }
+        let withdrawable = ctx.accounts.pool_account.withdrawable_watermelon(
+            ctx.accounts.pool_watermelon.amount,
+            ctx.accounts.redeemable_mint.supply,
+            ctx.accounts.clock.unix_timestamp,
+        );
+        if amount > withdrawable {
+            return Err(ErrorCode::WithdrawTokensNotAllowed.into());
+        }
 
         // Transfer total watermelon from pool account to creator account.
         let seeds = &[
@@ -303,175 +639,1921 @@ pub mod ido_pool {
         let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
         token::transfer(cpi_ctx, amount)?;
 
+        let stats = &mut ctx.accounts.pool_account.stats;
+        stats.creator_watermelon_withdrawn = stats
+            .creator_watermelon_withdrawn
+            .checked_add(amount)
+            .ok_or(ErrorCode::InvalidParam)?;
+
         Ok(())
     }
-}
 
-#[derive(Accounts)]
-pub struct InitializePool<'info> {
-    #[account(init, payer = payer, space = 8 + 32 + 32 + 32 + 32 + 32 + 1 + 8 + 8 + 8 + 8 + 8)]
-    pub pool_account: Box<Account<'info, PoolAccount>>,
-    pub pool_signer: AccountInfo<'info>,
-    #[account(
-        constraint = redeemable_mint.mint_authority == COption::Some(*pool_signer.key),
-        constraint = redeemable_mint.supply == 0
-    )]
-    pub redeemable_mint: Box<Account<'info, Mint>>,
-    #[account(constraint = usdc_mint.decimals == redeemable_mint.decimals)]
-    pub usdc_mint: Box<Account<'info, Mint>>,
-    #[account(constraint = pool_watermelon.mint == *watermelon_mint.to_account_info().key)]
-    pub watermelon_mint: Box<Account<'info, Mint>>,
-    #[account(mut, constraint = pool_watermelon.owner == *pool_signer.key)]
-    pub pool_watermelon: Box<Account<'info, TokenAccount>>,
-    #[account(constraint = pool_usdc.owner == *pool_signer.key)]
-    pub pool_usdc: Box<Account<'info, TokenAccount>>,
-    #[account(signer)]
-    pub distribution_authority: AccountInfo<'info>,
-    #[account(signer)]
-    pub payer: AccountInfo<'info>,
-    #[account(mut)]
-    pub creator_watermelon: Box<Account<'info, TokenAccount>>,
-    #[account(constraint = token_program.key == &token::ID)]
-    pub token_program: AccountInfo<'info>,
-    pub rent: Sysvar<'info, Rent>,
-    pub clock: Sysvar<'info, Clock>,
-    pub system_program: Program<'info, System>,
-}
+    // Starts moving the creator's withdrawals to new accounts, replacing any
+    // pending change. Takes effect after `PROCEEDS_TIMELOCK_SEC`.
+    pub fn propose_proceeds_accounts(ctx: Context<ProposeProceedsAccounts>) -> Result<()> {
+        if Pubkey::from_str(ALLOWED_DEPLOYER).unwrap() != *ctx.accounts.payer.key {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        let proceeds_change = &mut ctx.accounts.proceeds_change;
+        proceeds_change.pool_account = ctx.accounts.pool_account.origin;
+        proceeds_change.creator_usdc = *ctx.accounts.creator_usdc.to_account_info().key;
+        proceeds_change.creator_watermelon = *ctx.accounts.creator_watermelon.to_account_info().key;
+        proceeds_change.effective_ts = ctx.accounts.clock.unix_timestamp + PROCEEDS_TIMELOCK_SEC;
+        Ok(())
+    }
 
-impl<'info> InitializePool<'info> {
-    fn accounts(ctx: &Context<InitializePool<'info>>, nonce: u8) -> Result<()> {
-        let expected_signer = Pubkey::create_program_address(
-            &[ctx.accounts.pool_watermelon.mint.as_ref(), &[nonce]],
-            ctx.program_id,
-        )
-        .map_err(|_| ErrorCode::InvalidNonce)?;
-        if ctx.accounts.pool_signer.key != &expected_signer {
-            return Err(ErrorCode::InvalidNonce.into());
+    pub fn apply_proceeds_accounts(ctx: Context<ApplyProceedsAccounts>) -> Result<()> {
+        if Pubkey::from_str(ALLOWED_DEPLOYER).unwrap() != *ctx.accounts.payer.key {
+            return Err(ErrorCode::InvalidParam.into());
         }
+        let proceeds_change = &ctx.accounts.proceeds_change;
+        if ctx.accounts.clock.unix_timestamp < proceeds_change.effective_ts {
+            return Err(ErrorCode::ProceedsTimelocked.into());
+        }
+        let pool_account = &mut ctx.accounts.pool_account;
+        pool_account.creator_usdc = proceeds_change.creator_usdc;
+        pool_account.creator_watermelon = proceeds_change.creator_watermelon;
         Ok(())
     }
-}
 
-#[derive(Accounts)]
-pub struct ExchangeUsdcForRedeemable<'info> {
-    #[account(has_one = redeemable_mint, has_one = pool_usdc)]
-    pub pool_account: Account<'info, PoolAccount>,
-    #[account(seeds = [pool_account.watermelon_mint.as_ref()], bump = pool_account.nonce)]
-    pool_signer: AccountInfo<'info>,
-    #[account(
-        mut,
-        constraint = redeemable_mint.mint_authority == COption::Some(*pool_signer.key)
-    )]
-    pub redeemable_mint: Account<'info, Mint>,
-    #[account(mut, constraint = pool_usdc.owner == *pool_signer.key)]
-    pub pool_usdc: Account<'info, TokenAccount>,
-    #[account(signer)]
-    pub user_authority: AccountInfo<'info>,
-    #[account(mut, constraint = user_usdc.owner == *user_authority.key)]
-    pub user_usdc: Account<'info, TokenAccount>,
-    #[account(mut, constraint = user_redeemable.owner == *user_authority.key)]
-    pub user_redeemable: Account<'info, TokenAccount>,
-    #[account(constraint = token_program.key == &token::ID)]
-    pub token_program: AccountInfo<'info>,
-    pub clock: Sysvar<'info, Clock>,
-}
+    // Drops the pending proceeds change, e.g. one proposed by mistake. A new
+    // proposal replaces it as well, restarting the timelock.
+    pub fn cancel_proceeds_accounts(ctx: Context<CancelProceedsAccounts>) -> Result<()> {
+        if Pubkey::from_str(ALLOWED_DEPLOYER).unwrap() != *ctx.accounts.payer.key {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct ExchangeRedeemableForUsdc<'info> {
-    #[account(has_one = redeemable_mint, has_one = pool_usdc)]
-    pub pool_account: Account<'info, PoolAccount>,
-    #[account(seeds = [pool_account.watermelon_mint.as_ref()], bump = pool_account.nonce)]
-    pool_signer: AccountInfo<'info>,
-    #[account(
-        mut,
-        constraint = redeemable_mint.mint_authority == COption::Some(*pool_signer.key)
-    )]
-    pub redeemable_mint: Account<'info, Mint>,
-    #[account(mut, constraint = pool_usdc.owner == *pool_signer.key)]
-    pub pool_usdc: Account<'info, TokenAccount>,
-    #[account(signer)]
-    pub user_authority: AccountInfo<'info>,
-    #[account(mut, constraint = user_usdc.owner == *user_authority.key)]
-    pub user_usdc: Account<'info, TokenAccount>,
-    #[account(mut, constraint = user_redeemable.owner == *user_authority.key)]
-    pub user_redeemable: Account<'info, TokenAccount>,
-    #[account(constraint = token_program.key == &token::ID)]
-    pub token_program: AccountInfo<'info>,
-    pub clock: Sysvar<'info, Clock>,
-}
+    // Votes to cancel `tranche` and every later one, weighted by the user's
+    // contribution. Only the next tranche to be released can be voted on,
+    // once contributors can claim their watermelon.
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Claimable]
+    ))]
+    pub fn vote_halt(ctx: Context<VoteHalt>, tranche: u8) -> Result<()> {
+        let pool_account = &mut ctx.accounts.pool_account;
+        if pool_account.tranches.halted {
+            return Err(ErrorCode::ProceedsHalted.into());
+        }
+        let now = ctx.accounts.clock.unix_timestamp;
+        if pool_account.next_tranche(now) != Some(tranche as usize) {
+            return Err(ErrorCode::TrancheNotOpen.into());
+        }
+        let user_position = &mut ctx.accounts.user_position;
+        if user_position.halt_votes & (1 << tranche) != 0 {
+            return Err(ErrorCode::AlreadyVoted.into());
+        }
+        if user_position.usdc_deposited == 0 {
+            return Err(ErrorCode::NoContribution.into());
+        }
+        user_position.halt_votes |= 1 << tranche;
+        let halt_vote = &mut ctx.accounts.halt_vote;
+        halt_vote.pool_account = pool_account.origin;
+        halt_vote.tranche = tranche;
+        halt_vote.votes = halt_vote
+            .votes
+            .checked_add(user_position.usdc_deposited)
+            .ok_or(ErrorCode::InvalidParam)?;
 
-#[derive(Accounts)]
-pub struct ExchangeRedeemableForWatermelon<'info> {
-    #[account(has_one = redeemable_mint, has_one = pool_watermelon)]
-    pub pool_account: Account<'info, PoolAccount>,
-    #[account(seeds = [pool_account.watermelon_mint.as_ref()], bump = pool_account.nonce)]
-    pool_signer: AccountInfo<'info>,
-    #[account(
-        mut,
-        constraint = redeemable_mint.mint_authority == COption::Some(*pool_signer.key)
-    )]
-    pub redeemable_mint: Account<'info, Mint>,
-    #[account(mut, constraint = pool_watermelon.owner == *pool_signer.key)]
-    pub pool_watermelon: Account<'info, TokenAccount>,
-    #[account(signer)]
-    pub user_authority: AccountInfo<'info>,
-    #[account(mut, constraint = user_watermelon.owner == *user_authority.key)]
-    pub user_watermelon: Account<'info, TokenAccount>,
-    #[account(mut, constraint = user_redeemable.owner == *user_authority.key)]
-    pub user_redeemable: Account<'info, TokenAccount>,
-    #[account(constraint = token_program.key == &token::ID)]
-    pub token_program: AccountInfo<'info>,
-    pub clock: Sysvar<'info, Clock>,
-}
+        // Once the quorum is reached the tranche and every later one are
+        // never released, contributors get them back with
+        // `claim_halt_refund`.
+        let total = pool_account.total_usdc_deposited as u128;
+        if (halt_vote.votes as u128) * 10_000 >= total * HALT_QUORUM_BPS as u128 {
+            pool_account.tranches.halted = true;
+            pool_account.tranches.halted_tranche = tranche;
+        }
 
-#[derive(Accounts)]
-pub struct WithdrawPoolUsdc<'info> {
-    #[account(has_one = pool_usdc, has_one = distribution_authority)]
-    pub pool_account: Account<'info, PoolAccount>,
-    #[account(seeds = [pool_account.watermelon_mint.as_ref()], bump = pool_account.nonce)]
-    pub pool_signer: AccountInfo<'info>,
-    #[account(mut, constraint = pool_usdc.owner == *pool_signer.key)]
-    pub pool_usdc: Account<'info, TokenAccount>,
-    #[account(signer)]
-    pub distribution_authority: AccountInfo<'info>,
-    #[account(signer)]
-    pub payer: AccountInfo<'info>,
-    #[account(mut)]
-    pub creator_usdc: Account<'info, TokenAccount>,
-    #[account(constraint = token_program.key == &token::ID)]
-    pub token_program: AccountInfo<'info>,
-    pub clock: Sysvar<'info, Clock>,
-}
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct WithdrawPoolWatermelon<'info> {
-    #[account(has_one = pool_watermelon, has_one = distribution_authority)]
-    pub pool_account: Account<'info, PoolAccount>,
-    #[account(seeds = [pool_account.watermelon_mint.as_ref()], bump = pool_account.nonce)]
-    pub pool_signer: AccountInfo<'info>,
-    #[account(mut, constraint = pool_watermelon.owner == *pool_signer.key)]
-    pub pool_watermelon: Account<'info, TokenAccount>,
-    #[account(signer)]
-    pub distribution_authority: AccountInfo<'info>,
-    #[account(signer)]
-    pub payer: AccountInfo<'info>,
-    #[account(mut)]
-    pub creator_watermelon: Account<'info, TokenAccount>,
-    #[account(constraint = token_program.key == &token::ID)]
-    pub token_program: AccountInfo<'info>,
-    pub clock: Sysvar<'info, Clock>,
-}
-#[derive(Accounts)]
+    // Sets the release schedule of the proceeds, see `ProceedsTranches`. It
+    // cannot change once deposits are open.
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Scheduled]
+    ))]
+    pub fn configure_tranches(
+        ctx: Context<ConfigureTranches>,
+        tranches: [Tranche; MAX_TRANCHES],
+    ) -> Result<()> {
+        if Pubkey::from_str(ALLOWED_DEPLOYER).unwrap() != *ctx.accounts.payer.key {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        let pool_account = &mut ctx.accounts.pool_account;
+        let proceeds_tranches = ProceedsTranches {
+            tranches,
+            ..ProceedsTranches::default()
+        };
+        proceeds_tranches.validate(&pool_account.schedule)?;
+        pool_account.tranches = proceeds_tranches;
+        Ok(())
+    }
+
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Scheduled]
+    ))]
+    pub fn configure_referrals(
+        ctx: Context<ConfigureReferrals>,
+        referral_bps: u16,
+        referral_watermelon: u64,
+    ) -> Result<()> {
+        if Pubkey::from_str(ALLOWED_DEPLOYER).unwrap() != *ctx.accounts.payer.to_account_info().key
+        {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        if referral_bps > 10_000 {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        let pool_account = &mut ctx.accounts.pool_account;
+        if pool_account.referral_bps != 0 || pool_account.referral_watermelon != 0 {
+            return Err(ErrorCode::ReferralsConfigured.into());
+        }
+        pool_account.referral_bps = referral_bps;
+        pool_account.referral_watermelon = referral_watermelon;
+
+        if referral_watermelon > 0 {
+            // Transfer the referral bucket from creator to pool account.
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.creator_watermelon.to_account_info(),
+                to: ctx.accounts.pool_watermelon.to_account_info(),
+                authority: ctx.accounts.payer.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.clone();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            token::transfer(cpi_ctx, referral_watermelon)?;
+        }
+
+        Ok(())
+    }
+
+    // Sets the key attesting depositors passed KYC, the default pubkey lets
+    // anyone deposit. It cannot change once deposits are open.
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Scheduled]
+    ))]
+    pub fn configure_kyc(ctx: Context<ConfigureKyc>, kyc_verifier: Pubkey) -> Result<()> {
+        if Pubkey::from_str(ALLOWED_DEPLOYER).unwrap() != *ctx.accounts.payer.key {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        ctx.accounts.pool_account.kyc_verifier = kyc_verifier;
+        Ok(())
+    }
+
+    // Restricts deposits to holders of a token or of an NFT collection, see
+    // `ParticipationGate`. It cannot change once deposits are open.
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Scheduled]
+    ))]
+    pub fn configure_gate(ctx: Context<ConfigureGate>, gate: ParticipationGate) -> Result<()> {
+        if Pubkey::from_str(ALLOWED_DEPLOYER).unwrap() != *ctx.accounts.payer.key {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        gate.validate()?;
+        ctx.accounts.pool_account.gate = gate;
+        Ok(())
+    }
+
+    // Sets the tiers of guaranteed allocations, see `StakeTiers`. They cannot
+    // change once deposits are open.
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Scheduled]
+    ))]
+    pub fn configure_stake_tiers(
+        ctx: Context<ConfigureStakeTiers>,
+        stake_tiers: StakeTiers,
+    ) -> Result<()> {
+        if Pubkey::from_str(ALLOWED_DEPLOYER).unwrap() != *ctx.accounts.payer.key {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        stake_tiers.validate(&ctx.accounts.pool_account.schedule)?;
+        // Lottery pools only take deposits from winning tickets.
+        if stake_tiers.enabled() && ctx.accounts.pool_account.lottery.enabled() {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        ctx.accounts.pool_account.stake_tiers = stake_tiers;
+        Ok(())
+    }
+
+    // Snapshots the tier the user's stake reaches into their position.
+    // Registering again before the sale starts takes a new snapshot. Relies
+    // on the staking program locking the stake, see `StakeTiers`.
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Scheduled]
+    ))]
+    pub fn register_stake(ctx: Context<RegisterStake>) -> Result<()> {
+        let stake_tiers = &ctx.accounts.pool_account.stake_tiers;
+        if !stake_tiers.enabled() {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        let stake_vault = &ctx.accounts.stake_vault;
+        let stake_authority = stake_authority_address(
+            &stake_tiers.staking_program,
+            ctx.accounts.user_authority.key,
+        );
+        if stake_vault.mint != stake_tiers.stake_mint || stake_vault.owner != stake_authority {
+            return Err(ErrorCode::InvalidStakeAccount.into());
+        }
+        let (tier, allocation) = stake_tiers
+            .tier_for(stake_vault.amount)
+            .ok_or(ErrorCode::NoStakeTier)?;
+
+        let user_position = &mut ctx.accounts.user_position;
+        user_position.pool_account = ctx.accounts.pool_account.origin;
+        user_position.owner = *ctx.accounts.user_authority.key;
+        user_position.stake_tier = tier;
+        user_position.stake_allocation = allocation;
+        Ok(())
+    }
+
+    // Turns the pool into a lottery, see `Lottery`. `commitment` is the
+    // `lottery_commitment` of a secret the authority keeps until the draw.
+    // All zero arguments turn the lottery off again.
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Scheduled]
+    ))]
+    pub fn configure_lottery(
+        ctx: Context<ConfigureLottery>,
+        ticket_usdc: u64,
+        winning_tickets: u64,
+        commitment: [u8; 32],
+    ) -> Result<()> {
+        if Pubkey::from_str(ALLOWED_DEPLOYER).unwrap() != *ctx.accounts.payer.key {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        let lottery = Lottery {
+            ticket_usdc,
+            winning_tickets,
+            commitment,
+            ..Lottery::default()
+        };
+        if lottery != Lottery::default() && ctx.accounts.pool_account.stake_tiers.enabled() {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        lottery.validate(&ctx.accounts.pool_account.schedule)?;
+        ctx.accounts.pool_account.lottery = lottery;
+        Ok(())
+    }
+
+    // Escrows the ticket price for a single ticket per user. Gated and KYC
+    // pools take the same proofs as `exchange_usdc_for_redeemable`.
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Depositing]
+    ))]
+    pub fn buy_ticket(ctx: Context<BuyTicket>) -> Result<()> {
+        let ticket_usdc = ctx.accounts.pool_account.lottery.ticket_usdc;
+        if ticket_usdc == 0 {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        if is_blocked(&ctx.accounts.blocklist_entry, ctx.program_id) {
+            return Err(ErrorCode::AddressBlocked.into());
+        }
+        if ctx.accounts.user_usdc.amount < ticket_usdc {
+            return Err(ErrorCode::LowUsdc.into());
+        }
+        let proof_accounts = ctx.accounts.pool_account.gate.kind.proof_accounts();
+        if ctx.remaining_accounts.len() < proof_accounts {
+            return Err(ErrorCode::GateNotSatisfied.into());
+        }
+        check_participation(
+            &ctx.accounts.pool_account,
+            ctx.accounts.user_authority.key,
+            &ctx.remaining_accounts[..proof_accounts],
+            &ctx.accounts.instructions,
+            0,
+            ticket_usdc,
+            ctx.accounts.clock.unix_timestamp,
+        )?;
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.user_usdc.to_account_info(),
+            to: ctx.accounts.pool_usdc.to_account_info(),
+            authority: ctx.accounts.user_authority.clone(),
+        };
+        let cpi_program = ctx.accounts.token_program.clone();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, ticket_usdc)?;
+
+        let ticket = &mut ctx.accounts.ticket;
+        ticket.pool_account = ctx.accounts.pool_account.origin;
+        ticket.owner = *ctx.accounts.user_authority.key;
+        let lottery = &mut ctx.accounts.pool_account.lottery;
+        ticket.number = lottery.tickets;
+        lottery.tickets = lottery
+            .tickets
+            .checked_add(1)
+            .ok_or(ErrorCode::InvalidParam)?;
+        lottery.escrowed_usdc = lottery
+            .escrowed_usdc
+            .checked_add(ticket_usdc)
+            .ok_or(ErrorCode::InvalidParam)?;
+        Ok(())
+    }
+
+    // Picks the slot whose hash seeds the draw, `LOTTERY_DRAW_DELAY_SLOTS`
+    // from now, once tickets are sold. It can only be picked once.
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Grace, PoolStatus::Settling]
+    ))]
+    pub fn commit_lottery_draw(ctx: Context<CommitLotteryDraw>) -> Result<()> {
+        let pool_account = &mut ctx.accounts.pool_account;
+        if !pool_account.lottery.enabled() || pool_account.lottery_draw_slot != 0 {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        pool_account.lottery_draw_slot = ctx
+            .accounts
+            .clock
+            .slot
+            .checked_add(LOTTERY_DRAW_DELAY_SLOTS)
+            .ok_or(ErrorCode::InvalidParam)?;
+        Ok(())
+    }
+
+    // Reveals the secret committed to by `configure_lottery`. The seed of
+    // the draw mixes it with the hash of the slot picked by
+    // `commit_lottery_draw`, which did not exist when the secret was
+    // committed nor when the slot was picked. The authority learns the
+    // outcome before revealing and can only withhold it: once the slot hash
+    // leaves the slot hashes sysvar the lottery cannot be drawn anymore and
+    // every ticket is refunded.
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Grace, PoolStatus::Settling]
+    ))]
+    pub fn draw_lottery(ctx: Context<DrawLottery>, secret: [u8; 32]) -> Result<()> {
+        let draw_slot = ctx.accounts.pool_account.lottery_draw_slot;
+        let lottery = &mut ctx.accounts.pool_account.lottery;
+        if !lottery.enabled() {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        if lottery.drawn {
+            return Err(ErrorCode::LotteryAlreadyDrawn.into());
+        }
+        if draw_slot == 0 {
+            return Err(ErrorCode::LotteryNotCommitted.into());
+        }
+        if lottery_commitment(&secret) != lottery.commitment {
+            return Err(ErrorCode::InvalidLotteryReveal.into());
+        }
+        let slot_hash = slot_hash(&ctx.accounts.slot_hashes, draw_slot)?;
+        lottery.seed = hashv(&[&secret, &slot_hash]).to_bytes();
+        lottery.drawn = true;
+        Ok(())
+    }
+
+    // Turns the escrow of a winning ticket into a deposit. Winners have
+    // until claims open, all of them pay the same ticket price.
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Grace, PoolStatus::Settling]
+    ))]
+    pub fn claim_ticket(ctx: Context<ClaimTicket>) -> Result<()> {
+        let lottery = ctx.accounts.pool_account.lottery;
+        if !lottery.drawn {
+            return Err(ErrorCode::LotteryNotDrawn.into());
+        }
+        if is_blocked(&ctx.accounts.blocklist_entry, ctx.program_id) {
+            return Err(ErrorCode::AddressBlocked.into());
+        }
+        if !lottery.is_winner(ctx.accounts.ticket.number) {
+            return Err(ErrorCode::LosingTicket.into());
+        }
+
+        issue_redeemable(
+            &ctx.accounts.pool_account,
+            &ctx.accounts.pool_signer,
+            &ctx.accounts.redeemable_mint,
+            &ctx.accounts.user_redeemable,
+            &ctx.accounts.user_authority,
+            &ctx.accounts.token_program,
+            lottery.ticket_usdc,
+        )?;
+        record_deposit(
+            &mut ctx.accounts.pool_account,
+            &mut ctx.accounts.user_position,
+            ctx.accounts.user_authority.key,
+            lottery.ticket_usdc,
+        )?;
+        let lottery = &mut ctx.accounts.pool_account.lottery;
+        lottery.escrowed_usdc = lottery.escrowed_usdc.saturating_sub(lottery.ticket_usdc);
+        Ok(())
+    }
+
+    // Returns the escrow of a losing ticket. Winning tickets can be refunded
+    // once they cannot be claimed anymore, every ticket can if the lottery
+    // was never drawn or the pool was cancelled.
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[
+            PoolStatus::Grace,
+            PoolStatus::Settling,
+            PoolStatus::Claimable,
+            PoolStatus::Cancelled
+        ]
+    ))]
+    pub fn refund_ticket(ctx: Context<RefundTicket>) -> Result<()> {
+        let lottery = ctx.accounts.pool_account.lottery;
+        let status = ctx
+            .accounts
+            .pool_account
+            .current_status(ctx.accounts.clock.unix_timestamp);
+        if status != PoolStatus::Cancelled && status != PoolStatus::Claimable {
+            if !lottery.drawn {
+                return Err(ErrorCode::LotteryNotDrawn.into());
+            }
+            if lottery.is_winner(ctx.accounts.ticket.number) {
+                return Err(ErrorCode::WinningTicket.into());
+            }
+        }
+
+        let seeds = &[
+            ctx.accounts.pool_account.watermelon_mint.as_ref(),
+            &[ctx.accounts.pool_account.nonce],
+        ];
+        let signer = &[&seeds[..]];
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.pool_usdc.to_account_info(),
+            to: ctx.accounts.user_usdc.to_account_info(),
+            authority: ctx.accounts.pool_signer.clone(),
+        };
+        let cpi_program = ctx.accounts.token_program.clone();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::transfer(cpi_ctx, lottery.ticket_usdc)?;
+
+        let lottery = &mut ctx.accounts.pool_account.lottery;
+        lottery.escrowed_usdc = lottery.escrowed_usdc.saturating_sub(lottery.ticket_usdc);
+        Ok(())
+    }
+
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Scheduled, PoolStatus::Depositing]
+    ))]
+    pub fn register_referrer(ctx: Context<RegisterReferrer>) -> Result<()> {
+        let referrer = &mut ctx.accounts.referrer;
+        referrer.pool_account = ctx.accounts.pool_account.origin;
+        referrer.authority = *ctx.accounts.referrer_authority.key;
+        Ok(())
+    }
+
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Claimable]
+    ))]
+    pub fn claim_referral_reward(ctx: Context<ClaimReferralReward>) -> Result<()> {
+        let referrer = &mut ctx.accounts.referrer;
+        if referrer.claimed {
+            return Err(ErrorCode::AlreadyClaimed.into());
+        }
+        referrer.claimed = true;
+
+        let pool_account = &mut ctx.accounts.pool_account;
+        let usdc_reward = pool_account.referral_usdc_reward(referrer.referred_usdc);
+        let watermelon_reward = (referrer.referred_usdc as u128)
+            .checked_mul(pool_account.referral_watermelon as u128)
+            .unwrap()
+            .checked_div(pool_account.total_referred_usdc as u128)
+            .unwrap_or(0) as u64;
+        pool_account.referral_usdc_paid = pool_account
+            .referral_usdc_paid
+            .checked_add(usdc_reward)
+            .ok_or(ErrorCode::InvalidParam)?;
+        pool_account.referral_watermelon_paid = pool_account
+            .referral_watermelon_paid
+            .checked_add(watermelon_reward)
+            .ok_or(ErrorCode::InvalidParam)?;
+
+        let seeds = &[
+            ctx.accounts.pool_account.watermelon_mint.as_ref(),
+            &[ctx.accounts.pool_account.nonce],
+        ];
+        let signer = &[&seeds[..]];
+
+        // Transfer the USDC bonus from pool account to referrer.
+        if usdc_reward > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.pool_usdc.to_account_info(),
+                to: ctx.accounts.referrer_usdc.to_account_info(),
+                authority: ctx.accounts.pool_signer.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.clone();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::transfer(cpi_ctx, usdc_reward)?;
+        }
+
+        // Transfer the referrer's share of the watermelon bucket.
+        if watermelon_reward > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.pool_watermelon.to_account_info(),
+                to: ctx.accounts.referrer_watermelon.to_account_info(),
+                authority: ctx.accounts.pool_signer.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.clone();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::transfer(cpi_ctx, watermelon_reward)?;
+        }
+
+        Ok(())
+    }
+
+    // Refunds the user's share of the cancelled tranches, pro rata to their
+    // deposit. Any redeemable tokens the user still holds, up to the refund,
+    // are burned and their watermelon goes back to the creator; tokens already
+    // redeemed or distributed do not reduce the refund. Refunds close at the
+    // claim deadline, after which the creator can sweep what is left.
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Claimable]
+    ))]
+    pub fn claim_halt_refund(ctx: Context<ClaimHaltRefund>) -> Result<()> {
+        if !ctx.accounts.pool_account.tranches.halted {
+            return Err(ErrorCode::ProceedsNotHalted.into());
+        }
+        if ctx.accounts.clock.unix_timestamp >= ctx.accounts.pool_account.claim_deadline_ts() {
+            return Err(ErrorCode::HaltRefundsClosed.into());
+        }
+        let user_position = &mut ctx.accounts.user_position;
+        if user_position.halt_refunded {
+            return Err(ErrorCode::AlreadyRefunded.into());
+        }
+        if user_position.usdc_deposited == 0 {
+            return Err(ErrorCode::NoContribution.into());
+        }
+        let refund_bps = ctx
+            .accounts
+            .pool_account
+            .tranches
+            .refund_bps(&ctx.accounts.pool_account.schedule);
+        let refund_amount =
+            ((user_position.usdc_deposited as u128) * (refund_bps as u128) / 10_000) as u64;
+        user_position.halt_refunded = true;
+        let burn_amount = refund_amount.min(ctx.accounts.user_redeemable.amount);
+        let sale_watermelon = ctx
+            .accounts
+            .pool_account
+            .sale_watermelon(ctx.accounts.pool_watermelon.amount);
+        let watermelon_amount = redemption_amount(
+            burn_amount,
+            ctx.accounts.redeemable_mint.supply,
+            sale_watermelon,
+        );
+
+        let seeds = &[
+            ctx.accounts.pool_account.watermelon_mint.as_ref(),
+            &[ctx.accounts.pool_account.nonce],
+        ];
+        let signer = &[&seeds[..]];
+
+        if burn_amount > 0 {
+            // Burn the redeemable tokens covered by the refund.
+            let cpi_accounts = Burn {
+                mint: ctx.accounts.redeemable_mint.to_account_info(),
+                to: ctx.accounts.user_redeemable.to_account_info(),
+                authority: ctx.accounts.user_authority.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.clone();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            token::burn(cpi_ctx, burn_amount)?;
+        }
+
+        if refund_amount > 0 {
+            // Transfer the user's share of the cancelled tranches back to them.
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.pool_usdc.to_account_info(),
+                to: ctx.accounts.user_usdc.to_account_info(),
+                authority: ctx.accounts.pool_signer.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.clone();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::transfer(cpi_ctx, refund_amount)?;
+        }
+
+        if watermelon_amount > 0 {
+            // The watermelon the burned tokens would have claimed was not paid
+            // for, it goes back to the creator.
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.pool_watermelon.to_account_info(),
+                to: ctx.accounts.creator_watermelon.to_account_info(),
+                authority: ctx.accounts.pool_signer.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.clone();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::transfer(cpi_ctx, watermelon_amount)?;
+        }
+
+        let stats = &mut ctx.accounts.pool_account.stats;
+        stats.usdc_withdrawn = stats
+            .usdc_withdrawn
+            .checked_add(refund_amount)
+            .ok_or(ErrorCode::InvalidParam)?;
+        stats.redeemable_burned = stats
+            .redeemable_burned
+            .checked_add(burn_amount)
+            .ok_or(ErrorCode::InvalidParam)?;
+        stats.creator_watermelon_withdrawn = stats
+            .creator_watermelon_withdrawn
+            .checked_add(watermelon_amount)
+            .ok_or(ErrorCode::InvalidParam)?;
+
+        Ok(())
+    }
+
+    // Simulation only: returns the `PayoutQuote` for redeeming `amount`
+    // redeemable tokens right now as return data, without moving any tokens.
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[
+            PoolStatus::Scheduled,
+            PoolStatus::Depositing,
+            PoolStatus::Grace,
+            PoolStatus::Settling,
+            PoolStatus::Claimable
+        ]
+    ))]
+    pub fn quote_redemption(ctx: Context<QuotePayout>, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        let redeemable_supply = ctx.accounts.redeemable_mint.supply;
+        if amount > redeemable_supply {
+            return Err(ErrorCode::LowRedeemable.into());
+        }
+        let sale_watermelon = ctx
+            .accounts
+            .pool_account
+            .sale_watermelon(ctx.accounts.pool_watermelon.amount);
+        let quote = PayoutQuote::new(amount, redeemable_supply, sale_watermelon);
+        set_return_data(&quote.try_to_vec().map_err(ProgramError::from)?);
+        Ok(())
+    }
+
+    // Simulation only: returns the `PayoutQuote` for depositing `amount` USDC
+    // now, assuming nobody else deposits afterwards.
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Scheduled, PoolStatus::Depositing]
+    ))]
+    pub fn quote_deposit(ctx: Context<QuotePayout>, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        // Redeemable tokens are minted 1:1 for USDC.
+        let redeemable_supply = ctx
+            .accounts
+            .redeemable_mint
+            .supply
+            .checked_add(amount)
+            .ok_or(ErrorCode::InvalidParam)?;
+        let sale_watermelon = ctx
+            .accounts
+            .pool_account
+            .sale_watermelon(ctx.accounts.pool_watermelon.amount);
+        let quote = PayoutQuote::new(amount, redeemable_supply, sale_watermelon);
+        set_return_data(&quote.try_to_vec().map_err(ProgramError::from)?);
+        Ok(())
+    }
+
+    pub fn advance_pool_status(ctx: Context<AdvancePoolStatus>) -> Result<()> {
+        let pool_account = &mut ctx.accounts.pool_account;
+        let status = pool_account.current_status(ctx.accounts.clock.unix_timestamp);
+        if status == pool_account.status {
+            return Err(ErrorCode::InvalidStatusTransition.into());
+        }
+        pool_account.status = status;
+        Ok(())
+    }
+
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[
+            PoolStatus::Scheduled,
+            PoolStatus::Depositing,
+            PoolStatus::Grace,
+            PoolStatus::Settling
+        ]
+    ))]
+    pub fn cancel_pool(ctx: Context<CancelPool>) -> Result<()> {
+        if Pubkey::from_str(ALLOWED_DEPLOYER).unwrap() != *ctx.accounts.payer.to_account_info().key
+        {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        ctx.accounts.pool_account.status = PoolStatus::Cancelled;
+        Ok(())
+    }
+
+    #[access_control(in_status(
+        &ctx.accounts.pool_account,
+        &ctx.accounts.clock,
+        &[PoolStatus::Claimable, PoolStatus::Cancelled]
+    ))]
+    pub fn close_pool(ctx: Context<ClosePool>) -> Result<()> {
+        if Pubkey::from_str(ALLOWED_DEPLOYER).unwrap() != *ctx.accounts.payer.to_account_info().key
+        {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        // Everybody has to be paid out before the pool can be closed.
+        if ctx.accounts.redeemable_mint.supply != 0 || ctx.accounts.pool_usdc.amount != 0 {
+            return Err(ErrorCode::InvalidStatusTransition.into());
+        }
+        ctx.accounts.pool_account.status = PoolStatus::Closed;
+        Ok(())
+    }
+
+    // Blocks `wallet` from depositing and claiming in every pool, e.g. when it
+    // lands on a sanctions list.
+    pub fn block_address(ctx: Context<BlockAddress>) -> Result<()> {
+        if Pubkey::from_str(ALLOWED_DEPLOYER).unwrap() != *ctx.accounts.payer.key {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        ctx.accounts.blocklist_entry.wallet = *ctx.accounts.wallet.key;
+        Ok(())
+    }
+
+    pub fn unblock_address(ctx: Context<UnblockAddress>) -> Result<()> {
+        if Pubkey::from_str(ALLOWED_DEPLOYER).unwrap() != *ctx.accounts.payer.key {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        Ok(())
+    }
+
+    pub fn migrate_pool(ctx: Context<MigratePool>) -> Result<()> {
+        if Pubkey::from_str(ALLOWED_DEPLOYER).unwrap() != *ctx.accounts.payer.key {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        let pool_info = &ctx.accounts.pool_account;
+        if pool_info.owner != ctx.program_id {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        let mut pool_account =
+            PoolAccount::try_deserialize_any_version(&pool_info.try_borrow_data()?)?;
+        if pool_account.distribution_authority != *ctx.accounts.distribution_authority.key {
+            return Err(
+                ProgramError::from(anchor_lang::__private::ErrorCode::ConstraintHasOne).into(),
+            );
+        }
+        if pool_account.version >= POOL_ACCOUNT_VERSION {
+            return Err(ErrorCode::PoolUpToDate.into());
+        }
+        let accounts = &ctx.accounts;
+        if pool_account.watermelon_mint != *accounts.watermelon_mint.to_account_info().key
+            || pool_account.pool_watermelon != *accounts.current_pool_watermelon.key
+            || pool_account.pool_usdc != *accounts.current_pool_usdc.key
+        {
+            return Err(
+                ProgramError::from(anchor_lang::__private::ErrorCode::ConstraintHasOne).into(),
+            );
+        }
+        let expected_signer = Pubkey::create_program_address(
+            &[pool_account.watermelon_mint.as_ref(), &[pool_account.nonce]],
+            ctx.program_id,
+        )
+        .map_err(|_| ErrorCode::InvalidNonce)?;
+        if *accounts.pool_signer.key != expected_signer {
+            return Err(ErrorCode::InvalidNonce.into());
+        }
+        // Pools that predate `origin` were created where they are.
+        if pool_account.origin == Pubkey::default() {
+            pool_account.origin = *pool_info.key;
+        }
+        if pool_account.origin != *accounts.origin.key {
+            return Err(
+                ProgramError::from(anchor_lang::__private::ErrorCode::ConstraintSeeds).into(),
+            );
+        }
+
+        // Withdrawals only go to the proceeds accounts. Pools created before
+        // they were recorded get them now rather than through the timelocked
+        // `propose_proceeds_accounts`, which would hold up the creator.
+        for (recorded, account) in [
+            (&mut pool_account.creator_usdc, &accounts.creator_usdc),
+            (
+                &mut pool_account.creator_watermelon,
+                &accounts.creator_watermelon,
+            ),
+        ] {
+            let key = *account.to_account_info().key;
+            if *recorded == Pubkey::default() {
+                *recorded = key;
+            } else if *recorded != key {
+                return Err(ProgramError::from(
+                    anchor_lang::__private::ErrorCode::ConstraintHasOne,
+                )
+                .into());
+            }
+        }
+
+        // Pools created before versioning hold their funds in whatever token
+        // accounts the creator passed in. Every other instruction finds the
+        // vaults by seeds, so the balances move there and the old accounts
+        // are closed.
+        let seeds = &[pool_account.watermelon_mint.as_ref(), &[pool_account.nonce]];
+        let signer = &[&seeds[..]];
+        let usdc_balance = accounts.pool_usdc.amount.saturating_add(move_to_vault(
+            accounts,
+            &accounts.current_pool_usdc,
+            &accounts.pool_usdc,
+            signer,
+        )?);
+        move_to_vault(
+            accounts,
+            &accounts.current_pool_watermelon,
+            &accounts.pool_watermelon,
+            signer,
+        )?;
+        pool_account.pool_usdc = *accounts.pool_usdc.to_account_info().key;
+        pool_account.pool_watermelon = *accounts.pool_watermelon.to_account_info().key;
+
+        // The first release kept neither the deposit total nor the original
+        // claim date. The USDC in the pool stands in for the former, so the
+        // proceeds can be withdrawn, and the current claim date for the
+        // latter. Other fields added since then start out unset.
+        if pool_account.version == 0 {
+            if pool_account.total_usdc_deposited == 0 {
+                pool_account.total_usdc_deposited = usdc_balance;
+            }
+            if pool_account.original_withdraw_melon_ts == 0 {
+                pool_account.original_withdraw_melon_ts = pool_account.schedule.withdraw_melon_ts;
+            }
+        }
+        pool_account.version = POOL_ACCOUNT_VERSION;
+
+        // Programs cannot resize their accounts. A pool on a layout shorter
+        // than the current one moves to a new account derived from its
+        // origin, the old one is closed and its rent goes to the payer. Every
+        // other account of the pool is derived from the origin and stays.
+        let space = PoolAccount::space();
+        let migrated_info = &accounts.migrated_pool;
+        if pool_info.data_len() < space {
+            let (address, bump) = Pubkey::find_program_address(
+                &[b"pool".as_ref(), pool_account.origin.as_ref()],
+                ctx.program_id,
+            );
+            if *migrated_info.key != address {
+                return Err(
+                    ProgramError::from(anchor_lang::__private::ErrorCode::ConstraintSeeds).into(),
+                );
+            }
+            create_derived_account(
+                &accounts.payer,
+                migrated_info,
+                &accounts.system_program.to_account_info(),
+                &accounts.rent,
+                space,
+                &[b"pool".as_ref(), pool_account.origin.as_ref(), &[bump]],
+                ctx.program_id,
+            )?;
+            close_account_info(pool_info, &accounts.payer)?;
+        } else if migrated_info.key != pool_info.key {
+            return Err(ErrorCode::InvalidParam.into());
+        }
+        let mut data = migrated_info.try_borrow_mut_data()?;
+        pool_account.try_serialize(&mut &mut data[..])?;
+        Ok(())
+    }
+
+    // Brings a position created before the stake tier snapshot to the
+    // current layout. Programs cannot resize their accounts and a closed
+    // account can only be recreated in a later transaction, so this takes
+    // two calls: the first copies the position into a stash and closes it,
+    // the second recreates the position from the stash. A deposit made in
+    // between starts a new position, the stash is then added to it. Anyone
+    // can pay for it, the new fields start out unregistered.
+    pub fn migrate_position(ctx: Context<MigratePosition>) -> Result<()> {
+        let accounts = &ctx.accounts;
+        let position_info = &accounts.user_position;
+        let stash_info = &accounts.position_stash;
+        let (stash_address, stash_bump) = Pubkey::find_program_address(
+            &[b"position_stash".as_ref(), position_info.key.as_ref()],
+            ctx.program_id,
+        );
+        if *stash_info.key != stash_address {
+            return Err(
+                ProgramError::from(anchor_lang::__private::ErrorCode::ConstraintSeeds).into(),
+            );
+        }
+        let space = UserPosition::space();
+
+        if position_info.owner == ctx.program_id && position_info.data_len() < space {
+            let mut padded = position_info.try_borrow_data()?.to_vec();
+            padded.resize(space, 0);
+            UserPosition::try_deserialize(&mut &padded[..])?;
+            create_derived_account(
+                &accounts.payer,
+                stash_info,
+                &accounts.system_program.to_account_info(),
+                &accounts.rent,
+                space,
+                &[
+                    b"position_stash".as_ref(),
+                    position_info.key.as_ref(),
+                    &[stash_bump],
+                ],
+                ctx.program_id,
+            )?;
+            stash_info.try_borrow_mut_data()?.copy_from_slice(&padded);
+            return close_account_info(position_info, &accounts.payer).map_err(Into::into);
+        }
+
+        if stash_info.owner != ctx.program_id || stash_info.data_is_empty() {
+            return Err(ErrorCode::PositionUpToDate.into());
+        }
+        let stashed = UserPosition::try_deserialize(&mut &stash_info.try_borrow_data()?[..])?;
+        let (address, bump) = Pubkey::find_program_address(
+            &[
+                b"position".as_ref(),
+                stashed.pool_account.as_ref(),
+                stashed.owner.as_ref(),
+            ],
+            ctx.program_id,
+        );
+        if *position_info.key != address {
+            return Err(
+                ProgramError::from(anchor_lang::__private::ErrorCode::ConstraintSeeds).into(),
+            );
+        }
+        let position = if position_info.owner == ctx.program_id {
+            let mut position =
+                UserPosition::try_deserialize(&mut &position_info.try_borrow_data()?[..])?;
+            position.usdc_deposited = position
+                .usdc_deposited
+                .checked_add(stashed.usdc_deposited)
+                .ok_or(ErrorCode::InvalidParam)?;
+            position.halt_votes |= stashed.halt_votes;
+            position.halt_refunded |= stashed.halt_refunded;
+            position
+        } else {
+            create_derived_account(
+                &accounts.payer,
+                position_info,
+                &accounts.system_program.to_account_info(),
+                &accounts.rent,
+                space,
+                &[
+                    b"position".as_ref(),
+                    stashed.pool_account.as_ref(),
+                    stashed.owner.as_ref(),
+                    &[bump],
+                ],
+                ctx.program_id,
+            )?;
+            stashed
+        };
+        position.try_serialize(&mut &mut position_info.try_borrow_mut_data()?[..])?;
+        close_account_info(stash_info, &accounts.payer)?;
+        Ok(())
+    }
+}
+
+// Same as the generated `entry`, but also available with `no-entrypoint` so
+// the program can be run in-process from crates depending on it as a library.
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    if data.len() < 8 {
+        return Err(anchor_lang::__private::ErrorCode::InstructionMissing.into());
+    }
+    dispatch(program_id, accounts, data)
+}
+
+#[derive(Accounts)]
+pub struct InitializePool<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = PoolAccount::space()
+    )]
+    pub pool_account: Box<Account<'info, PoolAccount>>,
+    pub pool_signer: AccountInfo<'info>,
+    pub usdc_mint: Box<Account<'info, Mint>>,
+    #[account(
+        init,
+        seeds = [b"redeemable_mint".as_ref(), pool_account.to_account_info().key.as_ref()],
+        bump,
+        payer = payer,
+        mint::decimals = usdc_mint.decimals,
+        mint::authority = pool_signer
+    )]
+    pub redeemable_mint: Box<Account<'info, Mint>>,
+    // Checked by the token metadata program against its own PDA.
+    #[account(mut)]
+    pub redeemable_metadata: AccountInfo<'info>,
+    pub watermelon_mint: Box<Account<'info, Mint>>,
+    #[account(
+        init,
+        seeds = [b"pool_watermelon".as_ref(), pool_account.to_account_info().key.as_ref()],
+        bump,
+        payer = payer,
+        token::mint = watermelon_mint,
+        token::authority = pool_signer
+    )]
+    pub pool_watermelon: Box<Account<'info, TokenAccount>>,
+    #[account(
+        init,
+        seeds = [b"pool_usdc".as_ref(), pool_account.to_account_info().key.as_ref()],
+        bump,
+        payer = payer,
+        token::mint = usdc_mint,
+        token::authority = pool_signer
+    )]
+    pub pool_usdc: Box<Account<'info, TokenAccount>>,
+    #[account(signer)]
+    pub distribution_authority: AccountInfo<'info>,
+    #[account(mut, signer)]
+    pub payer: AccountInfo<'info>,
+    #[account(mut)]
+    pub creator_watermelon: Box<Account<'info, TokenAccount>>,
+    // Receives the raise, see `PoolAccount::creator_usdc`.
+    #[account(constraint = creator_usdc.mint == *usdc_mint.to_account_info().key)]
+    pub creator_usdc: Box<Account<'info, TokenAccount>>,
+    #[account(constraint = token_program.key == &token::ID)]
+    pub token_program: AccountInfo<'info>,
+    #[account(constraint = token_metadata_program.key == &spl_token_metadata::ID)]
+    pub token_metadata_program: AccountInfo<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub clock: Sysvar<'info, Clock>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> InitializePool<'info> {
+    fn accounts(ctx: &Context<InitializePool<'info>>, nonce: u8) -> Result<()> {
+        let expected_signer = Pubkey::create_program_address(
+            &[ctx.accounts.pool_watermelon.mint.as_ref(), &[nonce]],
+            ctx.program_id,
+        )
+        .map_err(|_| ErrorCode::InvalidNonce)?;
+        if ctx.accounts.pool_signer.key != &expected_signer {
+            return Err(ErrorCode::InvalidNonce.into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct ExchangeUsdcForRedeemable<'info> {
+    #[account(mut, has_one = redeemable_mint, has_one = pool_usdc)]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(seeds = [pool_account.watermelon_mint.as_ref()], bump = pool_account.nonce)]
+    pool_signer: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = redeemable_mint.mint_authority == COption::Some(*pool_signer.key)
+    )]
+    pub redeemable_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"pool_usdc".as_ref(), pool_account.origin.as_ref()],
+        bump
+    )]
+    pub pool_usdc: Account<'info, TokenAccount>,
+    #[account(mut, signer)]
+    pub user_authority: AccountInfo<'info>,
+    #[account(mut, constraint = user_usdc.owner == *user_authority.key)]
+    pub user_usdc: Account<'info, TokenAccount>,
+    // Created as the user's associated token account if missing. `init_if_needed`
+    // skips every check on existing accounts, hence the explicit constraints.
+    #[account(
+        init_if_needed,
+        payer = user_authority,
+        associated_token::mint = redeemable_mint,
+        associated_token::authority = user_authority,
+        constraint = user_redeemable.mint == *redeemable_mint.to_account_info().key,
+        constraint = user_redeemable.owner == *user_authority.key
+    )]
+    pub user_redeemable: Account<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        seeds = [
+            b"position".as_ref(),
+            pool_account.origin.as_ref(),
+            user_authority.key.as_ref()
+        ],
+        bump,
+        payer = user_authority,
+        space = UserPosition::space()
+    )]
+    pub user_position: Account<'info, UserPosition>,
+    #[account(constraint = token_program.key == &token::ID)]
+    pub token_program: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+    // Holds the KYC attestation of pools with a verifier.
+    #[account(constraint = instructions.key == &sysvar_instructions::ID)]
+    pub instructions: AccountInfo<'info>,
+    // Only exists if the user is blocked, see `block_address`.
+    #[account(seeds = [b"blocklist".as_ref(), user_authority.key.as_ref()], bump)]
+    pub blocklist_entry: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExchangeRedeemableForUsdc<'info> {
+    #[account(mut, has_one = redeemable_mint, has_one = pool_usdc)]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(seeds = [pool_account.watermelon_mint.as_ref()], bump = pool_account.nonce)]
+    pool_signer: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = redeemable_mint.mint_authority == COption::Some(*pool_signer.key)
+    )]
+    pub redeemable_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"pool_usdc".as_ref(), pool_account.origin.as_ref()],
+        bump
+    )]
+    pub pool_usdc: Account<'info, TokenAccount>,
+    #[account(signer)]
+    pub user_authority: AccountInfo<'info>,
+    #[account(mut, constraint = user_usdc.owner == *user_authority.key)]
+    pub user_usdc: Account<'info, TokenAccount>,
+    #[account(mut, constraint = user_redeemable.owner == *user_authority.key)]
+    pub user_redeemable: Account<'info, TokenAccount>,
+    #[account(constraint = token_program.key == &token::ID)]
+    pub token_program: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct ExchangeRedeemableForWatermelon<'info> {
+    #[account(
+        mut,
+        has_one = redeemable_mint, has_one = pool_watermelon, has_one = watermelon_mint)]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(seeds = [pool_account.watermelon_mint.as_ref()], bump = pool_account.nonce)]
+    pool_signer: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = redeemable_mint.mint_authority == COption::Some(*pool_signer.key)
+    )]
+    pub redeemable_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"pool_watermelon".as_ref(), pool_account.origin.as_ref()],
+        bump
+    )]
+    pub pool_watermelon: Account<'info, TokenAccount>,
+    pub watermelon_mint: Account<'info, Mint>,
+    #[account(mut, signer)]
+    pub user_authority: AccountInfo<'info>,
+    // Created as the user's associated token account if missing, see
+    // `ExchangeUsdcForRedeemable::user_redeemable`.
+    #[account(
+        init_if_needed,
+        payer = user_authority,
+        associated_token::mint = watermelon_mint,
+        associated_token::authority = user_authority,
+        constraint = user_watermelon.mint == *watermelon_mint.to_account_info().key,
+        constraint = user_watermelon.owner == *user_authority.key
+    )]
+    pub user_watermelon: Account<'info, TokenAccount>,
+    #[account(mut, constraint = user_redeemable.owner == *user_authority.key)]
+    pub user_redeemable: Account<'info, TokenAccount>,
+    #[account(constraint = token_program.key == &token::ID)]
+    pub token_program: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+    // Only exists if the user is blocked, see `block_address`.
+    #[account(seeds = [b"blocklist".as_ref(), user_authority.key.as_ref()], bump)]
+    pub blocklist_entry: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DistributeWatermelon<'info> {
+    #[account(mut, has_one = redeemable_mint, has_one = pool_watermelon)]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(seeds = [pool_account.watermelon_mint.as_ref()], bump = pool_account.nonce)]
+    pub pool_signer: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = redeemable_mint.mint_authority == COption::Some(*pool_signer.key)
+    )]
+    pub redeemable_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"pool_watermelon".as_ref(), pool_account.origin.as_ref()],
+        bump
+    )]
+    pub pool_watermelon: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub cranker_watermelon: Account<'info, TokenAccount>,
+    #[account(constraint = token_program.key == &token::ID)]
+    pub token_program: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawPoolUsdc<'info> {
+    #[account(
+        mut,
+        has_one = pool_usdc,
+        has_one = distribution_authority,
+        has_one = creator_usdc
+    )]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(seeds = [pool_account.watermelon_mint.as_ref()], bump = pool_account.nonce)]
+    pub pool_signer: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"pool_usdc".as_ref(), pool_account.origin.as_ref()],
+        bump
+    )]
+    pub pool_usdc: Account<'info, TokenAccount>,
+    #[account(signer)]
+    pub distribution_authority: AccountInfo<'info>,
+    #[account(signer)]
+    pub payer: AccountInfo<'info>,
+    #[account(mut)]
+    pub creator_usdc: Account<'info, TokenAccount>,
+    #[account(constraint = token_program.key == &token::ID)]
+    pub token_program: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawPoolWatermelon<'info> {
+    #[account(
+        mut,
+        has_one = redeemable_mint,
+        has_one = pool_watermelon,
+        has_one = distribution_authority,
+        has_one = creator_watermelon
+    )]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(seeds = [pool_account.watermelon_mint.as_ref()], bump = pool_account.nonce)]
+    pub pool_signer: AccountInfo<'info>,
+    pub redeemable_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"pool_watermelon".as_ref(), pool_account.origin.as_ref()],
+        bump
+    )]
+    pub pool_watermelon: Account<'info, TokenAccount>,
+    #[account(signer)]
+    pub distribution_authority: AccountInfo<'info>,
+    #[account(signer)]
+    pub payer: AccountInfo<'info>,
+    #[account(mut)]
+    pub creator_watermelon: Account<'info, TokenAccount>,
+    #[account(constraint = token_program.key == &token::ID)]
+    pub token_program: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeProceedsAccounts<'info> {
+    #[account(has_one = pool_usdc, has_one = distribution_authority)]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(
+        init_if_needed,
+        seeds = [b"proceeds_change".as_ref(), pool_account.origin.as_ref()],
+        bump,
+        payer = payer,
+        space = 8 + 32 + 32 + 32 + 8
+    )]
+    pub proceeds_change: Account<'info, ProceedsChange>,
+    pub pool_usdc: Account<'info, TokenAccount>,
+    #[account(constraint = creator_usdc.mint == pool_usdc.mint)]
+    pub creator_usdc: Account<'info, TokenAccount>,
+    #[account(constraint = creator_watermelon.mint == pool_account.watermelon_mint)]
+    pub creator_watermelon: Account<'info, TokenAccount>,
+    #[account(signer)]
+    pub distribution_authority: AccountInfo<'info>,
+    #[account(mut, signer)]
+    pub payer: AccountInfo<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub clock: Sysvar<'info, Clock>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApplyProceedsAccounts<'info> {
+    #[account(mut, has_one = distribution_authority)]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(
+        mut,
+        close = payer,
+        seeds = [b"proceeds_change".as_ref(), pool_account.origin.as_ref()],
+        bump
+    )]
+    pub proceeds_change: Account<'info, ProceedsChange>,
+    #[account(signer)]
+    pub distribution_authority: AccountInfo<'info>,
+    #[account(mut, signer)]
+    pub payer: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct CancelProceedsAccounts<'info> {
+    #[account(has_one = distribution_authority)]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(
+        mut,
+        close = payer,
+        seeds = [b"proceeds_change".as_ref(), pool_account.origin.as_ref()],
+        bump
+    )]
+    pub proceeds_change: Account<'info, ProceedsChange>,
+    #[account(signer)]
+    pub distribution_authority: AccountInfo<'info>,
+    #[account(mut, signer)]
+    pub payer: AccountInfo<'info>,
+}
+#[derive(Accounts)]
 pub struct ModifyIdoTime<'info> {
     #[account(mut, has_one = distribution_authority)]
     pub pool_account: Account<'info, PoolAccount>,
     #[account(signer)]
     pub distribution_authority: AccountInfo<'info>,
     #[account(signer)]
-    pub payer: AccountInfo<'info>,
+    pub payer: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(tranche: u8)]
+pub struct VoteHalt<'info> {
+    #[account(mut)]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(
+        init_if_needed,
+        seeds = [
+            b"halt_vote".as_ref(),
+            pool_account.origin.as_ref(),
+            &[tranche]
+        ],
+        bump,
+        payer = user_authority,
+        space = 8 + 32 + 1 + 8
+    )]
+    pub halt_vote: Account<'info, HaltVote>,
+    #[account(
+        mut,
+        seeds = [
+            b"position".as_ref(),
+            pool_account.origin.as_ref(),
+            user_authority.key.as_ref()
+        ],
+        bump
+    )]
+    pub user_position: Account<'info, UserPosition>,
+    #[account(mut, signer)]
+    pub user_authority: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigureTranches<'info> {
+    #[account(mut, has_one = distribution_authority)]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(signer)]
+    pub distribution_authority: AccountInfo<'info>,
+    #[account(signer)]
+    pub payer: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimHaltRefund<'info> {
+    #[account(
+        mut,
+        has_one = redeemable_mint,
+        has_one = pool_usdc,
+        has_one = pool_watermelon,
+        has_one = creator_watermelon
+    )]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(seeds = [pool_account.watermelon_mint.as_ref()], bump = pool_account.nonce)]
+    pub pool_signer: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"position".as_ref(),
+            pool_account.origin.as_ref(),
+            user_authority.key.as_ref()
+        ],
+        bump
+    )]
+    pub user_position: Account<'info, UserPosition>,
+    #[account(mut)]
+    pub redeemable_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"pool_usdc".as_ref(), pool_account.origin.as_ref()],
+        bump
+    )]
+    pub pool_usdc: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"pool_watermelon".as_ref(), pool_account.origin.as_ref()],
+        bump
+    )]
+    pub pool_watermelon: Account<'info, TokenAccount>,
+    #[account(signer)]
+    pub user_authority: AccountInfo<'info>,
+    #[account(mut, constraint = user_usdc.owner == *user_authority.key)]
+    pub user_usdc: Account<'info, TokenAccount>,
+    #[account(mut, constraint = user_redeemable.owner == *user_authority.key)]
+    pub user_redeemable: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub creator_watermelon: Account<'info, TokenAccount>,
+    #[account(constraint = token_program.key == &token::ID)]
+    pub token_program: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct QuotePayout<'info> {
+    #[account(has_one = redeemable_mint, has_one = pool_watermelon)]
+    pub pool_account: Account<'info, PoolAccount>,
+    pub redeemable_mint: Account<'info, Mint>,
+    #[account(
+        seeds = [b"pool_watermelon".as_ref(), pool_account.origin.as_ref()],
+        bump
+    )]
+    pub pool_watermelon: Account<'info, TokenAccount>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct AdvancePoolStatus<'info> {
+    #[account(mut)]
+    pub pool_account: Account<'info, PoolAccount>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct CancelPool<'info> {
+    #[account(mut, has_one = distribution_authority)]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(signer)]
+    pub distribution_authority: AccountInfo<'info>,
+    #[account(signer)]
+    pub payer: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct ClosePool<'info> {
+    #[account(
+        mut,
+        has_one = distribution_authority,
+        has_one = redeemable_mint,
+        has_one = pool_usdc
+    )]
+    pub pool_account: Account<'info, PoolAccount>,
+    pub redeemable_mint: Account<'info, Mint>,
+    #[account(
+        seeds = [b"pool_usdc".as_ref(), pool_account.origin.as_ref()],
+        bump
+    )]
+    pub pool_usdc: Account<'info, TokenAccount>,
+    #[account(signer)]
+    pub distribution_authority: AccountInfo<'info>,
+    #[account(signer)]
+    pub payer: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct MigratePool<'info> {
+    // Pools on older layouts cannot be deserialized as `PoolAccount`, the
+    // account is checked by the handler.
+    #[account(mut)]
+    pub pool_account: AccountInfo<'info>,
+    // Address the pool was created at, the pool account itself unless it
+    // was moved before. Checked by the handler.
+    pub origin: AccountInfo<'info>,
+    // Where the migrated pool is written: the pool account if it is large
+    // enough for the current layout, the account derived from `origin`
+    // otherwise. Checked by the handler.
+    #[account(mut)]
+    pub migrated_pool: AccountInfo<'info>,
+    // Checked by the handler against the pool's nonce.
+    pub pool_signer: AccountInfo<'info>,
+    pub usdc_mint: Box<Account<'info, Mint>>,
+    pub watermelon_mint: Box<Account<'info, Mint>>,
+    // Pools created before the vaults were derived from the pool address get
+    // them here, see `migrate_pool`.
+    #[account(
+        init_if_needed,
+        seeds = [b"pool_watermelon".as_ref(), origin.key.as_ref()],
+        bump,
+        payer = payer,
+        token::mint = watermelon_mint,
+        token::authority = pool_signer,
+        constraint = pool_watermelon.mint == *watermelon_mint.to_account_info().key
+    )]
+    pub pool_watermelon: Box<Account<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        seeds = [b"pool_usdc".as_ref(), origin.key.as_ref()],
+        bump,
+        payer = payer,
+        token::mint = usdc_mint,
+        token::authority = pool_signer,
+        constraint = pool_usdc.mint == *usdc_mint.to_account_info().key
+    )]
+    pub pool_usdc: Box<Account<'info, TokenAccount>>,
+    // The vaults the pool currently records, the same accounts as
+    // `pool_watermelon` and `pool_usdc` unless the pool predates them.
+    #[account(mut)]
+    pub current_pool_watermelon: AccountInfo<'info>,
+    #[account(mut)]
+    pub current_pool_usdc: AccountInfo<'info>,
+    // Recorded as the proceeds accounts if the pool predates them, must be
+    // the recorded ones otherwise.
+    #[account(constraint = creator_usdc.mint == *usdc_mint.to_account_info().key)]
+    pub creator_usdc: Box<Account<'info, TokenAccount>>,
+    #[account(constraint = creator_watermelon.mint == *watermelon_mint.to_account_info().key)]
+    pub creator_watermelon: Box<Account<'info, TokenAccount>>,
+    #[account(signer)]
+    pub distribution_authority: AccountInfo<'info>,
+    #[account(mut, signer)]
+    pub payer: AccountInfo<'info>,
+    #[account(constraint = token_program.key == &token::ID)]
+    pub token_program: AccountInfo<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigratePosition<'info> {
+    // Positions on older layouts cannot be deserialized as `UserPosition`,
+    // the account is checked by the handler.
+    #[account(mut)]
+    pub user_position: AccountInfo<'info>,
+    // Holds the position between the two steps of `migrate_position`,
+    // derived from its address. Checked by the handler.
+    #[account(mut)]
+    pub position_stash: AccountInfo<'info>,
+    #[account(mut, signer)]
+    pub payer: AccountInfo<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigureStakeTiers<'info> {
+    #[account(mut, has_one = distribution_authority)]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(signer)]
+    pub distribution_authority: AccountInfo<'info>,
+    #[account(signer)]
+    pub payer: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterStake<'info> {
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(mut, signer)]
+    pub user_authority: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        seeds = [
+            b"position".as_ref(),
+            pool_account.origin.as_ref(),
+            user_authority.key.as_ref()
+        ],
+        bump,
+        payer = user_authority,
+        space = UserPosition::space()
+    )]
+    pub user_position: Account<'info, UserPosition>,
+    // Token account holding the user's stake, owned by the stake authority
+    // PDA of the staking program, see `stake_authority_address`.
+    pub stake_vault: Account<'info, TokenAccount>,
+    pub clock: Sysvar<'info, Clock>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigureLottery<'info> {
+    #[account(mut, has_one = distribution_authority)]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(signer)]
+    pub distribution_authority: AccountInfo<'info>,
+    #[account(signer)]
+    pub payer: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct BuyTicket<'info> {
+    #[account(mut, has_one = pool_usdc)]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(
+        mut,
+        seeds = [b"pool_usdc".as_ref(), pool_account.origin.as_ref()],
+        bump
+    )]
+    pub pool_usdc: Account<'info, TokenAccount>,
+    #[account(mut, signer)]
+    pub user_authority: AccountInfo<'info>,
+    #[account(mut, constraint = user_usdc.owner == *user_authority.key)]
+    pub user_usdc: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        seeds = [
+            b"ticket".as_ref(),
+            pool_account.origin.as_ref(),
+            user_authority.key.as_ref()
+        ],
+        bump,
+        payer = user_authority,
+        space = 8 + 32 + 32 + 8
+    )]
+    pub ticket: Account<'info, LotteryTicket>,
+    #[account(constraint = token_program.key == &token::ID)]
+    pub token_program: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+    pub system_program: Program<'info, System>,
+    // Holds the KYC attestation of pools with a verifier.
+    #[account(constraint = instructions.key == &sysvar_instructions::ID)]
+    pub instructions: AccountInfo<'info>,
+    #[account(seeds = [b"blocklist".as_ref(), user_authority.key.as_ref()], bump)]
+    pub blocklist_entry: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CommitLotteryDraw<'info> {
+    #[account(mut, has_one = distribution_authority)]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(signer)]
+    pub distribution_authority: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct DrawLottery<'info> {
+    #[account(mut, has_one = distribution_authority)]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(signer)]
+    pub distribution_authority: AccountInfo<'info>,
+    #[account(constraint = slot_hashes.key == &slot_hashes::ID)]
+    pub slot_hashes: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimTicket<'info> {
+    #[account(mut, has_one = redeemable_mint)]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(seeds = [pool_account.watermelon_mint.as_ref()], bump = pool_account.nonce)]
+    pool_signer: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = redeemable_mint.mint_authority == COption::Some(*pool_signer.key)
+    )]
+    pub redeemable_mint: Account<'info, Mint>,
+    #[account(mut, signer)]
+    pub user_authority: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = user_authority,
+        associated_token::mint = redeemable_mint,
+        associated_token::authority = user_authority,
+        constraint = user_redeemable.mint == *redeemable_mint.to_account_info().key,
+        constraint = user_redeemable.owner == *user_authority.key
+    )]
+    pub user_redeemable: Account<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        seeds = [
+            b"position".as_ref(),
+            pool_account.origin.as_ref(),
+            user_authority.key.as_ref()
+        ],
+        bump,
+        payer = user_authority,
+        space = UserPosition::space()
+    )]
+    pub user_position: Account<'info, UserPosition>,
+    #[account(
+        mut,
+        close = user_authority,
+        seeds = [
+            b"ticket".as_ref(),
+            pool_account.origin.as_ref(),
+            user_authority.key.as_ref()
+        ],
+        bump
+    )]
+    pub ticket: Account<'info, LotteryTicket>,
+    #[account(constraint = token_program.key == &token::ID)]
+    pub token_program: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+    #[account(seeds = [b"blocklist".as_ref(), user_authority.key.as_ref()], bump)]
+    pub blocklist_entry: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RefundTicket<'info> {
+    #[account(mut, has_one = pool_usdc)]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(seeds = [pool_account.watermelon_mint.as_ref()], bump = pool_account.nonce)]
+    pool_signer: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"pool_usdc".as_ref(), pool_account.origin.as_ref()],
+        bump
+    )]
+    pub pool_usdc: Account<'info, TokenAccount>,
+    #[account(mut, signer)]
+    pub user_authority: AccountInfo<'info>,
+    #[account(mut, constraint = user_usdc.owner == *user_authority.key)]
+    pub user_usdc: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        close = user_authority,
+        seeds = [
+            b"ticket".as_ref(),
+            pool_account.origin.as_ref(),
+            user_authority.key.as_ref()
+        ],
+        bump
+    )]
+    pub ticket: Account<'info, LotteryTicket>,
+    #[account(constraint = token_program.key == &token::ID)]
+    pub token_program: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigureGate<'info> {
+    #[account(mut, has_one = distribution_authority)]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(signer)]
+    pub distribution_authority: AccountInfo<'info>,
+    #[account(signer)]
+    pub payer: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct BlockAddress<'info> {
+    #[account(
+        init,
+        seeds = [b"blocklist".as_ref(), wallet.key.as_ref()],
+        bump,
+        payer = payer,
+        space = 8 + 32
+    )]
+    pub blocklist_entry: Account<'info, BlocklistEntry>,
+    pub wallet: AccountInfo<'info>,
+    #[account(mut, signer)]
+    pub payer: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnblockAddress<'info> {
+    #[account(
+        mut,
+        close = payer,
+        seeds = [b"blocklist".as_ref(), wallet.key.as_ref()],
+        bump
+    )]
+    pub blocklist_entry: Account<'info, BlocklistEntry>,
+    pub wallet: AccountInfo<'info>,
+    #[account(mut, signer)]
+    pub payer: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigureReferrals<'info> {
+    #[account(mut, has_one = distribution_authority, has_one = pool_watermelon)]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(
+        mut,
+        seeds = [b"pool_watermelon".as_ref(), pool_account.origin.as_ref()],
+        bump
+    )]
+    pub pool_watermelon: Account<'info, TokenAccount>,
+    #[account(signer)]
+    pub distribution_authority: AccountInfo<'info>,
+    #[account(signer)]
+    pub payer: AccountInfo<'info>,
+    #[account(mut)]
+    pub creator_watermelon: Account<'info, TokenAccount>,
+    #[account(constraint = token_program.key == &token::ID)]
+    pub token_program: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigureKyc<'info> {
+    #[account(mut, has_one = distribution_authority)]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(signer)]
+    pub distribution_authority: AccountInfo<'info>,
+    #[account(signer)]
+    pub payer: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterReferrer<'info> {
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(
+        init,
+        seeds = [
+            b"referrer".as_ref(),
+            pool_account.origin.as_ref(),
+            referrer_authority.key.as_ref()
+        ],
+        bump,
+        payer = referrer_authority,
+        space = 8 + 32 + 32 + 8 + 1
+    )]
+    pub referrer: Account<'info, Referrer>,
+    #[account(mut, signer)]
+    pub referrer_authority: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimReferralReward<'info> {
+    #[account(mut, has_one = pool_usdc, has_one = pool_watermelon)]
+    pub pool_account: Account<'info, PoolAccount>,
+    #[account(seeds = [pool_account.watermelon_mint.as_ref()], bump = pool_account.nonce)]
+    pub pool_signer: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"referrer".as_ref(),
+            pool_account.origin.as_ref(),
+            referrer_authority.key.as_ref()
+        ],
+        bump
+    )]
+    pub referrer: Account<'info, Referrer>,
+    #[account(signer)]
+    pub referrer_authority: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"pool_usdc".as_ref(), pool_account.origin.as_ref()],
+        bump
+    )]
+    pub pool_usdc: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"pool_watermelon".as_ref(), pool_account.origin.as_ref()],
+        bump
+    )]
+    pub pool_watermelon: Account<'info, TokenAccount>,
+    #[account(mut, constraint = referrer_usdc.owner == *referrer_authority.key)]
+    pub referrer_usdc: Account<'info, TokenAccount>,
+    #[account(mut, constraint = referrer_watermelon.owner == *referrer_authority.key)]
+    pub referrer_watermelon: Account<'info, TokenAccount>,
+    #[account(constraint = token_program.key == &token::ID)]
+    pub token_program: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
 }
 
 #[account]
+#[derive(Default)]
 pub struct PoolAccount {
     pub redeemable_mint: Pubkey,
     pub pool_watermelon: Pubkey,
@@ -480,12 +2562,710 @@ pub struct PoolAccount {
     pub distribution_authority: Pubkey,
     pub nonce: u8,
     pub num_ido_tokens: u64,
+    pub schedule: PoolSchedule,
+    pub status: PoolStatus,
+    pub total_usdc_deposited: u64,
+    pub referral_bps: u16,
+    pub referral_watermelon: u64,
+    pub total_referred_usdc: u64,
+    pub referral_usdc_paid: u64,
+    pub referral_watermelon_paid: u64,
+    pub original_withdraw_melon_ts: i64,
+    pub schedule_modifications: u16,
+    // Layout version, see `migrate_pool`. Pools created before versioning
+    // read as 0.
+    pub version: u8,
+    pub stats: PoolStats,
+    // Only accounts the creator's withdrawals can go to, changed with
+    // `propose_proceeds_accounts` and `apply_proceeds_accounts`.
+    pub creator_usdc: Pubkey,
+    pub creator_watermelon: Pubkey,
+    // Signs the KYC attestations deposits need, unset for open sales.
+    pub kyc_verifier: Pubkey,
+    pub gate: ParticipationGate,
+    pub stake_tiers: StakeTiers,
+    pub lottery: Lottery,
+    pub tranches: ProceedsTranches,
+    // Slot whose hash seeds the lottery draw, see `commit_lottery_draw`.
+    // Zero until it is picked.
+    pub lottery_draw_slot: u64,
+    // Address the pool was created at. Its vaults, redeemable mint and the
+    // accounts of its users are derived from it, also once `migrate_pool`
+    // has moved the pool to another address.
+    pub origin: Pubkey,
+    // Room for the fields added next, always zero. A new field goes right
+    // before it and takes its bytes, reading as zero in existing pools, so
+    // the account keeps its size and `migrate_pool` only bumps the version.
+    pub reserved: [u64; 28],
+}
+
+impl PoolAccount {
+    // Space taken by a pool account, discriminator included. Every field has
+    // a fixed size so it is the same for any instance.
+    pub fn space() -> usize {
+        8 + PoolAccount::default().try_to_vec().unwrap().len()
+    }
+
+    // Decodes a pool account written with any layout version. Older layouts
+    // are shorter, the fields they lack read as zero.
+    pub fn try_deserialize_any_version(data: &[u8]) -> std::result::Result<Self, ProgramError> {
+        let mut padded = data.to_vec();
+        if padded.len() < PoolAccount::space() {
+            padded.resize(PoolAccount::space(), 0);
+        }
+        PoolAccount::try_deserialize(&mut &padded[..])
+    }
+
+    // Status of the pool at `now`. Cancelled and closed pools stay that way,
+    // otherwise the status follows the schedule.
+    pub fn current_status(&self, now: i64) -> PoolStatus {
+        match self.status {
+            PoolStatus::Cancelled | PoolStatus::Closed => self.status,
+            _ => self.schedule.phase(now),
+        }
+    }
+
+    // USDC bonus owed for the given referred volume.
+    pub fn referral_usdc_reward(&self, referred_usdc: u64) -> u64 {
+        ((referred_usdc as u128) * (self.referral_bps as u128) / 10_000) as u64
+    }
+
+    // USDC still owed to referrers that have not claimed yet.
+    pub fn referral_usdc_outstanding(&self) -> u64 {
+        self.referral_usdc_reward(self.total_referred_usdc)
+            .saturating_sub(self.referral_usdc_paid)
+    }
+
+    // USDC in the pool that is not part of the proceeds: unclaimed referral
+    // bonuses and the escrow of lottery tickets.
+    pub fn reserved_usdc(&self) -> u64 {
+        self.referral_usdc_outstanding()
+            .saturating_add(self.lottery.escrowed_usdc)
+    }
+
+    // Index of the tranche released next, if any is left to release.
+    pub fn next_tranche(&self, now: i64) -> Option<usize> {
+        self.tranches
+            .schedule(&self.schedule)
+            .iter()
+            .position(|tranche| now < tranche.release_ts)
+    }
+
+    // Proceeds the creator can still withdraw at `now`: the released share of
+    // the raise minus what was already withdrawn. Once halt refunds close at
+    // the claim deadline, everything that was not refunded is released.
+    pub fn releasable_usdc(&self, now: i64) -> u64 {
+        let released = if self.tranches.halted && now >= self.claim_deadline_ts() {
+            self.total_usdc_deposited
+                .saturating_sub(self.stats.usdc_withdrawn)
+        } else {
+            let released_bps = self.tranches.released_bps(&self.schedule, now);
+            ((self.total_usdc_deposited as u128) * (released_bps as u128) / 10_000) as u64
+        };
+        released.saturating_sub(self.stats.creator_usdc_withdrawn)
+    }
+
+    // Watermelon in the pool that is up for sale, i.e. without the part of
+    // the referral bucket that has not been claimed yet.
+    pub fn sale_watermelon(&self, pool_watermelon: u64) -> u64 {
+        pool_watermelon.saturating_sub(
+            self.referral_watermelon
+                .saturating_sub(self.referral_watermelon_paid),
+        )
+    }
+
+    // Watermelon up for sale that no redeemable token will ever claim: what
+    // the floor price withholds, or everything if nobody deposited. Below the
+    // floor every redeemable token is worth a fixed amount of watermelon, so
+    // this stays the same as redemptions go on.
+    pub fn unsold_watermelon(&self, pool_watermelon: u64, redeemable_supply: u64) -> u64 {
+        let sale_watermelon = self.sale_watermelon(pool_watermelon);
+        if redeemable_supply == 0 {
+            return sale_watermelon;
+        }
+        sale_watermelon.saturating_sub(redemption_amount(
+            redeemable_supply,
+            redeemable_supply,
+            sale_watermelon,
+        ))
+    }
+
+    // After this the creator can withdraw whatever watermelon is left.
+    pub fn claim_deadline_ts(&self) -> i64 {
+        self.schedule.withdraw_melon_ts + CLAIM_PERIOD_SEC
+    }
+
+    // Watermelon `withdraw_pool_watermelon` lets the creator take at `now`.
+    // Until the claim deadline only what the sale did not sell can leave the
+    // pool, cancelled sales did not sell anything.
+    pub fn withdrawable_watermelon(
+        &self,
+        pool_watermelon: u64,
+        redeemable_supply: u64,
+        now: i64,
+    ) -> u64 {
+        if self.status == PoolStatus::Cancelled || now >= self.claim_deadline_ts() {
+            pool_watermelon
+        } else {
+            self.unsold_watermelon(pool_watermelon, redeemable_supply)
+        }
+    }
+}
+
+// Running totals of a sale, next to `PoolAccount::total_usdc_deposited`.
+// Pools created before they were added only count from then on.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct PoolStats {
+    // Users with a `UserPosition`, i.e. that deposited at least once.
+    pub depositors: u64,
+    // USDC returned to users, by refunds of cancelled pools or halt refunds.
+    pub usdc_withdrawn: u64,
+    pub redeemable_burned: u64,
+    // Watermelon paid for redeemable tokens, crank rewards included.
+    pub watermelon_distributed: u64,
+    pub creator_usdc_withdrawn: u64,
+    pub creator_watermelon_withdrawn: u64,
+}
+
+// Holdings a user needs to deposit in a pool.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct ParticipationGate {
+    pub kind: GateKind,
+    // The mint of a token gate or the collection of a collection gate.
+    pub key: Pubkey,
+    // Balance a token gate requires, collection gates require one NFT.
+    pub min_amount: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GateKind {
+    // Anyone can deposit.
+    None,
+    // Proven with a token account of the user holding `min_amount` of `key`.
+    Token,
+    // Proven with a token account of the user holding an NFT, the NFT's mint
+    // and its metadata, which has to list `key` as its verified collection.
+    Collection,
+}
+
+impl Default for GateKind {
+    fn default() -> Self {
+        GateKind::None
+    }
+}
+
+impl GateKind {
+    // Accounts proving a user passes the gate.
+    pub fn proof_accounts(&self) -> usize {
+        match self {
+            GateKind::None => 0,
+            GateKind::Token => 1,
+            GateKind::Collection => 3,
+        }
+    }
+}
+
+impl ParticipationGate {
+    pub fn validate(&self) -> std::result::Result<(), ErrorCode> {
+        let valid = match self.kind {
+            GateKind::None => true,
+            GateKind::Token => self.key != Pubkey::default() && self.min_amount > 0,
+            GateKind::Collection => self.key != Pubkey::default(),
+        };
+        if !valid {
+            return Err(ErrorCode::InvalidParam);
+        }
+        Ok(())
+    }
+
+    // Asserts `proofs` show `user` passes the gate.
+    fn verify(&self, user: &Pubkey, proofs: &[AccountInfo]) -> Result<()> {
+        if self.kind == GateKind::None {
+            return Ok(());
+        }
+        let token: Account<TokenAccount> =
+            Account::try_from(&proofs[0]).map_err(|_| ErrorCode::GateNotSatisfied)?;
+        if token.owner != *user {
+            return Err(ErrorCode::GateNotSatisfied.into());
+        }
+        let passed = match self.kind {
+            GateKind::Token => token.mint == self.key && token.amount >= self.min_amount,
+            _ => {
+                token.amount >= 1
+                    && is_nft(&proofs[1], &token.mint)
+                    && nft_in_collection(&proofs[2], &token.mint, &self.key)
+            }
+        };
+        if !passed {
+            return Err(ErrorCode::GateNotSatisfied.into());
+        }
+        Ok(())
+    }
+}
+
+// Whether `mint` is the mint account at `address` and has minted a single
+// indivisible token.
+fn is_nft(mint: &AccountInfo, address: &Pubkey) -> bool {
+    if mint.key != address {
+        return false;
+    }
+    match Account::<Mint>::try_from(mint) {
+        Ok(mint) => mint.supply == 1 && mint.decimals == 0,
+        Err(_) => false,
+    }
+}
+
+// Collection of an NFT. The token metadata version this program builds
+// against predates it, later ones append it to the metadata right after the
+// token standard.
+#[derive(AnchorDeserialize)]
+struct MetadataCollection {
+    verified: bool,
+    key: Pubkey,
+}
+
+// Whether `metadata` is the metadata of the NFT `mint` and lists it in the
+// verified collection `collection`.
+fn nft_in_collection(metadata: &AccountInfo, mint: &Pubkey, collection: &Pubkey) -> bool {
+    let address = Pubkey::find_program_address(
+        &[
+            b"metadata".as_ref(),
+            spl_token_metadata::ID.as_ref(),
+            mint.as_ref(),
+        ],
+        &spl_token_metadata::ID,
+    )
+    .0;
+    if *metadata.key != address || *metadata.owner != spl_token_metadata::ID {
+        return false;
+    }
+    let data = match metadata.try_borrow_data() {
+        Ok(data) => data,
+        Err(_) => return false,
+    };
+    let mut rest: &[u8] = &data;
+    match spl_token_metadata::state::Metadata::deserialize(&mut rest) {
+        Ok(metadata)
+            if metadata.key == spl_token_metadata::state::Key::MetadataV1
+                && metadata.mint == *mint => {}
+        _ => return false,
+    }
+    // Metadata written before collections is padded with zeros, which read
+    // as neither a token standard nor a collection.
+    if Option::<u8>::deserialize(&mut rest).is_err() {
+        return false;
+    }
+    match Option::<MetadataCollection>::deserialize(&mut rest) {
+        Ok(Some(nft_collection)) => nft_collection.verified && nft_collection.key == *collection,
+        _ => false,
+    }
+}
+
+// Guaranteed allocations by amount staked. Users snapshot their tier with
+// `register_stake` before the sale, and until `guaranteed_end_ts` only they
+// can deposit, each up to the allocation of their tier.
+//
+// The snapshot is only a balance, the pool cannot tell whether the same
+// tokens were registered from another wallet before. The staking program
+// must keep a stake locked from the moment it is made until at least
+// `guaranteed_end_ts`, otherwise a user could unstake, restake from another
+// wallet and register again.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct StakeTiers {
+    // Owns the stake of each wallet through `stake_authority_address` and
+    // locks it through `guaranteed_end_ts`, unset for pools without tiers.
+    pub staking_program: Pubkey,
+    pub stake_mint: Pubkey,
+    pub guaranteed_end_ts: i64,
+    // By increasing `min_stake`, unused tiers at the end are left zero.
+    pub tiers: [StakeTier; MAX_STAKE_TIERS],
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct StakeTier {
+    pub min_stake: u64,
+    // USDC a user of the tier can deposit in the guaranteed round.
+    pub allocation: u64,
+}
+
+impl StakeTiers {
+    pub fn enabled(&self) -> bool {
+        self.staking_program != Pubkey::default()
+    }
+
+    // The guaranteed round falls within the deposit window, tiers have
+    // increasing stakes and non-zero allocations.
+    pub fn validate(&self, schedule: &PoolSchedule) -> std::result::Result<(), ErrorCode> {
+        if !self.enabled() {
+            if *self != StakeTiers::default() {
+                return Err(ErrorCode::InvalidParam);
+            }
+            return Ok(());
+        }
+        if self.stake_mint == Pubkey::default()
+            || self.guaranteed_end_ts <= schedule.start_ido_ts
+            || self.guaranteed_end_ts > schedule.end_deposits_ts
+            || self.tiers[0].min_stake == 0
+        {
+            return Err(ErrorCode::InvalidParam);
+        }
+        let used = self.tiers.iter().take_while(|tier| tier.min_stake > 0);
+        let mut previous = 0;
+        for tier in used {
+            if tier.min_stake <= previous || tier.allocation == 0 {
+                return Err(ErrorCode::InvalidParam);
+            }
+            previous = tier.min_stake;
+        }
+        let mut unused = self.tiers.iter().skip_while(|tier| tier.min_stake > 0);
+        if unused.any(|tier| *tier != StakeTier::default()) {
+            return Err(ErrorCode::InvalidParam);
+        }
+        Ok(())
+    }
+
+    // 1-based index and allocation of the highest tier `staked` reaches.
+    pub fn tier_for(&self, staked: u64) -> Option<(u8, u64)> {
+        self.tiers
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, tier)| tier.min_stake > 0 && staked >= tier.min_stake)
+            .map(|(i, tier)| (i as u8 + 1, tier.allocation))
+    }
+
+    pub fn in_guaranteed_round(&self, now: i64) -> bool {
+        self.enabled() && now < self.guaranteed_end_ts
+    }
+}
+
+// Release schedule of the USDC proceeds. Each tranche releases `bps` of the
+// raise to the creator at `release_ts`, until then contributors can vote to
+// cancel it and every later one with `vote_halt`. Pools without tranches
+// release everything when claims open.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct ProceedsTranches {
+    // By increasing `release_ts`, unused entries are zero.
+    pub tranches: [Tranche; MAX_TRANCHES],
+    // Set once a halt vote reached the quorum, `halted_tranche` and every
+    // later tranche are never released.
+    pub halted: bool,
+    pub halted_tranche: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Tranche {
+    pub release_ts: i64,
+    pub bps: u16,
+}
+
+impl ProceedsTranches {
+    pub fn enabled(&self) -> bool {
+        self.tranches[0].bps > 0
+    }
+
+    // The first tranche is released when claims open at the earliest, so
+    // contributors hold their watermelon before the creator is paid.
+    pub fn validate(&self, schedule: &PoolSchedule) -> std::result::Result<(), ErrorCode> {
+        if !self.enabled() {
+            return if self
+                .tranches
+                .iter()
+                .all(|tranche| *tranche == Tranche::default())
+            {
+                Ok(())
+            } else {
+                Err(ErrorCode::InvalidParam)
+            };
+        }
+        let mut previous_ts = schedule.withdraw_melon_ts - 1;
+        let mut total_bps: u64 = 0;
+        let mut used = true;
+        for tranche in self.tranches.iter() {
+            if tranche.bps == 0 {
+                used = false;
+            }
+            if !used {
+                if *tranche != Tranche::default() {
+                    return Err(ErrorCode::InvalidParam);
+                }
+                continue;
+            }
+            if tranche.release_ts <= previous_ts {
+                return Err(ErrorCode::InvalidParam);
+            }
+            previous_ts = tranche.release_ts;
+            total_bps += tranche.bps as u64;
+        }
+        if total_bps != 10_000 {
+            return Err(ErrorCode::InvalidParam);
+        }
+        Ok(())
+    }
+
+    // Tranches in release order, a single one releasing everything when
+    // claims open for pools without tranches.
+    pub fn schedule(&self, schedule: &PoolSchedule) -> Vec<Tranche> {
+        if !self.enabled() {
+            return vec![Tranche {
+                release_ts: schedule.withdraw_melon_ts,
+                bps: 10_000,
+            }];
+        }
+        self.tranches
+            .iter()
+            .take_while(|tranche| tranche.bps > 0)
+            .copied()
+            .collect()
+    }
+
+    // Share of the raise released to the creator by `now`.
+    pub fn released_bps(&self, schedule: &PoolSchedule, now: i64) -> u64 {
+        self.schedule(schedule)
+            .iter()
+            .enumerate()
+            .filter(|(index, tranche)| {
+                tranche.release_ts <= now
+                    && !(self.halted && *index >= self.halted_tranche as usize)
+            })
+            .map(|(_, tranche)| tranche.bps as u64)
+            .sum()
+    }
+
+    // Share of the raise refunded to contributors once halted.
+    pub fn refund_bps(&self, schedule: &PoolSchedule) -> u64 {
+        if !self.halted {
+            return 0;
+        }
+        self.schedule(schedule)
+            .iter()
+            .skip(self.halted_tranche as usize)
+            .map(|tranche| tranche.bps as u64)
+            .sum()
+    }
+}
+
+// Allocation of oversubscribed sales by lottery. Instead of depositing,
+// users escrow `ticket_usdc` with `buy_ticket` during the deposit window.
+// `draw_lottery` then picks `winning_tickets` of them, which become deposits
+// with `claim_ticket`; everybody else gets their escrow back with
+// `refund_ticket`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Lottery {
+    // Unset for pools without a lottery.
+    pub ticket_usdc: u64,
+    pub winning_tickets: u64,
+    // `lottery_commitment` of the secret revealed by `draw_lottery`.
+    pub commitment: [u8; 32],
+    pub tickets: u64,
+    // USDC of tickets neither claimed nor refunded, not part of the proceeds.
+    pub escrowed_usdc: u64,
+    pub drawn: bool,
+    pub seed: [u8; 32],
+}
+
+impl Lottery {
+    pub fn enabled(&self) -> bool {
+        self.ticket_usdc > 0
+    }
+
+    // Unset, or with a ticket price, winning tickets, a commitment and
+    // `MIN_LOTTERY_WINDOW_SEC` between the end of deposits and claims.
+    pub fn validate(&self, schedule: &PoolSchedule) -> std::result::Result<(), ErrorCode> {
+        if *self == Lottery::default() {
+            return Ok(());
+        }
+        if self.ticket_usdc == 0 || self.winning_tickets == 0 || self.commitment == [0; 32] {
+            return Err(ErrorCode::InvalidParam);
+        }
+        if schedule.withdraw_melon_ts - schedule.end_deposits_ts < MIN_LOTTERY_WINDOW_SEC {
+            return Err(ErrorCode::LotteryWindowTooShort);
+        }
+        Ok(())
+    }
+
+    // Whether ticket `number` won, once drawn. Exactly `winning_tickets`
+    // tickets win, or all of them if fewer were sold.
+    pub fn is_winner(&self, number: u64) -> bool {
+        self.drawn && lottery_rank(&self.seed, number, self.tickets) < self.winning_tickets
+    }
+}
+
+// Ticket of a user in a lottery pool, closed once claimed or refunded.
+#[account]
+pub struct LotteryTicket {
+    pub pool_account: Pubkey,
+    pub owner: Pubkey,
+    // Order of purchase, starting at 0.
+    pub number: u64,
+}
+
+// Deposits referred by a single referrer in a pool.
+#[account]
+pub struct Referrer {
+    pub pool_account: Pubkey,
+    pub authority: Pubkey,
+    pub referred_usdc: u64,
+    pub claimed: bool,
+}
+
+// Timestamps of the IDO phases, see `PoolStatus`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct PoolSchedule {
     pub start_ido_ts: i64,
     pub end_deposits_ts: i64,
     pub end_ido_ts: i64,
     pub withdraw_melon_ts: i64,
 }
 
+impl PoolSchedule {
+    // Builds a schedule, without a grace period deposits close at the same
+    // time the IDO ends.
+    pub fn new(
+        start_ido_ts: i64,
+        end_deposits_ts: i64,
+        grace_period: Option<i64>,
+        withdraw_melon_ts: i64,
+    ) -> Self {
+        PoolSchedule {
+            start_ido_ts,
+            end_deposits_ts,
+            end_ido_ts: end_deposits_ts + grace_period.unwrap_or(0),
+            withdraw_melon_ts,
+        }
+    }
+
+    // Seconds during which users can only withdraw USDC, if any.
+    pub fn grace_period(&self) -> Option<i64> {
+        let grace_period = self.end_ido_ts - self.end_deposits_ts;
+        if grace_period > 0 {
+            Some(grace_period)
+        } else {
+            None
+        }
+    }
+
+    pub fn validate(&self) -> std::result::Result<(), ErrorCode> {
+        if !(self.start_ido_ts < self.end_deposits_ts
+            && self.end_deposits_ts <= self.end_ido_ts
+            && self.end_ido_ts <= self.withdraw_melon_ts)
+        {
+            return Err(ErrorCode::SeqTimes);
+        }
+        Ok(())
+    }
+
+    pub fn phase(&self, now: i64) -> PoolStatus {
+        if now <= self.start_ido_ts {
+            PoolStatus::Scheduled
+        } else if now < self.end_deposits_ts {
+            PoolStatus::Depositing
+        } else if now < self.end_ido_ts {
+            PoolStatus::Grace
+        } else if now <= self.withdraw_melon_ts {
+            PoolStatus::Settling
+        } else {
+            PoolStatus::Claimable
+        }
+    }
+}
+
+#[derive(
+    AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub enum PoolStatus {
+    // The IDO has not started.
+    Scheduled,
+    // Users can deposit USDC.
+    Depositing,
+    // Users can only withdraw USDC.
+    Grace,
+    // The sale is over but watermelon cannot be claimed yet.
+    Settling,
+    // Users can exchange redeemable tokens for watermelon.
+    Claimable,
+    // The sale was called off, users can exchange redeemable tokens for USDC.
+    Cancelled,
+    // Everybody has been paid out, nothing can happen anymore.
+    Closed,
+}
+
+impl Default for PoolStatus {
+    fn default() -> Self {
+        PoolStatus::Scheduled
+    }
+}
+
+// Returned by `quote_redemption` and `quote_deposit` as borsh encoded return
+// data.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq)]
+pub struct PayoutQuote {
+    pub watermelon_amount: u64,
+    // USDC per watermelon scaled by 10^9, see `token_price`.
+    pub price: f64,
+    pub floor_applied: bool,
+}
+
+impl PayoutQuote {
+    pub fn new(amount: u64, redeemable_supply: u64, sale_watermelon: u64) -> Self {
+        let price = token_price(redeemable_supply, sale_watermelon);
+        PayoutQuote {
+            watermelon_amount: redemption_amount(amount, redeemable_supply, sale_watermelon),
+            price,
+            floor_applied: price < FLOOR_PRICE,
+        }
+    }
+}
+
+// Contribution record of a single user in a pool.
+#[account]
+#[derive(Default)]
+pub struct UserPosition {
+    pub pool_account: Pubkey,
+    pub owner: Pubkey,
+    pub usdc_deposited: u64,
+    // Bit `i` is set once the user voted to halt tranche `i`.
+    pub halt_votes: u8,
+    pub halt_refunded: bool,
+    // Snapshot taken by `register_stake`, the tier is 1-based and 0 for
+    // users that did not register. Positions created before it was added
+    // have to go through `migrate_position`.
+    pub stake_tier: u8,
+    pub stake_allocation: u64,
+}
+
+impl UserPosition {
+    // Space taken by a position, discriminator included.
+    pub fn space() -> usize {
+        8 + UserPosition::default().try_to_vec().unwrap().len()
+    }
+}
+
+// Contributor vote to cancel a tranche of the pool's USDC proceeds, see
+// `vote_halt`.
+#[account]
+pub struct HaltVote {
+    pub pool_account: Pubkey,
+    pub tranche: u8,
+    pub votes: u64,
+}
+
+// New proceeds accounts of a pool waiting for their timelock.
+#[account]
+pub struct ProceedsChange {
+    pub pool_account: Pubkey,
+    pub creator_usdc: Pubkey,
+    pub creator_watermelon: Pubkey,
+    pub effective_ts: i64,
+}
+
+// Marks a wallet as blocked in every pool, the account only exists while it
+// is.
+#[account]
+pub struct BlocklistEntry {
+    pub wallet: Pubkey,
+}
+
 #[error]
 pub enum ErrorCode {
     #[msg("IDO must start in the future")]
@@ -514,45 +3294,551 @@ pub enum ErrorCode {
     UsdcWithdrawNotAllowed, //311, 0x137
     #[msg("Tokens still need to be redeemed")]
     WithdrawTokensNotAllowed, //311, 0x138
+    #[msg("Proceeds have been halted by a contributor vote")]
+    ProceedsHalted, //313, 0x139
+    #[msg("Proceeds have not been halted")]
+    ProceedsNotHalted, //314, 0x13a
+    #[msg("User has already voted")]
+    AlreadyVoted, //315, 0x13b
+    #[msg("User has already been refunded")]
+    AlreadyRefunded, //316, 0x13c
+    #[msg("User has no recorded contribution")]
+    NoContribution, //317, 0x13d
+    #[msg("Invalid referrer account")]
+    InvalidReferrer, //318, 0x13e
+    #[msg("Referrals are already configured")]
+    ReferralsConfigured, //319, 0x13f
+    #[msg("Reward has already been claimed")]
+    AlreadyClaimed, //320, 0x140
+    #[msg("USDC is reserved for referral rewards")]
+    ReservedForReferrals, //321, 0x141
+    #[msg("IDO phase has already started")]
+    PhaseStarted, //322, 0x142
+    #[msg("IDO times must be changed with more notice")]
+    RescheduleNotice, //323, 0x143
+    #[msg("IDO cannot be delayed any further")]
+    MaxDelayExceeded, //324, 0x144
+    #[msg("IDO has been cancelled")]
+    PoolCancelled, //325, 0x145
+    #[msg("IDO has been closed")]
+    PoolClosed, //326, 0x146
+    #[msg("Invalid pool status transition")]
+    InvalidStatusTransition, //327, 0x147
+    #[msg("Pool account is already up to date")]
+    PoolUpToDate, //328, 0x148
+    #[msg("Invalid distribution accounts")]
+    InvalidDistribution, //329, 0x149
+    #[msg("Proceeds accounts change is still timelocked")]
+    ProceedsTimelocked, //330, 0x14a
+    #[msg("Missing or invalid KYC attestation")]
+    InvalidKycAttestation, //331, 0x14b
+    #[msg("KYC attestation has expired")]
+    KycExpired, //332, 0x14c
+    #[msg("Deposit exceeds the KYC allocation")]
+    KycAllocationExceeded, //333, 0x14d
+    #[msg("Address is blocked")]
+    AddressBlocked, //334, 0x14e
+    #[msg("Participation gate is not satisfied")]
+    GateNotSatisfied, //335, 0x14f
+    #[msg("Stake account does not belong to the user")]
+    InvalidStakeAccount, //336, 0x150
+    #[msg("Stake does not reach any tier")]
+    NoStakeTier, //337, 0x151
+    #[msg("Deposit exceeds the tier allocation")]
+    TierAllocationExceeded, //338, 0x152
+    #[msg("Position account is already up to date")]
+    PositionUpToDate, //339, 0x153
+    #[msg("Deposits go through lottery tickets in this pool")]
+    DepositsThroughLottery, //340, 0x154
+    #[msg("Lottery has not been drawn")]
+    LotteryNotDrawn, //341, 0x155
+    #[msg("Lottery has already been drawn")]
+    LotteryAlreadyDrawn, //342, 0x156
+    #[msg("Revealed secret does not match the lottery commitment")]
+    InvalidLotteryReveal, //343, 0x157
+    #[msg("Ticket did not win the lottery")]
+    LosingTicket, //344, 0x158
+    #[msg("Winning tickets can only be refunded once claims open")]
+    WinningTicket, //345, 0x159
+    #[msg("Proceeds have not been released yet")]
+    ProceedsNotReleased, //346, 0x15a
+    #[msg("Only the next tranche to be released can be voted on")]
+    TrancheNotOpen, //347, 0x15b
+    #[msg("Lottery draw slot has not been committed")]
+    LotteryNotCommitted, //348, 0x15c
+    #[msg("Lottery draw slot has not been reached")]
+    DrawSlotNotReached, //349, 0x15d
+    #[msg("Lottery draw slot hash is no longer available")]
+    DrawSlotExpired, //350, 0x15e
+    #[msg("Lottery pools need more time between the end of deposits and claims")]
+    LotteryWindowTooShort, //351, 0x15f
+    #[msg("Halt refunds closed at the claim deadline")]
+    HaltRefundsClosed, //352, 0x160
 }
 
 // Access control modifiers.
 
-// Asserts the IDO starts in the future.
-fn future_start_time<'info>(ctx: &Context<InitializePool<'info>>, start_ido_ts: i64) -> Result<()> {
-    if !(ctx.accounts.clock.unix_timestamp < start_ido_ts) {
-        return Err(ErrorCode::IdoFuture.into());
+// Rules a new pool has to follow, shared with off-chain tooling preparing
+// launches: the IDO starts in the future, the schedule is in order and there
+// are tokens to sell.
+pub fn validate_pool_params(
+    num_ido_tokens: u64,
+    schedule: &PoolSchedule,
+    now: i64,
+) -> std::result::Result<(), ErrorCode> {
+    // Strictly in the future: `phase` still reads a pool starting right now
+    // as scheduled.
+    if now >= schedule.start_ido_ts {
+        return Err(ErrorCode::IdoFuture);
+    }
+    schedule.validate()?;
+    if num_ido_tokens == 0 {
+        return Err(ErrorCode::InvalidParam);
     }
     Ok(())
 }
 
-// Asserts the IDO is in the first phase.
-fn unrestricted_phase<'info>(ctx: &Context<ExchangeUsdcForRedeemable<'info>>) -> Result<()> {
-    if !(ctx.accounts.pool_account.start_ido_ts < ctx.accounts.clock.unix_timestamp) {
-        return Err(ErrorCode::StartIdoTime.into());
-    } else if !(ctx.accounts.clock.unix_timestamp < ctx.accounts.pool_account.end_deposits_ts) {
-        return Err(ErrorCode::EndDepositsTime.into());
+// Sale price in USDC per watermelon, scaled by 10^9. Redeemable tokens have 6
+// decimals (same as USDC) and watermelon 9, hence the 10^3 adjustment.
+pub fn token_price(redeemable_supply: u64, sale_watermelon: u64) -> f64 {
+    let real_redeemable_supply = redeemable_supply * u64::pow(10, 3);
+    (real_redeemable_supply as f64 / sale_watermelon as f64) * f64::powf(10.0, 9.0)
+}
+
+// Below 0.50 USDC per watermelon the sale does not distribute all tokens.
+pub const FLOOR_PRICE: f64 = 0.50 * 1_000_000_000.0;
+
+// Watermelon paid out for burning `amount` redeemable tokens, given the live
+// redeemable supply and the watermelon in the pool that is up for sale.
+pub fn redemption_amount(amount: u64, redeemable_supply: u64, sale_watermelon: u64) -> u64 {
+    let token_price = token_price(redeemable_supply, sale_watermelon);
+    // The token multiple will be token price / 0.50;
+    // If token multiple is >= 1, then we don't have to distribute a fraction of the tokens
+    let token_multiple = token_price / FLOOR_PRICE;
+    // Calculate watermelon tokens due.
+    let mut watermelon_amount = (amount as u128)
+        .checked_mul(sale_watermelon as u128)
+        .unwrap()
+        .checked_div(redeemable_supply as u128)
+        .unwrap();
+    if token_price < FLOOR_PRICE {
+        watermelon_amount = ((watermelon_amount as f64) * token_multiple) as u128;
+    }
+    watermelon_amount as u64
+}
+
+// Token metadata name and symbol of the redeemable token of a sale, e.g.
+// "MELON IDO Redeemable" and "rMELON" for the MELON sale.
+pub fn redeemable_token_name(
+    sale_symbol: &str,
+) -> std::result::Result<(String, String), ErrorCode> {
+    let symbol = format!("r{}", sale_symbol.to_uppercase());
+    let name = format!("{} IDO Redeemable", sale_symbol.to_uppercase());
+    if sale_symbol.is_empty()
+        || symbol.len() > spl_token_metadata::state::MAX_SYMBOL_LENGTH
+        || name.len() > spl_token_metadata::state::MAX_NAME_LENGTH
+    {
+        return Err(ErrorCode::InvalidParam);
+    }
+    Ok((name, symbol))
+}
+
+// PDA marking `wallet` as blocked, see `block_address`.
+pub fn blocklist_entry_address(wallet: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"blocklist".as_ref(), wallet.as_ref()], &ID).0
+}
+
+// Commitment to the secret of a lottery, see `configure_lottery`.
+pub fn lottery_commitment(secret: &[u8; 32]) -> [u8; 32] {
+    hashv(&[secret]).to_bytes()
+}
+
+// Position of ticket `number` in the random order of the `tickets` tickets
+// drawn with `seed`, tickets ranked below the number of winners win. The
+// order is a keyed Feistel permutation of the ticket numbers, so every rank
+// is taken exactly once and each ticket's rank is computed on its own.
+pub fn lottery_rank(seed: &[u8; 32], number: u64, tickets: u64) -> u64 {
+    if tickets <= 1 {
+        return 0;
+    }
+    // Smallest even number of bits covering every ticket number, values
+    // outside of the range are walked through the permutation again.
+    let mut bits = 64 - (tickets - 1).leading_zeros();
+    bits += bits % 2;
+    let half = bits / 2;
+    let mask = (1u64 << half) - 1;
+    let mut rank = number;
+    loop {
+        let (mut left, mut right) = (rank >> half, rank & mask);
+        for round in 0..4u8 {
+            let hash = hashv(&[seed, &[round], &right.to_le_bytes()]).to_bytes();
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&hash[..8]);
+            let mixed = left ^ (u64::from_le_bytes(bytes) & mask);
+            left = right;
+            right = mixed;
+        }
+        rank = (left << half) | right;
+        if rank < tickets {
+            return rank;
+        }
+    }
+}
+
+// Authority a staking program holds `wallet`'s stake with, tiered pools only
+// count token accounts owned by it.
+pub fn stake_authority_address(staking_program: &Pubkey, wallet: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"stake".as_ref(), wallet.as_ref()], staking_program).0
+}
+
+// Whether the blocklist entry PDA of a wallet marks it as blocked. Only the
+// program can create data there, lamports sent to the address do not count.
+fn is_blocked(blocklist_entry: &AccountInfo, program_id: &Pubkey) -> bool {
+    blocklist_entry.owner == program_id && blocklist_entry.lamports() > 0
+}
+
+// Message a KYC verifier signs to let `user` deposit up to `max_allocation`
+// USDC in `pool` until `expiry`.
+pub fn kyc_attestation_message(
+    pool: &Pubkey,
+    user: &Pubkey,
+    expiry: i64,
+    max_allocation: u64,
+) -> Vec<u8> {
+    let mut message = Vec::with_capacity(32 + 32 + 8 + 8);
+    message.extend_from_slice(pool.as_ref());
+    message.extend_from_slice(user.as_ref());
+    message.extend_from_slice(&expiry.to_le_bytes());
+    message.extend_from_slice(&max_allocation.to_le_bytes());
+    message
+}
+
+// Returns the allocation of the KYC attestation in the ed25519 program
+// instruction right before the current one. The runtime has checked the
+// signature by then, what is left is checking who signed what.
+fn kyc_max_allocation(
+    instructions: &AccountInfo,
+    verifier: &Pubkey,
+    pool: &Pubkey,
+    user: &Pubkey,
+    now: i64,
+) -> Result<u64> {
+    let current = sysvar_instructions::load_current_index_checked(instructions)? as usize;
+    if current == 0 {
+        return Err(ErrorCode::InvalidKycAttestation.into());
+    }
+    let ix = sysvar_instructions::load_instruction_at_checked(current - 1, instructions)?;
+    if ix.program_id != ed25519_program::ID {
+        return Err(ErrorCode::InvalidKycAttestation.into());
+    }
+    let (public_key, message) =
+        ed25519_signed_data(&ix.data).ok_or(ErrorCode::InvalidKycAttestation)?;
+    if public_key != verifier.as_ref()
+        || message.len() != 32 + 32 + 8 + 8
+        || &message[..32] != pool.as_ref()
+        || &message[32..64] != user.as_ref()
+    {
+        return Err(ErrorCode::InvalidKycAttestation.into());
+    }
+    let mut expiry = [0u8; 8];
+    expiry.copy_from_slice(&message[64..72]);
+    if now > i64::from_le_bytes(expiry) {
+        return Err(ErrorCode::KycExpired.into());
+    }
+    let mut max_allocation = [0u8; 8];
+    max_allocation.copy_from_slice(&message[72..]);
+    Ok(u64::from_le_bytes(max_allocation))
+}
+
+// Public key and message of an ed25519 program instruction verifying a
+// single signature. Offsets pointing into other instructions are rejected,
+// the data read here has to be what the runtime verified.
+fn ed25519_signed_data(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    // Signature count and padding, then the seven u16 offsets of the
+    // signature: signature, its instruction, public key, its instruction,
+    // message, message size and its instruction.
+    if data.len() < 2 + 14 || data[0] != 1 {
+        return None;
+    }
+    let offset = |i: usize| u16::from_le_bytes([data[2 + 2 * i], data[3 + 2 * i]]);
+    if offset(1) != u16::MAX || offset(3) != u16::MAX || offset(6) != u16::MAX {
+        return None;
+    }
+    let public_key_start = offset(2) as usize;
+    let message_start = offset(4) as usize;
+    let public_key = data.get(public_key_start..public_key_start + 32)?;
+    let message = data.get(message_start..message_start + offset(5) as usize)?;
+    Some((public_key, message))
+}
+
+// Checks a user has to pass to put `amount` more USDC in a pool, by
+// depositing or with a lottery ticket: the participation gate, proven by
+// `gate_proofs`, and the KYC attestation of pools with a verifier, whose
+// allocation covers what the user `deposited` so far too.
+fn check_participation(
+    pool_account: &Account<PoolAccount>,
+    user: &Pubkey,
+    gate_proofs: &[AccountInfo],
+    instructions: &AccountInfo,
+    deposited: u64,
+    amount: u64,
+    now: i64,
+) -> Result<()> {
+    pool_account.gate.verify(user, gate_proofs)?;
+
+    let kyc_verifier = pool_account.kyc_verifier;
+    if kyc_verifier != Pubkey::default() {
+        let max_allocation =
+            kyc_max_allocation(instructions, &kyc_verifier, &pool_account.origin, user, now)?;
+        let deposited = deposited
+            .checked_add(amount)
+            .ok_or(ErrorCode::InvalidParam)?;
+        if deposited > max_allocation {
+            return Err(ErrorCode::KycAllocationExceeded.into());
+        }
     }
     Ok(())
 }
 
-// Asserts the IDO is in the second phase.
-fn withdraw_only_phase(ctx: &Context<ExchangeRedeemableForUsdc>) -> Result<()> {
-    if !(ctx.accounts.pool_account.start_ido_ts < ctx.accounts.clock.unix_timestamp) {
-        return Err(ErrorCode::StartIdoTime.into());
-    } else if !(ctx.accounts.clock.unix_timestamp < ctx.accounts.pool_account.end_ido_ts) {
-        return Err(ErrorCode::EndIdoTime.into());
+// Mints `amount` redeemable tokens for USDC already in the pool and lets the
+// pool burn them on the user's behalf, so `distribute_watermelon` can pay out
+// users that never claim.
+fn issue_redeemable<'info>(
+    pool_account: &PoolAccount,
+    pool_signer: &AccountInfo<'info>,
+    redeemable_mint: &Account<'info, Mint>,
+    user_redeemable: &Account<'info, TokenAccount>,
+    user_authority: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    let seeds = &[pool_account.watermelon_mint.as_ref(), &[pool_account.nonce]];
+    let signer = &[&seeds[..]];
+    let cpi_accounts = MintTo {
+        mint: redeemable_mint.to_account_info(),
+        to: user_redeemable.to_account_info(),
+        authority: pool_signer.clone(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer);
+    token::mint_to(cpi_ctx, amount)?;
+
+    let delegated_amount = user_redeemable
+        .amount
+        .checked_add(amount)
+        .ok_or(ErrorCode::InvalidParam)?;
+    let cpi_accounts = Approve {
+        to: user_redeemable.to_account_info(),
+        delegate: pool_signer.clone(),
+        authority: user_authority.clone(),
+    };
+    let cpi_ctx = CpiContext::new(token_program.clone(), cpi_accounts);
+    token::approve(cpi_ctx, delegated_amount)?;
+    Ok(())
+}
+
+// Records a contribution in the user's position and the pool totals, it is
+// used to weight halt votes and refunds.
+fn record_deposit(
+    pool_account: &mut Account<PoolAccount>,
+    user_position: &mut UserPosition,
+    user: &Pubkey,
+    amount: u64,
+) -> Result<()> {
+    // Registering a stake creates the position before the first deposit.
+    if user_position.pool_account == Pubkey::default() {
+        user_position.pool_account = pool_account.origin;
+        user_position.owner = *user;
+    }
+    if user_position.usdc_deposited == 0 {
+        pool_account.stats.depositors = pool_account
+            .stats
+            .depositors
+            .checked_add(1)
+            .ok_or(ErrorCode::InvalidParam)?;
     }
+    user_position.usdc_deposited = user_position
+        .usdc_deposited
+        .checked_add(amount)
+        .ok_or(ErrorCode::InvalidParam)?;
+    pool_account.total_usdc_deposited = pool_account
+        .total_usdc_deposited
+        .checked_add(amount)
+        .ok_or(ErrorCode::InvalidParam)?;
     Ok(())
 }
 
-// Asserts the IDO sale period has ended, based on the current timestamp.
-fn ido_over<'info>(
+// Empties `from` into the vault and closes it, the rent goes to the payer.
+// Returns the amount moved, nothing if `from` already is the vault.
+fn move_to_vault<'info>(
+    accounts: &MigratePool<'info>,
+    from: &AccountInfo<'info>,
+    vault: &Account<'info, TokenAccount>,
+    signer: &[&[&[u8]]],
+) -> Result<u64> {
+    if from.key == vault.to_account_info().key {
+        return Ok(0);
+    }
+    let amount = Account::<TokenAccount>::try_from(from)?.amount;
+    let cpi_accounts = Transfer {
+        from: from.clone(),
+        to: vault.to_account_info(),
+        authority: accounts.pool_signer.clone(),
+    };
+    let cpi_program = accounts.token_program.clone();
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    token::transfer(cpi_ctx, amount)?;
+
+    let cpi_accounts = CloseAccount {
+        account: from.clone(),
+        destination: accounts.payer.clone(),
+        authority: accounts.pool_signer.clone(),
+    };
+    let cpi_program = accounts.token_program.clone();
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    token::close_account(cpi_ctx)?;
+    Ok(amount)
+}
+
+// Hash of `slot` in the slot hashes sysvar, or of the first slot after it
+// if it was skipped. The sysvar only holds the most recent
+// `slot_hashes::MAX_ENTRIES` slots and is too big to deserialize, its entries
+// are read in place.
+fn slot_hash(slot_hashes: &AccountInfo, slot: u64) -> Result<[u8; 32]> {
+    let data = slot_hashes.try_borrow_data()?;
+    if data.len() < 8 {
+        return Err(ErrorCode::InvalidParam.into());
+    }
+    let read_u64 = |bytes: &[u8]| {
+        let mut le = [0u8; 8];
+        le.copy_from_slice(bytes);
+        u64::from_le_bytes(le)
+    };
+    // Entry count, then (slot, hash) pairs starting with the most recent.
+    let entries = data[8..]
+        .chunks_exact(8 + 32)
+        .take(read_u64(&data[..8]) as usize);
+    let mut after = None;
+    for entry in entries {
+        let entry_slot = read_u64(&entry[..8]);
+        if entry_slot < slot {
+            return after.ok_or_else(|| ErrorCode::DrawSlotNotReached.into());
+        }
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&entry[8..]);
+        if entry_slot == slot {
+            return Ok(hash);
+        }
+        after = Some(hash);
+    }
+    // Every entry is more recent, there is no telling whether `slot` was
+    // skipped or has already been evicted.
+    match after {
+        Some(_) => Err(ErrorCode::DrawSlotExpired.into()),
+        None => Err(ErrorCode::DrawSlotNotReached.into()),
+    }
+}
+
+// Asserts the pool is in one of the given statuses. This is the single guard
+// used by every handler, time-driven transitions are taken into account even
+// if they have not been persisted with `advance_pool_status` yet.
+fn in_status<'info>(
     pool_account: &Account<'info, PoolAccount>,
     clock: &Sysvar<'info, Clock>,
+    statuses: &[PoolStatus],
 ) -> Result<()> {
-    if !(pool_account.withdraw_melon_ts < clock.unix_timestamp) {
-        return Err(ErrorCode::IdoNotOver.into());
+    let status = pool_account.current_status(clock.unix_timestamp);
+    if statuses.contains(&status) {
+        return Ok(());
+    }
+    let err = match status {
+        PoolStatus::Cancelled => ErrorCode::PoolCancelled,
+        PoolStatus::Closed => ErrorCode::PoolClosed,
+        _ if statuses.iter().all(|allowed| status < *allowed) => {
+            if status == PoolStatus::Scheduled && statuses.contains(&PoolStatus::Depositing) {
+                ErrorCode::StartIdoTime
+            } else {
+                ErrorCode::IdoNotOver
+            }
+        }
+        _ => match statuses.iter().filter(|allowed| **allowed < status).max() {
+            Some(PoolStatus::Scheduled) => ErrorCode::IdoFuture,
+            Some(PoolStatus::Depositing) => ErrorCode::EndDepositsTime,
+            _ => ErrorCode::EndIdoTime,
+        },
+    };
+    Err(err.into())
+}
+
+// Creates an account of this program with `space` bytes at an address
+// derived with `seeds`, like anchor's `init` does. Lamports already sent to
+// the address count towards its rent.
+fn create_derived_account<'info>(
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    rent: &Rent,
+    space: usize,
+    seeds: &[&[u8]],
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let lamports = rent.minimum_balance(space);
+    if account.lamports() == 0 {
+        return invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                account.key,
+                lamports,
+                space as u64,
+                program_id,
+            ),
+            &[payer.clone(), account.clone(), system_program.clone()],
+            &[seeds],
+        );
+    }
+    let top_up = lamports.saturating_sub(account.lamports());
+    if top_up > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, top_up),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+    invoke_signed(
+        &system_instruction::allocate(account.key, space as u64),
+        &[account.clone(), system_program.clone()],
+        &[seeds],
+    )?;
+    invoke_signed(
+        &system_instruction::assign(account.key, program_id),
+        &[account.clone(), system_program.clone()],
+        &[seeds],
+    )
+}
+
+// Same as anchor's `close` constraint, for accounts the handler reads
+// itself: the rent goes to `destination` and the account is marked closed
+// until the runtime removes it at the end of the transaction.
+fn close_account_info(account: &AccountInfo, destination: &AccountInfo) -> ProgramResult {
+    let lamports = destination
+        .lamports()
+        .checked_add(account.lamports())
+        .ok_or(ProgramError::InvalidArgument)?;
+    **destination.try_borrow_mut_lamports()? = lamports;
+    **account.try_borrow_mut_lamports()? = 0;
+    account.try_borrow_mut_data()?[..8]
+        .copy_from_slice(&anchor_lang::__private::CLOSED_ACCOUNT_DISCRIMINATOR);
+    Ok(())
+}
+
+// Asserts a single IDO timestamp can be moved from `current_ts` to `new_ts`.
+fn reschedule_allowed(current_ts: i64, new_ts: i64, now: i64) -> Result<()> {
+    if current_ts == new_ts {
+        return Ok(());
+    }
+    if current_ts <= now {
+        return Err(ErrorCode::PhaseStarted.into());
+    }
+    if current_ts < now + MIN_RESCHEDULE_NOTICE_SEC || new_ts < now + MIN_RESCHEDULE_NOTICE_SEC {
+        return Err(ErrorCode::RescheduleNotice.into());
     }
     Ok(())
 }
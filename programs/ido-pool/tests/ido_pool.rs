@@ -0,0 +1,2754 @@
+// Integration tests running the program against a warpable clock, see
+// `program_test` for the runtime. Deposits and distributions are built with
+// `ido_pool_client`, whose remaining account layouts they depend on.
+//
+// Every `ErrorCode` returned by the program is exercised except:
+// - `UsdcNotEqRedeem`, which is not used.
+
+mod program_test;
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::hash::hashv;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program_option::COption;
+use anchor_lang::solana_program::slot_hashes::MAX_ENTRIES;
+use anchor_lang::solana_program::{ed25519_program, system_program, sysvar};
+use anchor_lang::InstructionData;
+use anchor_spl::associated_token::{self, get_associated_token_address};
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signer};
+use ido_pool::{
+    blocklist_entry_address, kyc_attestation_message, lottery_commitment, lottery_rank,
+    stake_authority_address, BlocklistEntry, ErrorCode, GateKind, HaltVote, Lottery, LotteryTicket,
+    ParticipationGate, PayoutQuote, PoolAccount, PoolSchedule, PoolStatus, ProceedsTranches,
+    Referrer, StakeTier, StakeTiers, Tranche, UserPosition, CLAIM_PERIOD_SEC,
+    LOTTERY_DRAW_DELAY_SLOTS, MAX_STAKE_TIERS, MAX_TRANCHES, POOL_ACCOUNT_VERSION,
+    PROCEEDS_TIMELOCK_SEC,
+};
+use ido_pool_client::{position_stash_address, DistributionRecipient, Pool};
+use program_test::{ProgramTest, LAMPORTS_PER_SOL};
+use spl_token_metadata::state::Metadata;
+use std::str::FromStr;
+
+const DAY: i64 = 24 * 60 * 60;
+const HOUR: i64 = 60 * 60;
+
+// 1000 watermelon (9 decimals) for sale against USDC (6 decimals).
+const NUM_IDO_TOKENS: u64 = 1_000_000_000_000;
+const USDC: u64 = 1_000_000;
+const WATERMELON: u64 = 1_000_000_000;
+
+fn ix(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: ido_pool::ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+fn assert_error(result: ProgramResult, err: ErrorCode) {
+    assert_eq!(result, Err(err.into()));
+}
+
+// Deterministic ed25519 keypair, e.g. of a KYC verifier.
+fn ed25519_keypair(seed: u8) -> Keypair {
+    let secret = SecretKey::from_bytes(&[seed; 32]).unwrap();
+    let public = PublicKey::from(&secret);
+    Keypair { secret, public }
+}
+
+// Ed25519 program instruction checking `signer`'s signature of `message`.
+fn ed25519_instruction(signer: &Keypair, message: &[u8]) -> Instruction {
+    let public_key_offset: u16 = 2 + 14;
+    let signature_offset = public_key_offset + 32;
+    let message_offset = signature_offset + 64;
+    let mut data = vec![1, 0];
+    for offset in [
+        signature_offset,
+        u16::MAX,
+        public_key_offset,
+        u16::MAX,
+        message_offset,
+        message.len() as u16,
+        u16::MAX,
+    ] {
+        data.extend_from_slice(&offset.to_le_bytes());
+    }
+    data.extend_from_slice(&signer.public.to_bytes());
+    data.extend_from_slice(&signer.sign(message).to_bytes());
+    data.extend_from_slice(message);
+    Instruction {
+        program_id: ed25519_program::ID,
+        accounts: vec![],
+        data,
+    }
+}
+
+struct User {
+    authority: Pubkey,
+    usdc: Pubkey,
+    redeemable: Pubkey,
+    watermelon: Pubkey,
+}
+
+struct Env {
+    test: ProgramTest,
+    deployer: Pubkey,
+    authority: Pubkey,
+    usdc_mint: Pubkey,
+    watermelon_mint: Pubkey,
+    redeemable_mint: Pubkey,
+    redeemable_metadata: Pubkey,
+    pool_account: Pubkey,
+    // Where the pool was created, see `PoolAccount::origin`.
+    origin: Pubkey,
+    pool_signer: Pubkey,
+    nonce: u8,
+    pool_usdc: Pubkey,
+    pool_watermelon: Pubkey,
+    creator_usdc: Pubkey,
+    creator_watermelon: Pubkey,
+    schedule: PoolSchedule,
+    sale_symbol: &'static str,
+}
+
+impl Env {
+    // Accounts of a pool that has not been initialized yet. The IDO starts
+    // in two days, deposits last a day followed by an hour of grace period
+    // and watermelon can be claimed a day later.
+    fn new() -> Self {
+        let mut test = ProgramTest::new();
+        let deployer = Pubkey::from_str(ido_pool::ALLOWED_DEPLOYER).unwrap();
+        test.fund(&deployer, 10 * LAMPORTS_PER_SOL);
+        let authority = Pubkey::new_unique();
+
+        let usdc_mint = test.create_mint(&Pubkey::new_unique(), 6);
+        let watermelon_mint = test.create_mint(&Pubkey::new_unique(), 9);
+        let (pool_signer, nonce) =
+            Pubkey::find_program_address(&[watermelon_mint.as_ref()], &ido_pool::ID);
+        let creator_usdc = test.create_token_account(&usdc_mint, &deployer, 0);
+        let creator_watermelon =
+            test.create_token_account(&watermelon_mint, &deployer, 2 * NUM_IDO_TOKENS);
+
+        let pool_account = Pubkey::new_unique();
+        let redeemable_mint = Pubkey::find_program_address(
+            &[b"redeemable_mint".as_ref(), pool_account.as_ref()],
+            &ido_pool::ID,
+        )
+        .0;
+        let pool_usdc = Pubkey::find_program_address(
+            &[b"pool_usdc".as_ref(), pool_account.as_ref()],
+            &ido_pool::ID,
+        )
+        .0;
+        let pool_watermelon = Pubkey::find_program_address(
+            &[b"pool_watermelon".as_ref(), pool_account.as_ref()],
+            &ido_pool::ID,
+        )
+        .0;
+        let redeemable_metadata = Pubkey::find_program_address(
+            &[
+                b"metadata".as_ref(),
+                spl_token_metadata::ID.as_ref(),
+                redeemable_mint.as_ref(),
+            ],
+            &spl_token_metadata::ID,
+        )
+        .0;
+        let start_ido_ts = test.now() + 2 * DAY;
+        let schedule = PoolSchedule::new(
+            start_ido_ts,
+            start_ido_ts + DAY,
+            Some(HOUR),
+            start_ido_ts + 2 * DAY,
+        );
+
+        Env {
+            test,
+            deployer,
+            authority,
+            usdc_mint,
+            watermelon_mint,
+            redeemable_mint,
+            redeemable_metadata,
+            pool_account,
+            origin: pool_account,
+            pool_signer,
+            nonce,
+            pool_usdc,
+            pool_watermelon,
+            creator_usdc,
+            creator_watermelon,
+            schedule,
+            sale_symbol: "melon",
+        }
+    }
+
+    fn initialized() -> Self {
+        let mut env = Env::new();
+        env.initialize(NUM_IDO_TOKENS, env.nonce, env.schedule, env.deployer)
+            .unwrap();
+        env
+    }
+
+    fn initialize(
+        &mut self,
+        num_ido_tokens: u64,
+        nonce: u8,
+        schedule: PoolSchedule,
+        payer: Pubkey,
+    ) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::InitializePool {
+                pool_account: self.pool_account,
+                pool_signer: self.pool_signer,
+                usdc_mint: self.usdc_mint,
+                redeemable_mint: self.redeemable_mint,
+                redeemable_metadata: self.redeemable_metadata,
+                watermelon_mint: self.watermelon_mint,
+                pool_watermelon: self.pool_watermelon,
+                pool_usdc: self.pool_usdc,
+                distribution_authority: self.authority,
+                payer,
+                creator_watermelon: self.creator_watermelon,
+                creator_usdc: self.creator_usdc,
+                token_program: spl_token::ID,
+                token_metadata_program: spl_token_metadata::ID,
+                rent: sysvar::rent::ID,
+                clock: sysvar::clock::ID,
+                system_program: system_program::ID,
+            },
+            ido_pool::instruction::InitializePool {
+                num_ido_tokens,
+                nonce,
+                schedule,
+                sale_symbol: self.sale_symbol.to_string(),
+            },
+        );
+        self.test
+            .process(ix, &[&self.pool_account, &self.authority, &payer])
+    }
+
+    fn pool(&self) -> PoolAccount {
+        self.test.account(&self.pool_account)
+    }
+
+    // Gives the pool the `data` of an older layout, back at the address it
+    // was created at like every pool before `migrate_pool` moved them.
+    fn set_legacy_pool(&mut self, data: Vec<u8>) {
+        if self.pool_account != self.origin {
+            self.test.move_account(&self.pool_account, &self.origin);
+            self.pool_account = self.origin;
+        }
+        self.test.set_data(&self.origin, data);
+    }
+
+    // The pool as seen by `ido_pool_client`, whatever its layout version.
+    fn client(&self) -> Pool {
+        Pool::from_account_data(self.pool_account, self.test.data(&self.pool_account)).unwrap()
+    }
+
+    fn warp_to_phase(&mut self, status: PoolStatus) {
+        let schedule = self.schedule;
+        let ts = match status {
+            PoolStatus::Scheduled => schedule.start_ido_ts,
+            PoolStatus::Depositing => schedule.start_ido_ts + 1,
+            PoolStatus::Grace => schedule.end_deposits_ts,
+            PoolStatus::Settling => schedule.end_ido_ts,
+            _ => schedule.withdraw_melon_ts + 1,
+        };
+        assert_eq!(schedule.phase(ts), status);
+        self.test.warp_to(ts);
+    }
+
+    fn user(&mut self, usdc: u64) -> User {
+        let authority = Pubkey::new_unique();
+        self.test.fund(&authority, LAMPORTS_PER_SOL);
+        User {
+            authority,
+            usdc: self
+                .test
+                .create_token_account(&self.usdc_mint, &authority, usdc),
+            redeemable: self
+                .test
+                .create_token_account(&self.redeemable_mint, &authority, 0),
+            watermelon: self
+                .test
+                .create_token_account(&self.watermelon_mint, &authority, 0),
+        }
+    }
+
+    // A signer other than the allowed deployer.
+    fn impostor(&mut self) -> Pubkey {
+        let impostor = Pubkey::new_unique();
+        self.test.fund(&impostor, LAMPORTS_PER_SOL);
+        impostor
+    }
+
+    fn position_address(&self, user: &User) -> Pubkey {
+        Pubkey::find_program_address(
+            &[
+                b"position".as_ref(),
+                self.origin.as_ref(),
+                user.authority.as_ref(),
+            ],
+            &ido_pool::ID,
+        )
+        .0
+    }
+
+    fn referrer_address(&self, referrer: &User) -> Pubkey {
+        Pubkey::find_program_address(
+            &[
+                b"referrer".as_ref(),
+                self.origin.as_ref(),
+                referrer.authority.as_ref(),
+            ],
+            &ido_pool::ID,
+        )
+        .0
+    }
+
+    fn deposit(&mut self, user: &User, amount: u64, referrer: Option<&User>) -> ProgramResult {
+        let ix = self.deposit_ix(user, amount, &[], referrer);
+        self.test.process(ix, &[&user.authority])
+    }
+
+    // Deposits with the KYC attestation of `verifier` allowing `user` to
+    // deposit up to `max_allocation` until `expiry`.
+    fn kyc_deposit(
+        &mut self,
+        user: &User,
+        amount: u64,
+        verifier: &Keypair,
+        expiry: i64,
+        max_allocation: u64,
+    ) -> ProgramResult {
+        let message =
+            kyc_attestation_message(&self.origin, &user.authority, expiry, max_allocation);
+        let attestation = ed25519_instruction(verifier, &message);
+        let deposit = self.deposit_ix(user, amount, &[], None);
+        self.test
+            .process_transaction(&[attestation, deposit], &[&user.authority])
+    }
+
+    // Deposits in a gated pool with the accounts proving `user` passes it.
+    fn gated_deposit(
+        &mut self,
+        user: &User,
+        amount: u64,
+        gate_proof: &[Pubkey],
+        referrer: Option<&User>,
+    ) -> ProgramResult {
+        let ix = self.deposit_ix(user, amount, gate_proof, referrer);
+        self.test.process(ix, &[&user.authority])
+    }
+
+    // Built by the client, so the remaining accounts come in the order it
+    // documents: gate proofs first, then the referrer.
+    fn deposit_ix(
+        &self,
+        user: &User,
+        amount: u64,
+        gate_proof: &[Pubkey],
+        referrer: Option<&User>,
+    ) -> Instruction {
+        self.client().deposit(
+            &user.authority,
+            &user.usdc,
+            &user.redeemable,
+            amount,
+            gate_proof,
+            referrer.map(|referrer| &referrer.authority),
+        )
+    }
+
+    fn refund(&mut self, user: &User, amount: u64) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::ExchangeRedeemableForUsdc {
+                pool_account: self.pool_account,
+                pool_signer: self.pool_signer,
+                redeemable_mint: self.redeemable_mint,
+                pool_usdc: self.pool_usdc,
+                user_authority: user.authority,
+                user_usdc: user.usdc,
+                user_redeemable: user.redeemable,
+                token_program: spl_token::ID,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::ExchangeRedeemableForUsdc { amount },
+        );
+        self.test.process(ix, &[&user.authority])
+    }
+
+    fn redeem(&mut self, user: &User, amount: u64) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::ExchangeRedeemableForWatermelon {
+                pool_account: self.pool_account,
+                pool_signer: self.pool_signer,
+                redeemable_mint: self.redeemable_mint,
+                pool_watermelon: self.pool_watermelon,
+                watermelon_mint: self.watermelon_mint,
+                user_authority: user.authority,
+                user_watermelon: user.watermelon,
+                user_redeemable: user.redeemable,
+                token_program: spl_token::ID,
+                clock: sysvar::clock::ID,
+                system_program: system_program::ID,
+                associated_token_program: associated_token::ID,
+                rent: sysvar::rent::ID,
+                blocklist_entry: blocklist_entry_address(&user.authority),
+            },
+            ido_pool::instruction::ExchangeRedeemableForWatermelon { amount },
+        );
+        self.test.process(ix, &[&user.authority])
+    }
+
+    // Built by the client, one group of remaining accounts per user.
+    fn distribute(&mut self, cranker_watermelon: Pubkey, users: &[&User]) -> ProgramResult {
+        let recipients = users
+            .iter()
+            .map(|user| DistributionRecipient {
+                user: user.authority,
+                user_redeemable: user.redeemable,
+                user_watermelon: user.watermelon,
+            })
+            .collect::<Vec<_>>();
+        let ix = self.client().distribute(&cranker_watermelon, &recipients);
+        self.test.process(ix, &[])
+    }
+
+    fn withdraw_usdc(&mut self, payer: Pubkey, amount: u64) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::WithdrawPoolUsdc {
+                pool_account: self.pool_account,
+                pool_signer: self.pool_signer,
+                pool_usdc: self.pool_usdc,
+                distribution_authority: self.authority,
+                payer,
+                creator_usdc: self.creator_usdc,
+                token_program: spl_token::ID,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::WithdrawPoolUsdc { amount },
+        );
+        self.test.process(ix, &[&self.authority, &payer])
+    }
+
+    fn withdraw_watermelon(&mut self, payer: Pubkey, amount: u64) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::WithdrawPoolWatermelon {
+                pool_account: self.pool_account,
+                pool_signer: self.pool_signer,
+                redeemable_mint: self.redeemable_mint,
+                pool_watermelon: self.pool_watermelon,
+                distribution_authority: self.authority,
+                payer,
+                creator_watermelon: self.creator_watermelon,
+                token_program: spl_token::ID,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::WithdrawPoolWatermelon { amount },
+        );
+        self.test.process(ix, &[&self.authority, &payer])
+    }
+
+    fn modify_ido_time(&mut self, payer: Pubkey, schedule: PoolSchedule) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::ModifyIdoTime {
+                pool_account: self.pool_account,
+                distribution_authority: self.authority,
+                payer,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::ModifyIdoTime { schedule },
+        );
+        self.test.process(ix, &[&self.authority, &payer])
+    }
+
+    fn halt_vote_address(&self, tranche: u8) -> Pubkey {
+        Pubkey::find_program_address(
+            &[b"halt_vote".as_ref(), self.origin.as_ref(), &[tranche]],
+            &ido_pool::ID,
+        )
+        .0
+    }
+
+    fn vote_halt(&mut self, user: &User, tranche: u8) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::VoteHalt {
+                pool_account: self.pool_account,
+                halt_vote: self.halt_vote_address(tranche),
+                user_position: self.position_address(user),
+                user_authority: user.authority,
+                clock: sysvar::clock::ID,
+                system_program: system_program::ID,
+            },
+            ido_pool::instruction::VoteHalt { tranche },
+        );
+        self.test.process(ix, &[&user.authority])
+    }
+
+    fn claim_halt_refund(&mut self, user: &User) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::ClaimHaltRefund {
+                pool_account: self.pool_account,
+                pool_signer: self.pool_signer,
+                user_position: self.position_address(user),
+                redeemable_mint: self.redeemable_mint,
+                pool_usdc: self.pool_usdc,
+                pool_watermelon: self.pool_watermelon,
+                user_authority: user.authority,
+                user_usdc: user.usdc,
+                user_redeemable: user.redeemable,
+                creator_watermelon: self.creator_watermelon,
+                token_program: spl_token::ID,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::ClaimHaltRefund {},
+        );
+        self.test.process(ix, &[&user.authority])
+    }
+
+    fn configure_tranches(
+        &mut self,
+        tranches: [Tranche; MAX_TRANCHES],
+        payer: Pubkey,
+    ) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::ConfigureTranches {
+                pool_account: self.pool_account,
+                distribution_authority: self.authority,
+                payer,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::ConfigureTranches { tranches },
+        );
+        self.test.process(ix, &[&self.authority, &payer])
+    }
+
+    fn configure_referrals(
+        &mut self,
+        referral_bps: u16,
+        referral_watermelon: u64,
+        payer: Pubkey,
+    ) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::ConfigureReferrals {
+                pool_account: self.pool_account,
+                pool_watermelon: self.pool_watermelon,
+                distribution_authority: self.authority,
+                payer,
+                creator_watermelon: self.creator_watermelon,
+                token_program: spl_token::ID,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::ConfigureReferrals {
+                referral_bps,
+                referral_watermelon,
+            },
+        );
+        self.test.process(ix, &[&self.authority, &payer])
+    }
+
+    fn configure_kyc(&mut self, kyc_verifier: Pubkey, payer: Pubkey) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::ConfigureKyc {
+                pool_account: self.pool_account,
+                distribution_authority: self.authority,
+                payer,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::ConfigureKyc { kyc_verifier },
+        );
+        self.test.process(ix, &[&self.authority, &payer])
+    }
+
+    fn configure_gate(&mut self, gate: ParticipationGate, payer: Pubkey) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::ConfigureGate {
+                pool_account: self.pool_account,
+                distribution_authority: self.authority,
+                payer,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::ConfigureGate { gate },
+        );
+        self.test.process(ix, &[&self.authority, &payer])
+    }
+
+    fn configure_stake_tiers(&mut self, stake_tiers: StakeTiers, payer: Pubkey) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::ConfigureStakeTiers {
+                pool_account: self.pool_account,
+                distribution_authority: self.authority,
+                payer,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::ConfigureStakeTiers { stake_tiers },
+        );
+        self.test.process(ix, &[&self.authority, &payer])
+    }
+
+    fn register_stake(&mut self, user: &User, stake_vault: Pubkey) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::RegisterStake {
+                pool_account: self.pool_account,
+                user_authority: user.authority,
+                user_position: self.position_address(user),
+                stake_vault,
+                clock: sysvar::clock::ID,
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+            },
+            ido_pool::instruction::RegisterStake {},
+        );
+        self.test.process(ix, &[&user.authority])
+    }
+
+    fn migrate_position(&mut self, user_position: Pubkey, payer: Pubkey) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::MigratePosition {
+                user_position,
+                position_stash: position_stash_address(&user_position),
+                payer,
+                rent: sysvar::rent::ID,
+                system_program: system_program::ID,
+            },
+            ido_pool::instruction::MigratePosition {},
+        );
+        self.test.process(ix, &[&payer])
+    }
+
+    fn configure_lottery(
+        &mut self,
+        ticket_usdc: u64,
+        winning_tickets: u64,
+        commitment: [u8; 32],
+        payer: Pubkey,
+    ) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::ConfigureLottery {
+                pool_account: self.pool_account,
+                distribution_authority: self.authority,
+                payer,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::ConfigureLottery {
+                ticket_usdc,
+                winning_tickets,
+                commitment,
+            },
+        );
+        self.test.process(ix, &[&self.authority, &payer])
+    }
+
+    fn ticket_address(&self, user: &User) -> Pubkey {
+        Pubkey::find_program_address(
+            &[
+                b"ticket".as_ref(),
+                self.origin.as_ref(),
+                user.authority.as_ref(),
+            ],
+            &ido_pool::ID,
+        )
+        .0
+    }
+
+    fn buy_ticket(&mut self, user: &User) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::BuyTicket {
+                pool_account: self.pool_account,
+                pool_usdc: self.pool_usdc,
+                user_authority: user.authority,
+                user_usdc: user.usdc,
+                ticket: self.ticket_address(user),
+                token_program: spl_token::ID,
+                clock: sysvar::clock::ID,
+                system_program: system_program::ID,
+                instructions: sysvar::instructions::ID,
+                blocklist_entry: blocklist_entry_address(&user.authority),
+            },
+            ido_pool::instruction::BuyTicket {},
+        );
+        self.test.process(ix, &[&user.authority])
+    }
+
+    fn commit_lottery_draw(&mut self) -> ProgramResult {
+        let ix = self.client().commit_lottery_draw();
+        self.test.process(ix, &[&self.authority])
+    }
+
+    fn draw_lottery(&mut self, secret: [u8; 32]) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::DrawLottery {
+                pool_account: self.pool_account,
+                distribution_authority: self.authority,
+                slot_hashes: sysvar::slot_hashes::ID,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::DrawLottery { secret },
+        );
+        self.test.process(ix, &[&self.authority])
+    }
+
+    fn claim_ticket(&mut self, user: &User) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::ClaimTicket {
+                pool_account: self.pool_account,
+                pool_signer: self.pool_signer,
+                redeemable_mint: self.redeemable_mint,
+                user_authority: user.authority,
+                user_redeemable: user.redeemable,
+                user_position: self.position_address(user),
+                ticket: self.ticket_address(user),
+                token_program: spl_token::ID,
+                clock: sysvar::clock::ID,
+                system_program: system_program::ID,
+                associated_token_program: associated_token::ID,
+                rent: sysvar::rent::ID,
+                blocklist_entry: blocklist_entry_address(&user.authority),
+            },
+            ido_pool::instruction::ClaimTicket {},
+        );
+        self.test.process(ix, &[&user.authority])
+    }
+
+    fn refund_ticket(&mut self, user: &User) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::RefundTicket {
+                pool_account: self.pool_account,
+                pool_signer: self.pool_signer,
+                pool_usdc: self.pool_usdc,
+                user_authority: user.authority,
+                user_usdc: user.usdc,
+                ticket: self.ticket_address(user),
+                token_program: spl_token::ID,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::RefundTicket {},
+        );
+        self.test.process(ix, &[&user.authority])
+    }
+
+    // Mints a token with `decimals` to `user` whose metadata lists it in
+    // `collection`, verified or not. The token metadata program used here
+    // predates collections, the collection is written where later versions
+    // put it. Returns the user's token account, the mint and the metadata.
+    fn create_nft(
+        &mut self,
+        user: &User,
+        collection: Pubkey,
+        verified: bool,
+        decimals: u8,
+    ) -> (Pubkey, Pubkey, Pubkey) {
+        let update_authority = Pubkey::new_unique();
+        self.test.fund(&update_authority, LAMPORTS_PER_SOL);
+        let mint = self.test.create_mint(&update_authority, decimals);
+        let token = self.test.create_token_account(&mint, &user.authority, 1);
+        let metadata = Pubkey::find_program_address(
+            &[
+                b"metadata".as_ref(),
+                spl_token_metadata::ID.as_ref(),
+                mint.as_ref(),
+            ],
+            &spl_token_metadata::ID,
+        )
+        .0;
+        let mut ix = spl_token_metadata::instruction::create_metadata_accounts(
+            spl_token_metadata::ID,
+            metadata,
+            mint,
+            update_authority,
+            update_authority,
+            update_authority,
+            "Melon Friend".to_string(),
+            "MF".to_string(),
+            String::new(),
+            None,
+            0,
+            true,
+            false,
+        );
+        // The payer is read-only in the instruction but writable as the fee
+        // payer of the transaction.
+        for meta in ix.accounts.iter_mut() {
+            if meta.pubkey == update_authority {
+                meta.is_writable = true;
+            }
+        }
+        self.test.process(ix, &[&update_authority]).unwrap();
+
+        // No token standard, then the collection.
+        let mut data = self.test.data(&metadata).to_vec();
+        let mut rest: &[u8] = &data;
+        Metadata::deserialize(&mut rest).unwrap();
+        let offset = data.len() - rest.len();
+        data[offset + 1] = 1;
+        data[offset + 2] = verified as u8;
+        data[offset + 3..offset + 35].copy_from_slice(collection.as_ref());
+        self.test.set_data(&metadata, data);
+        (token, mint, metadata)
+    }
+
+    fn block(&mut self, wallet: Pubkey, payer: Pubkey) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::BlockAddress {
+                blocklist_entry: blocklist_entry_address(&wallet),
+                wallet,
+                payer,
+                system_program: system_program::ID,
+            },
+            ido_pool::instruction::BlockAddress {},
+        );
+        self.test.process(ix, &[&payer])
+    }
+
+    fn unblock(&mut self, wallet: Pubkey, payer: Pubkey) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::UnblockAddress {
+                blocklist_entry: blocklist_entry_address(&wallet),
+                wallet,
+                payer,
+            },
+            ido_pool::instruction::UnblockAddress {},
+        );
+        self.test.process(ix, &[&payer])
+    }
+
+    fn register_referrer(&mut self, referrer: &User) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::RegisterReferrer {
+                pool_account: self.pool_account,
+                referrer: self.referrer_address(referrer),
+                referrer_authority: referrer.authority,
+                clock: sysvar::clock::ID,
+                system_program: system_program::ID,
+            },
+            ido_pool::instruction::RegisterReferrer {},
+        );
+        self.test.process(ix, &[&referrer.authority])
+    }
+
+    fn claim_referral_reward(&mut self, referrer: &User) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::ClaimReferralReward {
+                pool_account: self.pool_account,
+                pool_signer: self.pool_signer,
+                referrer: self.referrer_address(referrer),
+                referrer_authority: referrer.authority,
+                pool_usdc: self.pool_usdc,
+                pool_watermelon: self.pool_watermelon,
+                referrer_usdc: referrer.usdc,
+                referrer_watermelon: referrer.watermelon,
+                token_program: spl_token::ID,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::ClaimReferralReward {},
+        );
+        self.test.process(ix, &[&referrer.authority])
+    }
+
+    fn quote(
+        &mut self,
+        data: impl InstructionData,
+    ) -> std::result::Result<PayoutQuote, ProgramError> {
+        let ix = ix(
+            ido_pool::accounts::QuotePayout {
+                pool_account: self.pool_account,
+                redeemable_mint: self.redeemable_mint,
+                pool_watermelon: self.pool_watermelon,
+                clock: sysvar::clock::ID,
+            },
+            data,
+        );
+        self.test.process(ix, &[])?;
+        let (program_id, data) = self.test.return_data().unwrap();
+        assert_eq!(program_id, ido_pool::ID);
+        Ok(PayoutQuote::try_from_slice(&data).unwrap())
+    }
+
+    fn quote_deposit(&mut self, amount: u64) -> std::result::Result<PayoutQuote, ProgramError> {
+        self.quote(ido_pool::instruction::QuoteDeposit { amount })
+    }
+
+    fn quote_redemption(&mut self, amount: u64) -> std::result::Result<PayoutQuote, ProgramError> {
+        self.quote(ido_pool::instruction::QuoteRedemption { amount })
+    }
+
+    fn advance_status(&mut self) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::AdvancePoolStatus {
+                pool_account: self.pool_account,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::AdvancePoolStatus {},
+        );
+        self.test.process(ix, &[])
+    }
+
+    fn cancel(&mut self, payer: Pubkey) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::CancelPool {
+                pool_account: self.pool_account,
+                distribution_authority: self.authority,
+                payer,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::CancelPool {},
+        );
+        self.test.process(ix, &[&self.authority, &payer])
+    }
+
+    fn close(&mut self, payer: Pubkey) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::ClosePool {
+                pool_account: self.pool_account,
+                redeemable_mint: self.redeemable_mint,
+                pool_usdc: self.pool_usdc,
+                distribution_authority: self.authority,
+                payer,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::ClosePool {},
+        );
+        self.test.process(ix, &[&self.authority, &payer])
+    }
+
+    fn proceeds_change_address(&self) -> Pubkey {
+        Pubkey::find_program_address(
+            &[b"proceeds_change".as_ref(), self.origin.as_ref()],
+            &ido_pool::ID,
+        )
+        .0
+    }
+
+    fn propose_proceeds(
+        &mut self,
+        payer: Pubkey,
+        creator_usdc: Pubkey,
+        creator_watermelon: Pubkey,
+    ) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::ProposeProceedsAccounts {
+                pool_account: self.pool_account,
+                proceeds_change: self.proceeds_change_address(),
+                pool_usdc: self.pool_usdc,
+                creator_usdc,
+                creator_watermelon,
+                distribution_authority: self.authority,
+                payer,
+                rent: sysvar::rent::ID,
+                clock: sysvar::clock::ID,
+                system_program: system_program::ID,
+            },
+            ido_pool::instruction::ProposeProceedsAccounts {},
+        );
+        self.test.process(ix, &[&self.authority, &payer])
+    }
+
+    fn apply_proceeds(&mut self, payer: Pubkey) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::ApplyProceedsAccounts {
+                pool_account: self.pool_account,
+                proceeds_change: self.proceeds_change_address(),
+                distribution_authority: self.authority,
+                payer,
+                clock: sysvar::clock::ID,
+            },
+            ido_pool::instruction::ApplyProceedsAccounts {},
+        );
+        self.test.process(ix, &[&self.authority, &payer])
+    }
+
+    fn cancel_proceeds(&mut self, payer: Pubkey) -> ProgramResult {
+        let ix = ix(
+            ido_pool::accounts::CancelProceedsAccounts {
+                pool_account: self.pool_account,
+                proceeds_change: self.proceeds_change_address(),
+                distribution_authority: self.authority,
+                payer,
+            },
+            ido_pool::instruction::CancelProceedsAccounts {},
+        );
+        self.test.process(ix, &[&self.authority, &payer])
+    }
+
+    fn migrate(&mut self, payer: Pubkey) -> ProgramResult {
+        let client = self.client();
+        let ix = client.migrate(
+            &payer,
+            &self.usdc_mint,
+            &self.creator_usdc,
+            &self.creator_watermelon,
+        );
+        self.test.process(ix, &[&self.authority, &payer])?;
+        self.pool_account = client.migrated_address();
+        Ok(())
+    }
+}
+
+#[test]
+fn initialize_pool() {
+    let env = Env::initialized();
+
+    let pool = env.pool();
+    assert_eq!(pool.redeemable_mint, env.redeemable_mint);
+    assert_eq!(pool.pool_usdc, env.pool_usdc);
+    assert_eq!(pool.pool_watermelon, env.pool_watermelon);
+    assert_eq!(pool.creator_usdc, env.creator_usdc);
+    assert_eq!(pool.creator_watermelon, env.creator_watermelon);
+    assert_eq!(pool.watermelon_mint, env.watermelon_mint);
+    assert_eq!(pool.distribution_authority, env.authority);
+    assert_eq!(pool.nonce, env.nonce);
+    assert_eq!(pool.num_ido_tokens, NUM_IDO_TOKENS);
+    assert_eq!(pool.schedule, env.schedule);
+    assert_eq!(
+        pool.original_withdraw_melon_ts,
+        env.schedule.withdraw_melon_ts
+    );
+    assert_eq!(pool.status, PoolStatus::Scheduled);
+    assert_eq!(env.test.balance(&env.pool_watermelon), NUM_IDO_TOKENS);
+    let pool_usdc = env.test.token_account(&env.pool_usdc);
+    assert_eq!(pool_usdc.mint, env.usdc_mint);
+    assert_eq!(pool_usdc.owner, env.pool_signer);
+    let pool_watermelon = env.test.token_account(&env.pool_watermelon);
+    assert_eq!(pool_watermelon.mint, env.watermelon_mint);
+    assert_eq!(pool_watermelon.owner, env.pool_signer);
+    assert_eq!(env.test.balance(&env.creator_watermelon), NUM_IDO_TOKENS);
+    assert_eq!(pool.tranches, ProceedsTranches::default());
+
+    let redeemable_mint = env.test.mint(&env.redeemable_mint);
+    assert_eq!(
+        redeemable_mint.mint_authority,
+        COption::Some(env.pool_signer)
+    );
+    assert_eq!(redeemable_mint.decimals, 6);
+    assert_eq!(redeemable_mint.supply, 0);
+
+    let metadata = Metadata::deserialize(&mut env.test.data(&env.redeemable_metadata)).unwrap();
+    assert_eq!(metadata.mint, env.redeemable_mint);
+    assert_eq!(metadata.update_authority, env.authority);
+    assert_eq!(
+        metadata.data.name.trim_end_matches('\0'),
+        "MELON IDO Redeemable"
+    );
+    assert_eq!(metadata.data.symbol.trim_end_matches('\0'), "rMELON");
+}
+
+#[test]
+fn initialize_pool_rejects_invalid_params() {
+    let mut env = Env::new();
+    let (nonce, schedule, deployer) = (env.nonce, env.schedule, env.deployer);
+    let now = env.test.now();
+
+    for start_ido_ts in [now - 1, now] {
+        let started = PoolSchedule {
+            start_ido_ts,
+            ..schedule
+        };
+        assert_error(
+            env.initialize(NUM_IDO_TOKENS, nonce, started, deployer),
+            ErrorCode::IdoFuture,
+        );
+    }
+
+    let unordered = PoolSchedule {
+        end_ido_ts: schedule.end_deposits_ts - 1,
+        ..schedule
+    };
+    assert_error(
+        env.initialize(NUM_IDO_TOKENS, nonce, unordered, deployer),
+        ErrorCode::SeqTimes,
+    );
+
+    assert_error(
+        env.initialize(0, nonce, schedule, deployer),
+        ErrorCode::InvalidParam,
+    );
+
+    let impostor = env.impostor();
+    assert_error(
+        env.initialize(NUM_IDO_TOKENS, nonce, schedule, impostor),
+        ErrorCode::InvalidParam,
+    );
+
+    assert_error(
+        env.initialize(NUM_IDO_TOKENS, nonce.wrapping_sub(1), schedule, deployer),
+        ErrorCode::InvalidNonce,
+    );
+
+    // The redeemable symbol would not fit in the token metadata.
+    env.sale_symbol = "WATERMELON";
+    assert_error(
+        env.initialize(NUM_IDO_TOKENS, nonce, schedule, deployer),
+        ErrorCode::InvalidParam,
+    );
+    env.sale_symbol = "melon";
+
+    assert!(!env.test.exists(&env.pool_account));
+    env.initialize(NUM_IDO_TOKENS, nonce, schedule, deployer)
+        .unwrap();
+}
+
+#[test]
+fn deposits_are_only_accepted_while_depositing() {
+    let mut env = Env::initialized();
+    let user = env.user(1_000 * USDC);
+
+    assert_error(
+        env.deposit(&user, 100 * USDC, None),
+        ErrorCode::StartIdoTime,
+    );
+
+    env.warp_to_phase(PoolStatus::Depositing);
+    assert_error(env.deposit(&user, 0, None), ErrorCode::InvalidParam);
+    assert_error(env.deposit(&user, 1_001 * USDC, None), ErrorCode::LowUsdc);
+    env.deposit(&user, 600 * USDC, None).unwrap();
+    env.deposit(&user, 400 * USDC, None).unwrap();
+
+    assert_eq!(env.test.balance(&user.usdc), 0);
+    assert_eq!(env.test.balance(&user.redeemable), 1_000 * USDC);
+    assert_eq!(env.test.balance(&env.pool_usdc), 1_000 * USDC);
+    assert_eq!(env.pool().total_usdc_deposited, 1_000 * USDC);
+    let position: UserPosition = env.test.account(&env.position_address(&user));
+    assert_eq!(position.owner, user.authority);
+    assert_eq!(position.usdc_deposited, 1_000 * USDC);
+
+    let late = env.user(100 * USDC);
+    env.warp_to_phase(PoolStatus::Grace);
+    assert_error(
+        env.deposit(&late, 100 * USDC, None),
+        ErrorCode::EndDepositsTime,
+    );
+    env.warp_to_phase(PoolStatus::Settling);
+    assert_error(
+        env.deposit(&late, 100 * USDC, None),
+        ErrorCode::EndDepositsTime,
+    );
+}
+
+#[test]
+fn redeemable_cannot_be_exchanged_back_for_usdc() {
+    let mut env = Env::initialized();
+    let user = env.user(100 * USDC);
+
+    assert_error(env.refund(&user, 100 * USDC), ErrorCode::StartIdoTime);
+
+    env.warp_to_phase(PoolStatus::Depositing);
+    env.deposit(&user, 100 * USDC, None).unwrap();
+    assert_error(
+        env.refund(&user, 100 * USDC),
+        ErrorCode::UsdcWithdrawNotAllowed,
+    );
+
+    env.warp_to_phase(PoolStatus::Grace);
+    assert_error(
+        env.refund(&user, 100 * USDC),
+        ErrorCode::UsdcWithdrawNotAllowed,
+    );
+
+    env.warp_to_phase(PoolStatus::Settling);
+    assert_error(env.refund(&user, 100 * USDC), ErrorCode::EndIdoTime);
+    assert_eq!(env.test.balance(&env.pool_usdc), 100 * USDC);
+}
+
+#[test]
+fn cancelled_pool_refunds_deposits() {
+    let mut env = Env::initialized();
+    let first = env.user(600 * USDC);
+    let second = env.user(400 * USDC);
+    env.warp_to_phase(PoolStatus::Depositing);
+    env.deposit(&first, 600 * USDC, None).unwrap();
+    env.deposit(&second, 400 * USDC, None).unwrap();
+
+    let impostor = env.impostor();
+    assert_error(env.cancel(impostor), ErrorCode::InvalidParam);
+    env.cancel(env.deployer).unwrap();
+    assert_eq!(env.pool().status, PoolStatus::Cancelled);
+    assert_error(env.cancel(env.deployer), ErrorCode::PoolCancelled);
+    assert_error(env.deposit(&first, 0, None), ErrorCode::PoolCancelled);
+
+    assert_error(env.refund(&first, 0), ErrorCode::InvalidParam);
+    assert_error(env.refund(&first, 601 * USDC), ErrorCode::LowRedeemable);
+    env.refund(&first, 600 * USDC).unwrap();
+    assert_eq!(env.test.balance(&first.usdc), 600 * USDC);
+    assert_eq!(env.test.balance(&first.redeemable), 0);
+
+    // Cancellation sticks even once the schedule has run out.
+    env.warp_to_phase(PoolStatus::Claimable);
+    assert_error(env.redeem(&second, 400 * USDC), ErrorCode::PoolCancelled);
+    assert_error(env.close(env.deployer), ErrorCode::InvalidStatusTransition);
+    env.refund(&second, 400 * USDC).unwrap();
+    assert_eq!(env.test.balance(&second.usdc), 400 * USDC);
+
+    let stats = env.pool().stats;
+    assert_eq!(stats.usdc_withdrawn, 1_000 * USDC);
+    assert_eq!(stats.redeemable_burned, 1_000 * USDC);
+
+    assert_error(env.close(impostor), ErrorCode::InvalidParam);
+    env.close(env.deployer).unwrap();
+    assert_eq!(env.pool().status, PoolStatus::Closed);
+    assert_error(env.refund(&second, 1), ErrorCode::PoolClosed);
+    assert_error(env.close(env.deployer), ErrorCode::PoolClosed);
+}
+
+#[test]
+fn redemption_above_floor_price() {
+    let mut env = Env::initialized();
+    let first = env.user(600 * USDC);
+    let second = env.user(1_400 * USDC);
+    env.warp_to_phase(PoolStatus::Depositing);
+    env.deposit(&first, 600 * USDC, None).unwrap();
+    env.deposit(&second, 1_400 * USDC, None).unwrap();
+
+    env.warp_to_phase(PoolStatus::Settling);
+    assert_error(env.redeem(&first, 600 * USDC), ErrorCode::IdoNotOver);
+
+    // 2000 USDC for 1000 watermelon, everything is distributed pro-rata.
+    env.warp_to_phase(PoolStatus::Claimable);
+    assert_error(env.redeem(&first, 0), ErrorCode::InvalidParam);
+    assert_error(env.redeem(&first, 601 * USDC), ErrorCode::LowRedeemable);
+    env.redeem(&first, 200 * USDC).unwrap();
+    env.redeem(&first, 400 * USDC).unwrap();
+    env.redeem(&second, 1_400 * USDC).unwrap();
+
+    assert_eq!(env.test.balance(&first.watermelon), 300 * WATERMELON);
+    assert_eq!(env.test.balance(&second.watermelon), 700 * WATERMELON);
+    assert_eq!(env.test.balance(&first.redeemable), 0);
+    assert_eq!(env.test.balance(&env.pool_watermelon), 0);
+    assert_eq!(env.test.mint(&env.redeemable_mint).supply, 0);
+}
+
+#[test]
+fn user_token_accounts_are_created_if_missing() {
+    let mut env = Env::initialized();
+    let authority = Pubkey::new_unique();
+    env.test.fund(&authority, LAMPORTS_PER_SOL);
+    let usdc = env
+        .test
+        .create_token_account(&env.usdc_mint, &authority, 1_000 * USDC);
+    let user = User {
+        authority,
+        usdc,
+        redeemable: get_associated_token_address(&authority, &env.redeemable_mint),
+        watermelon: get_associated_token_address(&authority, &env.watermelon_mint),
+    };
+
+    // Accounts of the wrong mint are rejected, also when they already exist.
+    env.warp_to_phase(PoolStatus::Depositing);
+    let wrong_mint = User {
+        redeemable: user.usdc,
+        ..user
+    };
+    assert_eq!(
+        env.deposit(&wrong_mint, 500 * USDC, None),
+        Err(anchor_lang::__private::ErrorCode::ConstraintRaw.into())
+    );
+
+    env.deposit(&user, 500 * USDC, None).unwrap();
+    env.deposit(&user, 500 * USDC, None).unwrap();
+    let redeemable = env.test.token_account(&user.redeemable);
+    assert_eq!(redeemable.owner, authority);
+    assert_eq!(redeemable.amount, 1_000 * USDC);
+
+    env.warp_to_phase(PoolStatus::Claimable);
+    env.redeem(&user, 1_000 * USDC).unwrap();
+    let watermelon = env.test.token_account(&user.watermelon);
+    assert_eq!(watermelon.mint, env.watermelon_mint);
+    assert_eq!(watermelon.amount, NUM_IDO_TOKENS);
+}
+
+#[test]
+fn distribution_crank() {
+    let mut env = Env::initialized();
+    let first = env.user(600 * USDC);
+    let second = env.user(1_400 * USDC);
+    let cranker = env.user(0);
+    env.warp_to_phase(PoolStatus::Depositing);
+    env.deposit(&first, 600 * USDC, None).unwrap();
+    env.deposit(&second, 1_400 * USDC, None).unwrap();
+
+    env.warp_to_phase(PoolStatus::Settling);
+    assert_error(
+        env.distribute(cranker.watermelon, &[&first, &second]),
+        ErrorCode::IdoNotOver,
+    );
+
+    // The first user claims part of their tokens, the crank pays out the
+    // rest minus the 10 bps cranker reward.
+    env.warp_to_phase(PoolStatus::Claimable);
+    env.redeem(&first, 200 * USDC).unwrap();
+    env.distribute(cranker.watermelon, &[&first, &second])
+        .unwrap();
+    assert_eq!(
+        env.test.balance(&first.watermelon),
+        100 * WATERMELON + 200 * WATERMELON * 9_990 / 10_000
+    );
+    assert_eq!(
+        env.test.balance(&second.watermelon),
+        700 * WATERMELON * 9_990 / 10_000
+    );
+    assert_eq!(
+        env.test.balance(&cranker.watermelon),
+        900 * WATERMELON / 1_000
+    );
+    assert_eq!(env.test.balance(&first.redeemable), 0);
+    assert_eq!(env.test.balance(&env.pool_watermelon), 0);
+    assert_eq!(env.test.mint(&env.redeemable_mint).supply, 0);
+
+    // Users that have been paid out are skipped.
+    env.distribute(cranker.watermelon, &[&first, &second, &first])
+        .unwrap();
+    assert_eq!(
+        env.test.balance(&cranker.watermelon),
+        900 * WATERMELON / 1_000
+    );
+
+    let mismatched = User {
+        redeemable: second.redeemable,
+        ..first
+    };
+    assert_error(
+        env.distribute(cranker.watermelon, &[&mismatched]),
+        ErrorCode::InvalidDistribution,
+    );
+    // The recipients' token accounts have to be passed writable.
+    let mut read_only = env.client().distribute(
+        &cranker.watermelon,
+        &[DistributionRecipient {
+            user: second.authority,
+            user_redeemable: second.redeemable,
+            user_watermelon: second.watermelon,
+        }],
+    );
+    let watermelon = read_only.accounts.len() - 2;
+    read_only.accounts[watermelon].is_writable = false;
+    assert_error(
+        env.test.process(read_only, &[]),
+        ErrorCode::InvalidDistribution,
+    );
+}
+
+#[test]
+fn redemption_below_floor_price() {
+    let mut env = Env::initialized();
+    let user = env.user(250 * USDC);
+    env.warp_to_phase(PoolStatus::Depositing);
+    env.deposit(&user, 250 * USDC, None).unwrap();
+
+    // 250 USDC for 1000 watermelon is half the floor price of 0.50, so only
+    // half of the tokens are handed out.
+    env.warp_to_phase(PoolStatus::Claimable);
+    env.redeem(&user, 250 * USDC).unwrap();
+    assert_eq!(env.test.balance(&user.watermelon), 500 * WATERMELON);
+    assert_eq!(env.test.balance(&env.pool_watermelon), 500 * WATERMELON);
+}
+
+#[test]
+fn payout_quotes() {
+    let mut env = Env::initialized();
+    let first = env.user(250 * USDC);
+    let second = env.user(1_750 * USDC);
+
+    // Alone in the pool, 250 USDC is below the floor price.
+    let quote = env.quote_deposit(250 * USDC).unwrap();
+    assert_eq!(quote.watermelon_amount, 500 * WATERMELON);
+    assert_eq!(quote.price, 0.25 * 1e9);
+    assert!(quote.floor_applied);
+    assert_error(env.quote_deposit(0).map(|_| ()), ErrorCode::InvalidParam);
+
+    env.warp_to_phase(PoolStatus::Depositing);
+    env.deposit(&first, 250 * USDC, None).unwrap();
+    let quote = env.quote_deposit(1_750 * USDC).unwrap();
+    assert_eq!(quote.watermelon_amount, 875 * WATERMELON);
+    assert_eq!(quote.price, 2.0 * 1e9);
+    assert!(!quote.floor_applied);
+    env.deposit(&second, 1_750 * USDC, None).unwrap();
+
+    env.warp_to_phase(PoolStatus::Grace);
+    assert_error(
+        env.quote_deposit(USDC).map(|_| ()),
+        ErrorCode::EndDepositsTime,
+    );
+    assert_error(
+        env.quote_redemption(2_001 * USDC).map(|_| ()),
+        ErrorCode::LowRedeemable,
+    );
+
+    // Quotes match what the redemption pays out and move no tokens.
+    env.warp_to_phase(PoolStatus::Claimable);
+    let quote = env.quote_redemption(250 * USDC).unwrap();
+    assert_eq!(env.test.balance(&first.redeemable), 250 * USDC);
+    env.redeem(&first, 250 * USDC).unwrap();
+    assert_eq!(env.test.balance(&first.watermelon), quote.watermelon_amount);
+    assert_eq!(quote.watermelon_amount, 125 * WATERMELON);
+}
+
+#[test]
+fn creator_withdrawals() {
+    let mut env = Env::initialized();
+    let user = env.user(1_000 * USDC);
+    env.warp_to_phase(PoolStatus::Depositing);
+    env.deposit(&user, 1_000 * USDC, None).unwrap();
+
+    env.warp_to_phase(PoolStatus::Settling);
+    assert_error(
+        env.withdraw_usdc(env.deployer, 1_000 * USDC),
+        ErrorCode::IdoNotOver,
+    );
+
+    env.warp_to_phase(PoolStatus::Claimable);
+    let impostor = env.impostor();
+    assert_error(
+        env.withdraw_usdc(impostor, 1_000 * USDC),
+        ErrorCode::InvalidParam,
+    );
+    env.withdraw_usdc(env.deployer, 400 * USDC).unwrap();
+    env.withdraw_usdc(env.deployer, 600 * USDC).unwrap();
+    assert_eq!(env.test.balance(&env.creator_usdc), 1_000 * USDC);
+    assert_eq!(env.test.balance(&env.pool_usdc), 0);
+
+    assert_error(
+        env.withdraw_watermelon(impostor, WATERMELON),
+        ErrorCode::InvalidParam,
+    );
+    // Everything was sold, the watermelon belongs to the user until the
+    // claim deadline.
+    assert_error(
+        env.withdraw_watermelon(env.deployer, 100 * WATERMELON),
+        ErrorCode::WithdrawTokensNotAllowed,
+    );
+    let claim_deadline_ts = env.pool().claim_deadline_ts();
+    assert_eq!(
+        claim_deadline_ts,
+        env.schedule.withdraw_melon_ts + CLAIM_PERIOD_SEC
+    );
+    env.test.warp_to(claim_deadline_ts);
+    env.withdraw_watermelon(env.deployer, 100 * WATERMELON)
+        .unwrap();
+    assert_eq!(
+        env.test.balance(&env.creator_watermelon),
+        NUM_IDO_TOKENS + 100 * WATERMELON
+    );
+
+    let stats = env.pool().stats;
+    assert_eq!(stats.creator_usdc_withdrawn, 1_000 * USDC);
+    assert_eq!(stats.creator_watermelon_withdrawn, 100 * WATERMELON);
+}
+
+#[test]
+fn proceeds_accounts_change_after_timelock() {
+    let mut env = Env::initialized();
+    let user = env.user(1_000 * USDC);
+    env.warp_to_phase(PoolStatus::Depositing);
+    env.deposit(&user, 1_000 * USDC, None).unwrap();
+    env.warp_to_phase(PoolStatus::Claimable);
+
+    // Withdrawals only go to the recorded accounts.
+    let (deployer, creator_usdc) = (env.deployer, env.creator_usdc);
+    let new_usdc = env
+        .test
+        .create_token_account(&env.usdc_mint, &Pubkey::new_unique(), 0);
+    let new_watermelon =
+        env.test
+            .create_token_account(&env.watermelon_mint, &Pubkey::new_unique(), 0);
+    env.creator_usdc = new_usdc;
+    assert_eq!(
+        env.withdraw_usdc(deployer, 100 * USDC),
+        Err(anchor_lang::__private::ErrorCode::ConstraintHasOne.into())
+    );
+
+    let impostor = env.impostor();
+    assert_error(
+        env.propose_proceeds(impostor, new_usdc, new_watermelon),
+        ErrorCode::InvalidParam,
+    );
+    assert_eq!(
+        env.propose_proceeds(deployer, new_watermelon, new_usdc),
+        Err(anchor_lang::__private::ErrorCode::ConstraintRaw.into())
+    );
+    // A pending change can be dropped.
+    let mistaken_usdc = env
+        .test
+        .create_token_account(&env.usdc_mint, &Pubkey::new_unique(), 0);
+    env.propose_proceeds(deployer, mistaken_usdc, new_watermelon)
+        .unwrap();
+    assert_error(env.cancel_proceeds(impostor), ErrorCode::InvalidParam);
+    env.cancel_proceeds(deployer).unwrap();
+    assert!(!env.test.exists(&env.proceeds_change_address()));
+    env.test.warp_to(env.test.now() + PROCEEDS_TIMELOCK_SEC);
+    assert!(env.apply_proceeds(deployer).is_err());
+    assert_eq!(env.pool().creator_usdc, creator_usdc);
+
+    // Or replaced, which restarts the timelock.
+    env.propose_proceeds(deployer, mistaken_usdc, new_watermelon)
+        .unwrap();
+    env.test.warp_to(env.test.now() + PROCEEDS_TIMELOCK_SEC / 2);
+    env.propose_proceeds(deployer, new_usdc, new_watermelon)
+        .unwrap();
+    env.test.warp_to(env.test.now() + PROCEEDS_TIMELOCK_SEC / 2);
+    assert_error(env.apply_proceeds(deployer), ErrorCode::ProceedsTimelocked);
+    assert_eq!(env.pool().creator_usdc, creator_usdc);
+
+    env.test.warp_to(env.test.now() + PROCEEDS_TIMELOCK_SEC / 2);
+    env.apply_proceeds(deployer).unwrap();
+    let pool = env.pool();
+    assert_eq!(pool.creator_usdc, new_usdc);
+    assert_eq!(pool.creator_watermelon, new_watermelon);
+    assert!(!env.test.exists(&env.proceeds_change_address()));
+
+    env.withdraw_usdc(deployer, 100 * USDC).unwrap();
+    assert_eq!(env.test.balance(&new_usdc), 100 * USDC);
+}
+
+#[test]
+fn only_unsold_watermelon_can_be_withdrawn() {
+    let mut env = Env::initialized();
+    let user = env.user(200 * USDC);
+    env.warp_to_phase(PoolStatus::Depositing);
+    env.deposit(&user, 200 * USDC, None).unwrap();
+
+    env.warp_to_phase(PoolStatus::Settling);
+    assert_error(
+        env.withdraw_watermelon(env.deployer, WATERMELON),
+        ErrorCode::IdoNotOver,
+    );
+
+    // 0.20 USDC per watermelon is below the floor price, the 200 USDC
+    // deposited only buy 400 watermelon.
+    env.warp_to_phase(PoolStatus::Claimable);
+    let pool = env.pool();
+    assert_eq!(
+        pool.unsold_watermelon(NUM_IDO_TOKENS, 200 * USDC),
+        600 * WATERMELON
+    );
+    assert_error(
+        env.withdraw_watermelon(env.deployer, 600 * WATERMELON + 1),
+        ErrorCode::WithdrawTokensNotAllowed,
+    );
+    env.withdraw_watermelon(env.deployer, 600 * WATERMELON)
+        .unwrap();
+    assert_error(
+        env.withdraw_watermelon(env.deployer, 1),
+        ErrorCode::WithdrawTokensNotAllowed,
+    );
+
+    // The user still gets everything the floor price entitles them to.
+    env.redeem(&user, 200 * USDC).unwrap();
+    assert_eq!(env.test.balance(&user.watermelon), 400 * WATERMELON);
+    assert_eq!(env.test.balance(&env.pool_watermelon), 0);
+}
+
+#[test]
+fn cancelled_or_empty_sales_return_all_watermelon() {
+    let mut env = Env::initialized();
+    let user = env.user(500 * USDC);
+    env.warp_to_phase(PoolStatus::Depositing);
+    env.deposit(&user, 500 * USDC, None).unwrap();
+    env.cancel(env.deployer).unwrap();
+    env.withdraw_watermelon(env.deployer, NUM_IDO_TOKENS)
+        .unwrap();
+    assert_eq!(env.test.balance(&env.pool_watermelon), 0);
+
+    // Nobody deposited, nothing was sold.
+    let mut env = Env::initialized();
+    env.warp_to_phase(PoolStatus::Claimable);
+    env.withdraw_watermelon(env.deployer, NUM_IDO_TOKENS)
+        .unwrap();
+    assert_eq!(
+        env.test.balance(&env.creator_watermelon),
+        2 * NUM_IDO_TOKENS
+    );
+}
+
+#[test]
+fn sale_statistics() {
+    let mut env = Env::initialized();
+    let first = env.user(600 * USDC);
+    let second = env.user(1_400 * USDC);
+    let cranker = env.user(0);
+    env.warp_to_phase(PoolStatus::Depositing);
+    env.deposit(&first, 200 * USDC, None).unwrap();
+    env.deposit(&first, 400 * USDC, None).unwrap();
+    env.deposit(&second, 1_400 * USDC, None).unwrap();
+    let pool = env.pool();
+    assert_eq!(pool.total_usdc_deposited, 2_000 * USDC);
+    assert_eq!(pool.stats.depositors, 2);
+
+    // Redemptions and crank payouts add up to the whole sale.
+    env.warp_to_phase(PoolStatus::Claimable);
+    env.redeem(&first, 600 * USDC).unwrap();
+    env.distribute(cranker.watermelon, &[&second]).unwrap();
+    let stats = env.pool().stats;
+    assert_eq!(stats.redeemable_burned, 2_000 * USDC);
+    assert_eq!(stats.watermelon_distributed, NUM_IDO_TOKENS);
+    assert_eq!(stats.usdc_withdrawn, 0);
+}
+
+#[test]
+fn modify_ido_time() {
+    let mut env = Env::initialized();
+    let schedule = env.schedule;
+    let delayed = PoolSchedule {
+        start_ido_ts: schedule.start_ido_ts + HOUR,
+        end_deposits_ts: schedule.end_deposits_ts + HOUR,
+        end_ido_ts: schedule.end_ido_ts + HOUR,
+        withdraw_melon_ts: schedule.withdraw_melon_ts + HOUR,
+    };
+
+    let impostor = env.impostor();
+    assert_error(
+        env.modify_ido_time(impostor, delayed),
+        ErrorCode::InvalidParam,
+    );
+    let unordered = PoolSchedule {
+        end_deposits_ts: delayed.start_ido_ts,
+        ..delayed
+    };
+    assert_error(
+        env.modify_ido_time(env.deployer, unordered),
+        ErrorCode::SeqTimes,
+    );
+    let too_soon = PoolSchedule {
+        start_ido_ts: env.test.now() + HOUR,
+        ..schedule
+    };
+    assert_error(
+        env.modify_ido_time(env.deployer, too_soon),
+        ErrorCode::RescheduleNotice,
+    );
+    let too_late = PoolSchedule {
+        withdraw_melon_ts: schedule.withdraw_melon_ts + 8 * DAY,
+        ..schedule
+    };
+    assert_error(
+        env.modify_ido_time(env.deployer, too_late),
+        ErrorCode::MaxDelayExceeded,
+    );
+
+    env.modify_ido_time(env.deployer, delayed).unwrap();
+    let pool = env.pool();
+    assert_eq!(pool.schedule, delayed);
+    assert_eq!(pool.schedule_modifications, 1);
+    assert_eq!(pool.original_withdraw_melon_ts, schedule.withdraw_melon_ts);
+    env.schedule = delayed;
+
+    // Once deposits are open only the later timestamps can move.
+    env.warp_to_phase(PoolStatus::Depositing);
+    let restarted = PoolSchedule {
+        start_ido_ts: delayed.start_ido_ts + HOUR,
+        ..delayed
+    };
+    assert_error(
+        env.modify_ido_time(env.deployer, restarted),
+        ErrorCode::PhaseStarted,
+    );
+    let extended = PoolSchedule {
+        withdraw_melon_ts: delayed.withdraw_melon_ts + DAY,
+        ..delayed
+    };
+    env.modify_ido_time(env.deployer, extended).unwrap();
+    assert_eq!(env.pool().schedule_modifications, 2);
+    env.schedule = extended;
+
+    env.warp_to_phase(PoolStatus::Claimable);
+    assert_error(
+        env.modify_ido_time(env.deployer, extended),
+        ErrorCode::EndIdoTime,
+    );
+}
+
+#[test]
+fn proceeds_tranches() {
+    let mut env = Env::initialized();
+    let release_ts = env.schedule.withdraw_melon_ts;
+    let tranches = |first_ts: i64| {
+        let mut tranches = [Tranche::default(); MAX_TRANCHES];
+        tranches[0] = Tranche {
+            release_ts: first_ts,
+            bps: 4_000,
+        };
+        tranches[1] = Tranche {
+            release_ts: release_ts + 30 * DAY,
+            bps: 3_000,
+        };
+        tranches[2] = Tranche {
+            release_ts: release_ts + 60 * DAY,
+            bps: 3_000,
+        };
+        tranches
+    };
+    let impostor = env.impostor();
+    assert_error(
+        env.configure_tranches(tranches(release_ts), impostor),
+        ErrorCode::InvalidParam,
+    );
+    // Before claims open, out of order, or not adding up to the whole raise.
+    assert_error(
+        env.configure_tranches(tranches(release_ts - 1), env.deployer),
+        ErrorCode::InvalidParam,
+    );
+    assert_error(
+        env.configure_tranches(tranches(release_ts + 31 * DAY), env.deployer),
+        ErrorCode::InvalidParam,
+    );
+    let mut short = tranches(release_ts);
+    short[2].bps = 2_000;
+    assert_error(
+        env.configure_tranches(short, env.deployer),
+        ErrorCode::InvalidParam,
+    );
+    env.configure_tranches(tranches(release_ts), env.deployer)
+        .unwrap();
+    assert!(env.pool().tranches.enabled());
+    // Claims cannot be delayed past the first release.
+    assert_error(
+        env.modify_ido_time(
+            env.deployer,
+            PoolSchedule {
+                withdraw_melon_ts: release_ts + HOUR,
+                ..env.schedule
+            },
+        ),
+        ErrorCode::InvalidParam,
+    );
+
+    let first = env.user(500 * USDC);
+    let second = env.user(300 * USDC);
+    let third = env.user(200 * USDC);
+    env.warp_to_phase(PoolStatus::Depositing);
+    assert_error(
+        env.configure_tranches(tranches(release_ts), env.deployer),
+        ErrorCode::IdoFuture,
+    );
+    env.deposit(&first, 500 * USDC, None).unwrap();
+    env.deposit(&second, 300 * USDC, None).unwrap();
+    env.deposit(&third, 200 * USDC, None).unwrap();
+    env.warp_to_phase(PoolStatus::Settling);
+    assert_error(env.vote_halt(&first, 1), ErrorCode::IdoNotOver);
+
+    // The first tranche is released when claims open.
+    env.warp_to_phase(PoolStatus::Claimable);
+    assert_error(
+        env.withdraw_usdc(env.deployer, 400 * USDC + 1),
+        ErrorCode::ProceedsNotReleased,
+    );
+    env.withdraw_usdc(env.deployer, 400 * USDC).unwrap();
+    assert_eq!(env.test.balance(&env.creator_usdc), 400 * USDC);
+    assert_error(env.vote_halt(&first, 0), ErrorCode::TrancheNotOpen);
+    assert_error(env.vote_halt(&first, 2), ErrorCode::TrancheNotOpen);
+
+    env.vote_halt(&second, 1).unwrap();
+    assert_error(env.vote_halt(&second, 1), ErrorCode::AlreadyVoted);
+    assert_error(env.claim_halt_refund(&second), ErrorCode::ProceedsNotHalted);
+    let halt_vote: HaltVote = env.test.account(&env.halt_vote_address(1));
+    assert_eq!(halt_vote.pool_account, env.pool_account);
+    assert_eq!(halt_vote.tranche, 1);
+    assert_eq!(halt_vote.votes, 300 * USDC);
+    assert!(!env.pool().tranches.halted);
+
+    // Users keep redeeming while the vote is open.
+    env.redeem(&second, 300 * USDC).unwrap();
+    env.redeem(&third, 100 * USDC).unwrap();
+    env.vote_halt(&first, 1).unwrap();
+    let pool = env.pool();
+    assert!(pool.tranches.halted);
+    assert_eq!(pool.tranches.halted_tranche, 1);
+    assert_error(env.vote_halt(&third, 1), ErrorCode::ProceedsHalted);
+
+    // The cancelled 60% are refunded pro rata to the deposits, burning the
+    // redeemable tokens still held, whose watermelon goes back to the creator.
+    let creator_watermelon = env.test.balance(&env.creator_watermelon);
+    env.claim_halt_refund(&first).unwrap();
+    assert_eq!(env.test.balance(&first.usdc), 300 * USDC);
+    assert_eq!(env.test.balance(&first.redeemable), 200 * USDC);
+    assert_eq!(
+        env.test.balance(&env.creator_watermelon),
+        creator_watermelon + 300 * WATERMELON
+    );
+    assert_error(env.claim_halt_refund(&first), ErrorCode::AlreadyRefunded);
+    // Tokens already redeemed do not reduce the refund.
+    env.claim_halt_refund(&second).unwrap();
+    assert_eq!(env.test.balance(&second.usdc), 180 * USDC);
+    assert_eq!(
+        env.test.balance(&env.creator_watermelon),
+        creator_watermelon + 300 * WATERMELON
+    );
+
+    // The halted tranches are never released, the first one stays released.
+    env.test.warp_to(release_ts + 90 * DAY);
+    assert_error(
+        env.withdraw_usdc(env.deployer, 1),
+        ErrorCode::ProceedsHalted,
+    );
+    env.redeem(&first, 200 * USDC).unwrap();
+    assert_eq!(env.test.balance(&first.watermelon), 200 * WATERMELON);
+
+    // Refunds close at the claim deadline and the creator sweeps what was
+    // not refunded, leaving the vault empty.
+    env.test.warp_to(env.pool().claim_deadline_ts());
+    assert_error(env.claim_halt_refund(&third), ErrorCode::HaltRefundsClosed);
+    env.withdraw_usdc(env.deployer, 120 * USDC).unwrap();
+    assert_eq!(env.test.balance(&env.creator_usdc), 520 * USDC);
+    assert_eq!(env.test.balance(&env.pool_usdc), 0);
+}
+
+#[test]
+fn halt_refund_after_distribution() {
+    let mut env = Env::initialized();
+    let release_ts = env.schedule.withdraw_melon_ts;
+    let mut tranches = [Tranche::default(); MAX_TRANCHES];
+    tranches[0] = Tranche {
+        release_ts,
+        bps: 5_000,
+    };
+    tranches[1] = Tranche {
+        release_ts: release_ts + 30 * DAY,
+        bps: 5_000,
+    };
+    env.configure_tranches(tranches, env.deployer).unwrap();
+    let first = env.user(400 * USDC);
+    let second = env.user(600 * USDC);
+    let cranker = env.user(0);
+    env.warp_to_phase(PoolStatus::Depositing);
+    env.deposit(&first, 400 * USDC, None).unwrap();
+    env.deposit(&second, 600 * USDC, None).unwrap();
+
+    // The crank burns the first user's tokens while the vote is open, which
+    // does not cost them their refund.
+    env.warp_to_phase(PoolStatus::Claimable);
+    env.vote_halt(&first, 1).unwrap();
+    env.distribute(cranker.watermelon, &[&first]).unwrap();
+    assert_eq!(env.test.balance(&first.redeemable), 0);
+    env.vote_halt(&second, 1).unwrap();
+    assert!(env.pool().tranches.halted);
+    env.claim_halt_refund(&first).unwrap();
+    assert_eq!(env.test.balance(&first.usdc), 200 * USDC);
+}
+
+#[test]
+fn proceeds_released_when_claims_open() {
+    let mut env = Env::initialized();
+    let user = env.user(500 * USDC);
+    env.warp_to_phase(PoolStatus::Depositing);
+    env.deposit(&user, 500 * USDC, None).unwrap();
+
+    // Without tranches everything is released when claims open, so there is
+    // nothing left to vote on.
+    env.warp_to_phase(PoolStatus::Claimable);
+    assert_error(env.vote_halt(&user, 0), ErrorCode::TrancheNotOpen);
+    env.withdraw_usdc(env.deployer, 500 * USDC).unwrap();
+}
+
+#[test]
+fn referral_rewards() {
+    let mut env = Env::initialized();
+    let impostor = env.impostor();
+    assert_error(
+        env.configure_referrals(500, 100 * WATERMELON, impostor),
+        ErrorCode::InvalidParam,
+    );
+    assert_error(
+        env.configure_referrals(10_001, 100 * WATERMELON, env.deployer),
+        ErrorCode::InvalidParam,
+    );
+    env.configure_referrals(500, 100 * WATERMELON, env.deployer)
+        .unwrap();
+    assert_error(
+        env.configure_referrals(500, 0, env.deployer),
+        ErrorCode::ReferralsConfigured,
+    );
+    assert_eq!(
+        env.test.balance(&env.pool_watermelon),
+        NUM_IDO_TOKENS + 100 * WATERMELON
+    );
+
+    let referrer = env.user(100 * USDC);
+    let user = env.user(1_000 * USDC);
+    env.register_referrer(&referrer).unwrap();
+    let record: Referrer = env.test.account(&env.referrer_address(&referrer));
+    assert_eq!(record.authority, referrer.authority);
+
+    env.warp_to_phase(PoolStatus::Depositing);
+    assert_error(
+        env.configure_referrals(500, 0, env.deployer),
+        ErrorCode::IdoFuture,
+    );
+    assert_error(
+        env.deposit(&referrer, 100 * USDC, Some(&referrer)),
+        ErrorCode::InvalidReferrer,
+    );
+    // The referrer account is written to, it has to be passed writable.
+    let mut read_only = env.deposit_ix(&user, 1_000 * USDC, &[], Some(&referrer));
+    read_only.accounts.last_mut().unwrap().is_writable = false;
+    assert_error(
+        env.test.process(read_only, &[&user.authority]),
+        ErrorCode::InvalidReferrer,
+    );
+    env.deposit(&user, 1_000 * USDC, Some(&referrer)).unwrap();
+    env.deposit(&referrer, 100 * USDC, None).unwrap();
+    let record: Referrer = env.test.account(&env.referrer_address(&referrer));
+    assert_eq!(record.referred_usdc, 1_000 * USDC);
+    assert_eq!(env.pool().total_referred_usdc, 1_000 * USDC);
+
+    // The 5% bonus stays in the pool until the referrer claims it.
+    env.warp_to_phase(PoolStatus::Claimable);
+    assert_error(
+        env.withdraw_usdc(env.deployer, 1_100 * USDC),
+        ErrorCode::ReservedForReferrals,
+    );
+    env.withdraw_usdc(env.deployer, 1_050 * USDC).unwrap();
+
+    env.claim_referral_reward(&referrer).unwrap();
+    assert_eq!(env.test.balance(&referrer.usdc), 50 * USDC);
+    assert_eq!(env.test.balance(&referrer.watermelon), 100 * WATERMELON);
+    assert_error(
+        env.claim_referral_reward(&referrer),
+        ErrorCode::AlreadyClaimed,
+    );
+
+    // The referral bucket does not count towards the sale.
+    env.redeem(&user, 1_000 * USDC).unwrap();
+    env.redeem(&referrer, 100 * USDC).unwrap();
+    assert_eq!(env.test.balance(&user.watermelon), 909_090_909_090);
+    assert_eq!(env.test.balance(&env.pool_usdc), 0);
+}
+
+#[test]
+fn kyc_gated_deposits() {
+    let mut env = Env::initialized();
+    let verifier = ed25519_keypair(1);
+    let verifier_key = Pubkey::new(&verifier.public.to_bytes());
+    let impostor = env.impostor();
+    assert_error(
+        env.configure_kyc(verifier_key, impostor),
+        ErrorCode::InvalidParam,
+    );
+    env.configure_kyc(verifier_key, env.deployer).unwrap();
+    assert_eq!(env.pool().kyc_verifier, verifier_key);
+
+    let user = env.user(1_000 * USDC);
+    let other = env.user(100 * USDC);
+    env.warp_to_phase(PoolStatus::Depositing);
+    assert_error(
+        env.configure_kyc(Pubkey::default(), env.deployer),
+        ErrorCode::IdoFuture,
+    );
+    let expiry = env.test.now() + HOUR;
+
+    // The attestation has to be signed by the verifier for this user.
+    assert_error(
+        env.deposit(&user, 100 * USDC, None),
+        ErrorCode::InvalidKycAttestation,
+    );
+    assert_error(
+        env.kyc_deposit(&user, 100 * USDC, &ed25519_keypair(2), expiry, 500 * USDC),
+        ErrorCode::InvalidKycAttestation,
+    );
+    let message = kyc_attestation_message(&env.pool_account, &other.authority, expiry, 500 * USDC);
+    let attestation = ed25519_instruction(&verifier, &message);
+    let deposit = env.deposit_ix(&user, 100 * USDC, &[], None);
+    assert_error(
+        env.test
+            .process_transaction(&[attestation, deposit], &[&user.authority]),
+        ErrorCode::InvalidKycAttestation,
+    );
+    assert_error(
+        env.kyc_deposit(&user, 100 * USDC, &verifier, env.test.now() - 1, 500 * USDC),
+        ErrorCode::KycExpired,
+    );
+
+    // A forged signature is rejected before the program runs.
+    let message = kyc_attestation_message(&env.pool_account, &user.authority, expiry, 500 * USDC);
+    let mut attestation = ed25519_instruction(&verifier, &message);
+    attestation.data[2 + 14 + 32] ^= 1;
+    let deposit = env.deposit_ix(&user, 100 * USDC, &[], None);
+    assert!(env
+        .test
+        .process_transaction(&[attestation, deposit], &[&user.authority])
+        .is_err());
+    assert_eq!(env.test.balance(&user.usdc), 1_000 * USDC);
+
+    // The allocation caps what the user deposits in total.
+    env.kyc_deposit(&user, 300 * USDC, &verifier, expiry, 500 * USDC)
+        .unwrap();
+    assert_error(
+        env.kyc_deposit(&user, 201 * USDC, &verifier, expiry, 500 * USDC),
+        ErrorCode::KycAllocationExceeded,
+    );
+    env.kyc_deposit(&user, 200 * USDC, &verifier, expiry, 500 * USDC)
+        .unwrap();
+    assert_eq!(env.test.balance(&user.redeemable), 500 * USDC);
+    assert_eq!(env.pool().total_usdc_deposited, 500 * USDC);
+}
+
+#[test]
+fn token_gated_deposits() {
+    let mut env = Env::initialized();
+    let governance_mint = env.test.create_mint(&Pubkey::new_unique(), 6);
+    let gate = ParticipationGate {
+        kind: GateKind::Token,
+        key: governance_mint,
+        min_amount: 50 * USDC,
+    };
+    let impostor = env.impostor();
+    assert_error(env.configure_gate(gate, impostor), ErrorCode::InvalidParam);
+    assert_error(
+        env.configure_gate(
+            ParticipationGate {
+                min_amount: 0,
+                ..gate
+            },
+            env.deployer,
+        ),
+        ErrorCode::InvalidParam,
+    );
+    env.configure_gate(gate, env.deployer).unwrap();
+    assert_eq!(env.pool().gate, gate);
+
+    let holder = env.user(100 * USDC);
+    let holding = env
+        .test
+        .create_token_account(&governance_mint, &holder.authority, 50 * USDC);
+    let small = env.user(100 * USDC);
+    let small_holding =
+        env.test
+            .create_token_account(&governance_mint, &small.authority, 49 * USDC);
+    let referrer = env.user(0);
+    env.register_referrer(&referrer).unwrap();
+
+    env.warp_to_phase(PoolStatus::Depositing);
+    assert_error(
+        env.configure_gate(ParticipationGate::default(), env.deployer),
+        ErrorCode::IdoFuture,
+    );
+    assert_error(
+        env.deposit(&holder, 100 * USDC, None),
+        ErrorCode::GateNotSatisfied,
+    );
+    assert_error(
+        env.gated_deposit(&small, 100 * USDC, &[small_holding], None),
+        ErrorCode::GateNotSatisfied,
+    );
+    // The holding has to be the depositor's and of the gate token.
+    assert_error(
+        env.gated_deposit(&small, 100 * USDC, &[holding], None),
+        ErrorCode::GateNotSatisfied,
+    );
+    assert_error(
+        env.gated_deposit(&holder, 100 * USDC, &[holder.usdc], None),
+        ErrorCode::GateNotSatisfied,
+    );
+    env.gated_deposit(&holder, 100 * USDC, &[holding], Some(&referrer))
+        .unwrap();
+    assert_eq!(env.test.balance(&holder.redeemable), 100 * USDC);
+    let record: Referrer = env.test.account(&env.referrer_address(&referrer));
+    assert_eq!(record.referred_usdc, 100 * USDC);
+}
+
+#[test]
+fn collection_gated_deposits() {
+    let mut env = Env::initialized();
+    let collection = Pubkey::new_unique();
+    env.configure_gate(
+        ParticipationGate {
+            kind: GateKind::Collection,
+            key: collection,
+            min_amount: 0,
+        },
+        env.deployer,
+    )
+    .unwrap();
+
+    let holder = env.user(100 * USDC);
+    let (nft, mint, metadata) = env.create_nft(&holder, collection, true, 0);
+    let other = env.user(100 * USDC);
+    let unverified = env.create_nft(&other, collection, false, 0);
+    let foreign = env.create_nft(&other, Pubkey::new_unique(), true, 0);
+    let divisible = env.create_nft(&other, collection, true, 1);
+    let editions = env.create_nft(&other, collection, true, 0);
+    env.test
+        .create_token_account(&editions.1, &Pubkey::new_unique(), 1);
+
+    env.warp_to_phase(PoolStatus::Depositing);
+    assert_error(
+        env.gated_deposit(&holder, 100 * USDC, &[nft, mint], None),
+        ErrorCode::GateNotSatisfied,
+    );
+    for (token, mint, metadata) in [unverified, foreign, divisible, editions] {
+        assert_error(
+            env.gated_deposit(&other, 100 * USDC, &[token, mint, metadata], None),
+            ErrorCode::GateNotSatisfied,
+        );
+    }
+    // The mint and the metadata have to be the ones of the NFT held.
+    assert_error(
+        env.gated_deposit(&other, 100 * USDC, &[foreign.0, mint, metadata], None),
+        ErrorCode::GateNotSatisfied,
+    );
+    assert_error(
+        env.gated_deposit(&other, 100 * USDC, &[foreign.0, foreign.1, metadata], None),
+        ErrorCode::GateNotSatisfied,
+    );
+    assert_error(
+        env.gated_deposit(&other, 100 * USDC, &[nft, mint, metadata], None),
+        ErrorCode::GateNotSatisfied,
+    );
+    // Metadata written before collections has none.
+    let mut data = env.test.data(&metadata).to_vec();
+    let mut rest: &[u8] = &data;
+    Metadata::deserialize(&mut rest).unwrap();
+    let offset = data.len() - rest.len();
+    data[offset..].fill(0);
+    let original = env.test.data(&metadata).to_vec();
+    env.test.set_data(&metadata, data);
+    assert_error(
+        env.gated_deposit(&holder, 100 * USDC, &[nft, mint, metadata], None),
+        ErrorCode::GateNotSatisfied,
+    );
+    env.test.set_data(&metadata, original);
+    env.gated_deposit(&holder, 100 * USDC, &[nft, mint, metadata], None)
+        .unwrap();
+    assert_eq!(env.test.balance(&holder.redeemable), 100 * USDC);
+}
+
+#[test]
+fn blocked_addresses_cannot_deposit_or_claim() {
+    let mut env = Env::initialized();
+    let user = env.user(200 * USDC);
+    let other = env.user(100 * USDC);
+    let cranker = env.user(0);
+    let impostor = env.impostor();
+    assert_error(env.block(user.authority, impostor), ErrorCode::InvalidParam);
+    env.block(user.authority, env.deployer).unwrap();
+    let entry: BlocklistEntry = env.test.account(&blocklist_entry_address(&user.authority));
+    assert_eq!(entry.wallet, user.authority);
+    // Lamports sent to the entry address of a wallet do not block it.
+    env.test
+        .fund(&blocklist_entry_address(&other.authority), LAMPORTS_PER_SOL);
+
+    env.warp_to_phase(PoolStatus::Depositing);
+    assert_error(
+        env.deposit(&user, 100 * USDC, None),
+        ErrorCode::AddressBlocked,
+    );
+    env.deposit(&other, 100 * USDC, None).unwrap();
+    assert_error(
+        env.unblock(user.authority, impostor),
+        ErrorCode::InvalidParam,
+    );
+    env.unblock(user.authority, env.deployer).unwrap();
+    assert!(!env.test.exists(&blocklist_entry_address(&user.authority)));
+    env.deposit(&user, 100 * USDC, None).unwrap();
+
+    // Blocked users cannot claim and the crank skips them.
+    env.block(user.authority, env.deployer).unwrap();
+    env.warp_to_phase(PoolStatus::Claimable);
+    assert_error(env.redeem(&user, 100 * USDC), ErrorCode::AddressBlocked);
+    env.distribute(cranker.watermelon, &[&user, &other])
+        .unwrap();
+    assert_eq!(env.test.balance(&user.watermelon), 0);
+    assert_eq!(env.test.balance(&user.redeemable), 100 * USDC);
+    assert!(env.test.balance(&other.watermelon) > 0);
+
+    env.unblock(user.authority, env.deployer).unwrap();
+    env.redeem(&user, 100 * USDC).unwrap();
+    assert!(env.test.balance(&user.watermelon) > 0);
+}
+
+#[test]
+fn stake_tiered_deposits() {
+    let mut env = Env::initialized();
+    let staking_program = Pubkey::new_unique();
+    let stake_mint = env.test.create_mint(&Pubkey::new_unique(), 6);
+    let mut tiers = [StakeTier::default(); MAX_STAKE_TIERS];
+    tiers[0] = StakeTier {
+        min_stake: 100 * USDC,
+        allocation: 200 * USDC,
+    };
+    tiers[1] = StakeTier {
+        min_stake: 1_000 * USDC,
+        allocation: 1_000 * USDC,
+    };
+    let stake_tiers = StakeTiers {
+        staking_program,
+        stake_mint,
+        guaranteed_end_ts: env.schedule.start_ido_ts + HOUR,
+        tiers,
+    };
+    let impostor = env.impostor();
+    assert_error(
+        env.configure_stake_tiers(stake_tiers, impostor),
+        ErrorCode::InvalidParam,
+    );
+    let mut unsorted = stake_tiers;
+    unsorted.tiers.swap(0, 1);
+    let mut gap = stake_tiers;
+    gap.tiers.swap(1, 2);
+    let late_round = StakeTiers {
+        guaranteed_end_ts: env.schedule.end_deposits_ts + 1,
+        ..stake_tiers
+    };
+    for invalid in [unsorted, gap, late_round] {
+        assert_error(
+            env.configure_stake_tiers(invalid, env.deployer),
+            ErrorCode::InvalidParam,
+        );
+    }
+    env.configure_stake_tiers(stake_tiers, env.deployer)
+        .unwrap();
+    assert_eq!(env.pool().stake_tiers, stake_tiers);
+    // Nor can the sale be rescheduled to start after the guaranteed round.
+    let late_start = PoolSchedule {
+        start_ido_ts: stake_tiers.guaranteed_end_ts,
+        ..env.schedule
+    };
+    assert_error(
+        env.modify_ido_time(env.deployer, late_start),
+        ErrorCode::InvalidParam,
+    );
+    assert_eq!(env.pool().schedule, env.schedule);
+    let mut tranches = [Tranche::default(); MAX_TRANCHES];
+    tranches[0] = Tranche {
+        release_ts: env.schedule.withdraw_melon_ts,
+        bps: 5_000,
+    };
+    tranches[1] = Tranche {
+        release_ts: env.schedule.withdraw_melon_ts + 30 * DAY,
+        bps: 5_000,
+    };
+    env.configure_tranches(tranches, env.deployer).unwrap();
+
+    // Stakes are held by the staking program for each wallet.
+    let staker = |env: &mut Env, stake: u64| {
+        let user = env.user(2_000 * USDC);
+        let authority = stake_authority_address(&staking_program, &user.authority);
+        let vault = env
+            .test
+            .create_token_account(&stake_mint, &authority, stake);
+        (user, vault)
+    };
+    let (whale, whale_vault) = staker(&mut env, 5_000 * USDC);
+    let (small, small_vault) = staker(&mut env, 100 * USDC);
+    let (tiny, tiny_vault) = staker(&mut env, 99 * USDC);
+    let (idle, idle_vault) = staker(&mut env, 100 * USDC);
+    let unregistered = env.user(2_000 * USDC);
+
+    assert_error(
+        env.register_stake(&tiny, tiny_vault),
+        ErrorCode::NoStakeTier,
+    );
+    // Tokens the user can move themselves are not staked.
+    let unlocked = env
+        .test
+        .create_token_account(&stake_mint, &tiny.authority, 5_000 * USDC);
+    assert_error(
+        env.register_stake(&tiny, unlocked),
+        ErrorCode::InvalidStakeAccount,
+    );
+    assert_error(
+        env.register_stake(&tiny, whale_vault),
+        ErrorCode::InvalidStakeAccount,
+    );
+    env.register_stake(&whale, whale_vault).unwrap();
+    env.register_stake(&small, small_vault).unwrap();
+    env.register_stake(&idle, idle_vault).unwrap();
+    let position: UserPosition = env.test.account(&env.position_address(&whale));
+    assert_eq!(position.stake_tier, 2);
+    assert_eq!(position.stake_allocation, 1_000 * USDC);
+    assert_eq!(position.usdc_deposited, 0);
+    let position: UserPosition = env.test.account(&env.position_address(&small));
+    assert_eq!(position.stake_tier, 1);
+    assert_eq!(position.stake_allocation, 200 * USDC);
+
+    env.warp_to_phase(PoolStatus::Depositing);
+    assert_error(
+        env.configure_stake_tiers(StakeTiers::default(), env.deployer),
+        ErrorCode::IdoFuture,
+    );
+    assert_error(env.register_stake(&tiny, tiny_vault), ErrorCode::IdoFuture);
+    assert_error(
+        env.deposit(&unregistered, 100 * USDC, None),
+        ErrorCode::NoStakeTier,
+    );
+    assert_error(
+        env.deposit(&small, 201 * USDC, None),
+        ErrorCode::TierAllocationExceeded,
+    );
+    env.deposit(&small, 150 * USDC, None).unwrap();
+    env.deposit(&small, 50 * USDC, None).unwrap();
+    assert_error(
+        env.deposit(&small, USDC, None),
+        ErrorCode::TierAllocationExceeded,
+    );
+    env.deposit(&whale, 1_000 * USDC, None).unwrap();
+
+    // After the guaranteed round deposits are open to everyone, uncapped.
+    env.test.warp_to(stake_tiers.guaranteed_end_ts);
+    env.deposit(&unregistered, 1_500 * USDC, None).unwrap();
+    env.deposit(&small, 500 * USDC, None).unwrap();
+    let pool = env.pool();
+    assert_eq!(pool.total_usdc_deposited, 3_200 * USDC);
+    // Registering alone does not make a depositor, nor give a say on the
+    // proceeds.
+    assert_eq!(pool.stats.depositors, 3);
+    env.warp_to_phase(PoolStatus::Claimable);
+    assert_error(env.vote_halt(&idle, 1), ErrorCode::NoContribution);
+    env.vote_halt(&small, 1).unwrap();
+}
+
+#[test]
+fn migrate_baseline_pool() {
+    let mut env = Env::initialized();
+    env.warp_to_phase(PoolStatus::Depositing);
+    let user = env.user(200 * USDC);
+    env.deposit(&user, 100 * USDC, None).unwrap();
+
+    // The first release took any token accounts owned by the pool signer as
+    // vaults and stored 209 bytes: the keys, the nonce, the number of tokens
+    // and the schedule.
+    let legacy_usdc = env.test.create_token_account(
+        &env.usdc_mint,
+        &env.pool_signer,
+        env.test.balance(&env.pool_usdc),
+    );
+    let legacy_watermelon = env.test.create_token_account(
+        &env.watermelon_mint,
+        &env.pool_signer,
+        env.test.balance(&env.pool_watermelon),
+    );
+    env.test.remove(&env.pool_usdc);
+    env.test.remove(&env.pool_watermelon);
+    let mut legacy = env.test.data(&env.pool_account)[..209].to_vec();
+    legacy[8 + 32..8 + 64].copy_from_slice(legacy_watermelon.as_ref());
+    legacy[8 + 96..8 + 128].copy_from_slice(legacy_usdc.as_ref());
+    env.test.set_data(&env.pool_account, legacy);
+    assert!(env.deposit(&user, 100 * USDC, None).is_err());
+
+    env.migrate(env.deployer).unwrap();
+    let pool = env.pool();
+    assert_eq!(pool.version, POOL_ACCOUNT_VERSION);
+    assert_eq!(pool.pool_usdc, env.pool_usdc);
+    assert_eq!(pool.pool_watermelon, env.pool_watermelon);
+    assert_eq!(env.test.balance(&env.pool_usdc), 100 * USDC);
+    assert_eq!(env.test.balance(&env.pool_watermelon), NUM_IDO_TOKENS);
+    assert!(!env.test.exists(&legacy_usdc));
+    assert!(!env.test.exists(&legacy_watermelon));
+    assert_eq!(pool.total_usdc_deposited, 100 * USDC);
+    assert_eq!(
+        pool.original_withdraw_melon_ts,
+        env.schedule.withdraw_melon_ts
+    );
+    assert_eq!(pool.creator_usdc, env.creator_usdc);
+    assert_eq!(pool.creator_watermelon, env.creator_watermelon);
+
+    // The pool works as if it had been created by the current program.
+    env.deposit(&user, 100 * USDC, None).unwrap();
+    env.warp_to_phase(PoolStatus::Claimable);
+    env.redeem(&user, 200 * USDC).unwrap();
+    assert_eq!(
+        env.test.balance(&user.watermelon),
+        ido_pool::redemption_amount(200 * USDC, 200 * USDC, NUM_IDO_TOKENS)
+    );
+    env.withdraw_usdc(env.deployer, 200 * USDC).unwrap();
+    assert_eq!(env.test.balance(&env.creator_usdc), 200 * USDC);
+}
+
+#[test]
+fn migrate_position() {
+    let mut env = Env::initialized();
+    let user = env.user(200 * USDC);
+    let other = env.user(200 * USDC);
+    env.warp_to_phase(PoolStatus::Depositing);
+    env.deposit(&user, 100 * USDC, None).unwrap();
+    env.deposit(&other, 100 * USDC, None).unwrap();
+    let position = env.position_address(&user);
+    let other_position = env.position_address(&other);
+    assert_eq!(env.test.data(&position).len(), UserPosition::space());
+    let payer = env.impostor();
+    assert_error(
+        env.migrate_position(position, payer),
+        ErrorCode::PositionUpToDate,
+    );
+
+    // Positions created before stake tiers lack the 9 bytes of the snapshot.
+    // They cannot grow in place, the first call stashes and closes them.
+    for address in [position, other_position] {
+        let old = env.test.data(&address)[..UserPosition::space() - 9].to_vec();
+        env.test.set_data(&address, old);
+    }
+    assert!(env.deposit(&user, 100 * USDC, None).is_err());
+    assert!(env.migrate_position(env.pool_account, payer).is_err());
+    env.migrate_position(position, payer).unwrap();
+    assert!(!env.test.exists(&position));
+    let stash = position_stash_address(&position);
+    let stashed: UserPosition = env.test.account(&stash);
+    assert_eq!(stashed.usdc_deposited, 100 * USDC);
+
+    // The second recreates it at the current size.
+    env.migrate_position(position, payer).unwrap();
+    assert!(!env.test.exists(&stash));
+    assert_eq!(env.test.data(&position).len(), UserPosition::space());
+    assert_eq!(
+        env.test.lamports(&position),
+        Rent::default().minimum_balance(UserPosition::space())
+    );
+    assert_error(
+        env.migrate_position(position, payer),
+        ErrorCode::PositionUpToDate,
+    );
+    env.deposit(&user, 100 * USDC, None).unwrap();
+    let record: UserPosition = env.test.account(&position);
+    assert_eq!(record.owner, user.authority);
+    assert_eq!(record.usdc_deposited, 200 * USDC);
+    assert_eq!(record.stake_tier, 0);
+
+    // A deposit made between the two calls starts a new position, the stash
+    // is added to it.
+    env.migrate_position(other_position, payer).unwrap();
+    env.deposit(&other, 100 * USDC, None).unwrap();
+    let record: UserPosition = env.test.account(&other_position);
+    assert_eq!(record.usdc_deposited, 100 * USDC);
+    env.migrate_position(other_position, payer).unwrap();
+    assert!(!env.test.exists(&position_stash_address(&other_position)));
+    let record: UserPosition = env.test.account(&other_position);
+    assert_eq!(record.owner, other.authority);
+    assert_eq!(record.usdc_deposited, 200 * USDC);
+}
+
+#[test]
+fn lottery_allocation() {
+    let mut env = Env::initialized();
+    let secret = [7u8; 32];
+    let commitment = lottery_commitment(&secret);
+    let impostor = env.impostor();
+    assert_error(
+        env.configure_lottery(100 * USDC, 2, commitment, impostor),
+        ErrorCode::InvalidParam,
+    );
+    for (ticket_usdc, winning_tickets, commitment) in [
+        (0, 2, commitment),
+        (100 * USDC, 0, commitment),
+        (100 * USDC, 2, [0; 32]),
+    ] {
+        assert_error(
+            env.configure_lottery(ticket_usdc, winning_tickets, commitment, env.deployer),
+            ErrorCode::InvalidParam,
+        );
+    }
+    // The draw and the claims of the winners need time between the end of
+    // deposits and claims opening.
+    let schedule = env.schedule;
+    let rushed = PoolSchedule {
+        withdraw_melon_ts: schedule.end_ido_ts + HOUR,
+        ..schedule
+    };
+    env.modify_ido_time(env.deployer, rushed).unwrap();
+    assert_error(
+        env.configure_lottery(100 * USDC, 2, commitment, env.deployer),
+        ErrorCode::LotteryWindowTooShort,
+    );
+    env.modify_ido_time(env.deployer, schedule).unwrap();
+    env.configure_lottery(100 * USDC, 2, commitment, env.deployer)
+        .unwrap();
+    assert_error(
+        env.modify_ido_time(env.deployer, rushed),
+        ErrorCode::LotteryWindowTooShort,
+    );
+    // Lottery pools have no guaranteed round.
+    let mut tiers = [StakeTier::default(); MAX_STAKE_TIERS];
+    tiers[0] = StakeTier {
+        min_stake: 1,
+        allocation: USDC,
+    };
+    let stake_tiers = StakeTiers {
+        staking_program: Pubkey::new_unique(),
+        stake_mint: Pubkey::new_unique(),
+        guaranteed_end_ts: env.schedule.start_ido_ts + HOUR,
+        tiers,
+    };
+    assert_error(
+        env.configure_stake_tiers(stake_tiers, env.deployer),
+        ErrorCode::InvalidParam,
+    );
+
+    let users: Vec<User> = (0..5).map(|_| env.user(100 * USDC)).collect();
+    let poor = env.user(50 * USDC);
+    env.warp_to_phase(PoolStatus::Depositing);
+    assert_error(
+        env.configure_lottery(0, 0, [0; 32], env.deployer),
+        ErrorCode::IdoFuture,
+    );
+    assert_error(
+        env.deposit(&users[0], 100 * USDC, None),
+        ErrorCode::DepositsThroughLottery,
+    );
+    assert_error(env.buy_ticket(&poor), ErrorCode::LowUsdc);
+    for user in &users {
+        env.buy_ticket(user).unwrap();
+        assert_eq!(env.test.balance(&user.usdc), 0);
+    }
+    // A single ticket per user.
+    env.test.fund(&users[0].authority, LAMPORTS_PER_SOL);
+    assert!(env.buy_ticket(&users[0]).is_err());
+    let lottery = env.pool().lottery;
+    assert_eq!(lottery.tickets, 5);
+    assert_eq!(lottery.escrowed_usdc, 500 * USDC);
+    let ticket: LotteryTicket = env.test.account(&env.ticket_address(&users[3]));
+    assert_eq!(ticket.owner, users[3].authority);
+    assert_eq!(ticket.number, 3);
+    assert_error(env.commit_lottery_draw(), ErrorCode::IdoNotOver);
+    assert_error(env.draw_lottery(secret), ErrorCode::IdoNotOver);
+
+    // The draw is seeded by the hash of a slot picked once tickets are sold.
+    env.warp_to_phase(PoolStatus::Grace);
+    assert_error(env.claim_ticket(&users[0]), ErrorCode::LotteryNotDrawn);
+    assert_error(env.refund_ticket(&users[0]), ErrorCode::LotteryNotDrawn);
+    assert_error(env.draw_lottery(secret), ErrorCode::LotteryNotCommitted);
+    env.commit_lottery_draw().unwrap();
+    assert_error(env.commit_lottery_draw(), ErrorCode::InvalidParam);
+    let draw_slot = env.pool().lottery_draw_slot;
+    assert_eq!(draw_slot, env.test.slot() + LOTTERY_DRAW_DELAY_SLOTS);
+    assert_error(env.draw_lottery(secret), ErrorCode::DrawSlotNotReached);
+    // The hash of a slot is known once the next one starts.
+    env.test.advance_slots(LOTTERY_DRAW_DELAY_SLOTS);
+    assert_error(env.draw_lottery(secret), ErrorCode::DrawSlotNotReached);
+    env.test.advance_slots(1);
+    assert_error(env.draw_lottery([8; 32]), ErrorCode::InvalidLotteryReveal);
+    env.draw_lottery(secret).unwrap();
+    assert_error(env.draw_lottery(secret), ErrorCode::LotteryAlreadyDrawn);
+    let lottery = env.pool().lottery;
+    assert!(lottery.drawn);
+    assert_eq!(
+        lottery.seed,
+        hashv(&[&secret, ProgramTest::slot_hash(draw_slot).as_ref()]).to_bytes()
+    );
+    let is_winner = |user: &&User| {
+        let number = users.iter().position(|u| u.authority == user.authority);
+        lottery.is_winner(number.unwrap() as u64)
+    };
+    let (winners, losers): (Vec<&User>, Vec<&User>) = users.iter().partition(is_winner);
+    assert_eq!(winners.len(), 2);
+
+    for loser in &losers {
+        assert_error(env.claim_ticket(loser), ErrorCode::LosingTicket);
+        env.refund_ticket(loser).unwrap();
+        assert_eq!(env.test.balance(&loser.usdc), 100 * USDC);
+        assert!(!env.test.exists(&env.ticket_address(loser)));
+    }
+    let (winner, late_winner) = (winners[0], winners[1]);
+    assert_error(env.refund_ticket(winner), ErrorCode::WinningTicket);
+    env.claim_ticket(winner).unwrap();
+    assert_eq!(env.test.balance(&winner.redeemable), 100 * USDC);
+    assert!(env.claim_ticket(winner).is_err());
+    let pool = env.pool();
+    assert_eq!(pool.total_usdc_deposited, 100 * USDC);
+    assert_eq!(pool.stats.depositors, 1);
+    assert_eq!(pool.lottery.escrowed_usdc, 100 * USDC);
+
+    // Winners that did not claim in time get their escrow back, which never
+    // is part of the proceeds.
+    env.warp_to_phase(PoolStatus::Claimable);
+    assert!(env.claim_ticket(late_winner).is_err());
+    assert_error(
+        env.withdraw_usdc(env.deployer, 101 * USDC),
+        ErrorCode::ReservedForReferrals,
+    );
+    env.refund_ticket(late_winner).unwrap();
+    assert_eq!(env.test.balance(&late_winner.usdc), 100 * USDC);
+    assert_eq!(env.pool().lottery.escrowed_usdc, 0);
+    env.withdraw_usdc(env.deployer, 100 * USDC).unwrap();
+    env.redeem(winner, 100 * USDC).unwrap();
+    assert!(env.test.balance(&winner.watermelon) > 0);
+}
+
+#[test]
+fn lottery_draw_slot() {
+    let secret = [7u8; 32];
+    let lottery_pool = || {
+        let mut env = Env::initialized();
+        env.configure_lottery(100 * USDC, 1, lottery_commitment(&secret), env.deployer)
+            .unwrap();
+        env.warp_to_phase(PoolStatus::Grace);
+        env.commit_lottery_draw().unwrap();
+        let draw_slot = env.pool().lottery_draw_slot;
+        (env, draw_slot)
+    };
+
+    // A skipped slot is replaced by the first one after it.
+    let (mut env, draw_slot) = lottery_pool();
+    env.test.skip_slots(LOTTERY_DRAW_DELAY_SLOTS + 1);
+    env.test.advance_slots(3);
+    env.draw_lottery(secret).unwrap();
+    assert_eq!(
+        env.pool().lottery.seed,
+        hashv(&[&secret, ProgramTest::slot_hash(draw_slot + 1).as_ref()]).to_bytes()
+    );
+
+    // The authority cannot wait for the slot hash to be evicted and pick
+    // another slot. The lottery is never drawn, every ticket is refunded
+    // once claims open.
+    let (mut env, _) = lottery_pool();
+    env.test
+        .advance_slots(LOTTERY_DRAW_DELAY_SLOTS + MAX_ENTRIES as u64 + 1);
+    assert_error(env.draw_lottery(secret), ErrorCode::DrawSlotExpired);
+    assert_error(env.commit_lottery_draw(), ErrorCode::InvalidParam);
+}
+
+#[test]
+fn lottery_ranks_are_a_permutation() {
+    for seed in [[0u8; 32], lottery_commitment(&[1; 32])] {
+        for tickets in 1..=40 {
+            let mut ranks: Vec<u64> = (0..tickets)
+                .map(|number| lottery_rank(&seed, number, tickets))
+                .collect();
+            ranks.sort_unstable();
+            assert_eq!(ranks, (0..tickets).collect::<Vec<_>>());
+        }
+    }
+}
+
+#[test]
+fn advance_pool_status() {
+    let mut env = Env::initialized();
+    assert_error(env.advance_status(), ErrorCode::InvalidStatusTransition);
+
+    for status in [
+        PoolStatus::Depositing,
+        PoolStatus::Grace,
+        PoolStatus::Settling,
+        PoolStatus::Claimable,
+    ] {
+        env.warp_to_phase(status);
+        env.advance_status().unwrap();
+        assert_eq!(env.pool().status, status);
+        assert_error(env.advance_status(), ErrorCode::InvalidStatusTransition);
+    }
+}
+
+#[test]
+fn migrate_pool() {
+    let mut env = Env::initialized();
+    assert_eq!(env.test.data(&env.pool_account).len(), PoolAccount::space());
+    assert_eq!(env.pool().version, POOL_ACCOUNT_VERSION);
+    assert_eq!(env.pool().origin, env.pool_account);
+    assert_error(env.migrate(env.deployer), ErrorCode::PoolUpToDate);
+
+    // Pools created before versioning were allocated 270 bytes, 8 more than
+    // their fields needed.
+    let mut legacy = env.test.data(&env.pool_account)[..8 + 254].to_vec();
+    legacy.resize(270, 0);
+    env.set_legacy_pool(legacy);
+    let user = env.user(100 * USDC);
+    env.warp_to_phase(PoolStatus::Depositing);
+    assert!(env.deposit(&user, 100 * USDC, None).is_err());
+
+    let impostor = env.impostor();
+    assert_error(env.migrate(impostor), ErrorCode::InvalidParam);
+    // The account cannot grow in place, the pool moves to an address derived
+    // from the one it was created at.
+    let mut ix = env.client().migrate(
+        &env.deployer,
+        &env.usdc_mint,
+        &env.creator_usdc,
+        &env.creator_watermelon,
+    );
+    ix.accounts[2].pubkey = env.pool_account;
+    assert_eq!(
+        env.test.process(ix, &[&env.authority, &env.deployer]),
+        Err(anchor_lang::__private::ErrorCode::ConstraintSeeds.into())
+    );
+    let legacy_lamports = env.test.lamports(&env.pool_account);
+    let deployer_lamports = env.test.lamports(&env.deployer);
+    env.migrate(env.deployer).unwrap();
+    assert_eq!(
+        env.pool_account,
+        ido_pool_client::migrated_pool_address(&env.origin)
+    );
+    assert!(!env.test.exists(&env.origin));
+    assert_eq!(env.test.data(&env.pool_account).len(), PoolAccount::space());
+    let rent = Rent::default().minimum_balance(PoolAccount::space());
+    assert_eq!(env.test.lamports(&env.pool_account), rent);
+    assert_eq!(
+        env.test.lamports(&env.deployer),
+        deployer_lamports + legacy_lamports - rent
+    );
+    let pool = env.pool();
+    assert_eq!(pool.version, POOL_ACCOUNT_VERSION);
+    assert_eq!(pool.origin, env.origin);
+    assert_eq!(pool.num_ido_tokens, NUM_IDO_TOKENS);
+    assert_eq!(pool.schedule, env.schedule);
+
+    // Everything else is still derived from the original address.
+    env.deposit(&user, 100 * USDC, None).unwrap();
+    assert_eq!(env.test.balance(&env.pool_usdc), 100 * USDC);
+    let position: UserPosition = env.test.account(&env.position_address(&user));
+    assert_eq!(position.pool_account, env.origin);
+    assert_error(env.migrate(env.deployer), ErrorCode::PoolUpToDate);
+
+    // Later versions added the KYC verifier (32 bytes), the participation
+    // gate (41 bytes), the stake tiers (136 bytes), the lottery (97 bytes),
+    // the proceeds tranches (42 bytes), the lottery draw slot (8 bytes) then
+    // enlarged the reserved space (240 bytes), all are left unset.
+    let mut deposited = 100 * USDC;
+    for (version, missing) in [
+        (1u8, 32 + 41 + 136 + 97 + 42 + 8 + 240),
+        (2, 41 + 136 + 97 + 42 + 8 + 240),
+        (3, 136 + 97 + 42 + 8 + 240),
+        (4, 97 + 42 + 8 + 240),
+        (5, 42 + 8 + 240),
+        (6, 8 + 240),
+        (7, 240),
+    ] {
+        let mut old = env.test.data(&env.pool_account)[..PoolAccount::space() - missing].to_vec();
+        old[8 + 254] = version;
+        env.set_legacy_pool(old);
+        let other = env.user(100 * USDC);
+        assert!(env.deposit(&other, 100 * USDC, None).is_err());
+        // Proceeds accounts already recorded can only change through the
+        // timelock.
+        let elsewhere = env
+            .test
+            .create_token_account(&env.usdc_mint, &env.deployer, 0);
+        let ix = env.client().migrate(
+            &env.deployer,
+            &env.usdc_mint,
+            &elsewhere,
+            &env.creator_watermelon,
+        );
+        assert_eq!(
+            env.test.process(ix, &[&env.authority, &env.deployer]),
+            Err(anchor_lang::__private::ErrorCode::ConstraintHasOne.into())
+        );
+        env.migrate(env.deployer).unwrap();
+        assert!(!env.test.exists(&env.origin));
+        assert_eq!(env.test.data(&env.pool_account).len(), PoolAccount::space());
+        let pool = env.pool();
+        assert_eq!(pool.version, POOL_ACCOUNT_VERSION);
+        assert_eq!(pool.origin, env.origin);
+        assert_eq!(pool.kyc_verifier, Pubkey::default());
+        assert_eq!(pool.gate, ParticipationGate::default());
+        assert_eq!(pool.stake_tiers, StakeTiers::default());
+        assert_eq!(pool.lottery, Lottery::default());
+        assert_eq!(pool.tranches, ProceedsTranches::default());
+        assert_eq!(pool.lottery_draw_slot, 0);
+        assert_eq!(pool.total_usdc_deposited, deposited);
+        env.deposit(&other, 100 * USDC, None).unwrap();
+        deposited += 100 * USDC;
+    }
+
+    // Version 8 pools already have the current size and are migrated in
+    // place, the origin takes reserved bytes that read as zero.
+    let mut old = env.test.data(&env.pool_account).to_vec();
+    let origin_offset = PoolAccount::space() - 8 * 28 - 32;
+    old[origin_offset..origin_offset + 32].fill(0);
+    old[8 + 254] = 8;
+    env.set_legacy_pool(old);
+    env.migrate(env.deployer).unwrap();
+    assert_eq!(env.pool_account, env.origin);
+    assert_eq!(env.pool().origin, env.origin);
+    assert_eq!(env.pool().version, POOL_ACCOUNT_VERSION);
+    let other = env.user(100 * USDC);
+    env.deposit(&other, 100 * USDC, None).unwrap();
+}
@@ -0,0 +1,906 @@
+// In-process test runtime for the ido-pool program.
+//
+// `solana-program-test` 1.8.16, the release matching the program's
+// `solana-program`, does not build with the toolchain the rest of
+// `Cargo.lock` needs: rustc 1.95 rejects its `add_builtin!` invocations of
+// the BPF loader macros with "expected None-delimited group", and toolchains
+// old enough to accept them cannot build the locked dependencies. So this
+// follows what it does for native programs instead: the real program,
+// spl-token, associated token account and token metadata processors run
+// against accounts serialized like the BPF loader serializes them and read
+// back with `entrypoint::deserialize`, the program entrypoint's own code. CPIs
+// go through the syscall stubs, which hand the callee its own input and copy
+// the results back into the caller's like the 1.8 loader does, and the clock
+// sysvar can be warped to any timestamp. Transaction level checks (fees,
+// signatures) are not modelled; signers are simply the keys passed to
+// `process`. There is no fee payer either, so only the accounts the
+// transaction's instructions mark writable can be written to. Ed25519 program
+// instructions are verified like the runtime's precompile does. After every
+// instruction and CPI the account changes are checked like the runtime
+// checks them, see `verify_change`.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::{
+    deserialize, ProgramResult, BPF_ALIGN_OF_U128, MAX_PERMITTED_DATA_INCREASE,
+};
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::message::Message;
+use anchor_lang::solana_program::program_error::ProgramError;
+use anchor_lang::solana_program::program_option::COption;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+use anchor_lang::solana_program::slot_hashes::SlotHashes;
+use anchor_lang::solana_program::system_instruction::SystemInstruction;
+use anchor_lang::solana_program::sysvar::instructions::store_current_index;
+use anchor_lang::solana_program::{ed25519_program, hash, system_program, sysvar};
+use anchor_lang::AccountDeserialize;
+use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::sync::Once;
+
+pub const LAMPORTS_PER_SOL: u64 = 1_000_000_000;
+
+#[derive(Clone, Default, PartialEq)]
+struct StoredAccount {
+    lamports: u64,
+    data: Vec<u8>,
+    owner: Pubkey,
+    executable: bool,
+}
+
+impl StoredAccount {
+    fn from_info(info: &AccountInfo) -> Self {
+        StoredAccount {
+            lamports: info.lamports(),
+            data: info.data.borrow().to_vec(),
+            owner: *info.owner,
+            executable: info.executable,
+        }
+    }
+}
+
+thread_local! {
+    // Accounts of the instruction being processed, as the last program to
+    // return or make a CPI left them.
+    static ACCOUNTS: RefCell<HashMap<Pubkey, StoredAccount>> = RefCell::new(HashMap::new());
+    // Programs currently executing, the last one is the caller of a CPI.
+    static FRAMES: RefCell<Vec<Frame>> = const { RefCell::new(Vec::new()) };
+    // Last data set with `set_return_data` and the program that set it.
+    static RETURN_DATA: RefCell<Option<(Pubkey, Vec<u8>)>> = const { RefCell::new(None) };
+}
+
+// A program being executed and the input its accounts were deserialized
+// from.
+struct Frame {
+    program_id: Pubkey,
+    input: *mut u8,
+    // Where each distinct account is serialized in `input`.
+    offsets: Vec<(Pubkey, usize)>,
+    // The accounts as they were when the program started, or when its last
+    // CPI returned.
+    pre_states: Vec<PreState>,
+}
+
+impl Frame {
+    fn offset(&self, key: &Pubkey) -> usize {
+        self.offsets.iter().find(|(k, _)| k == key).unwrap().1
+    }
+
+    fn pre_state(&mut self, key: &Pubkey) -> &mut PreState {
+        self.pre_states
+            .iter_mut()
+            .find(|state| state.key == *key)
+            .unwrap()
+    }
+}
+
+struct Stubs;
+
+impl SyscallStubs for Stubs {
+    fn sol_log(&self, message: &str) {
+        if std::env::var("IDO_POOL_TEST_LOG").is_ok() {
+            println!("{}", message);
+        }
+    }
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let caller = FRAMES.with(|frames| frames.borrow().last().unwrap().program_id);
+        let pda_signers = signers_seeds
+            .iter()
+            .map(|seeds| Pubkey::create_program_address(seeds, &caller))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|_| ProgramError::InvalidSeeds)?;
+
+        let mut metas = Vec::with_capacity(instruction.accounts.len());
+        let mut infos: Vec<&AccountInfo> = Vec::new();
+        for meta in &instruction.accounts {
+            let info = account_infos
+                .iter()
+                .find(|info| info.key == &meta.pubkey)
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            if meta.is_signer && !info.is_signer && !pda_signers.contains(&meta.pubkey) {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            // Like the 1.8 runtime, writability is decided by the transaction:
+            // a CPI can write to the accounts it marks writable, whatever the
+            // account metas of the CPI say, and to no other.
+            if meta.is_writable && !info.is_writable {
+                return Err(privilege_violation("writable escalated", &meta.pubkey));
+            }
+            metas.push(AccountMeta {
+                pubkey: meta.pubkey,
+                is_signer: meta.is_signer,
+                is_writable: info.is_writable,
+            });
+            if !infos.iter().any(|other| other.key == info.key) {
+                infos.push(info);
+            }
+        }
+        sync_caller_changes(&infos)?;
+        invoke(&instruction.program_id, &metas, &instruction.data)?;
+        update_caller(&infos)
+    }
+
+    fn sol_set_return_data(&mut self, data: &[u8]) {
+        let program_id = FRAMES.with(|frames| frames.borrow().last().unwrap().program_id);
+        RETURN_DATA.with(|return_data| {
+            *return_data.borrow_mut() = Some((program_id, data.to_vec()));
+        });
+    }
+
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        RETURN_DATA.with(|return_data| return_data.borrow().clone())
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        anchor_lang::solana_program::entrypoint::SUCCESS
+    }
+}
+
+// Runs `program_id` with `metas`, top level or as a CPI, and keeps the
+// account changes once they pass the runtime's checks.
+fn invoke(program_id: &Pubkey, metas: &[AccountMeta], data: &[u8]) -> ProgramResult {
+    let pre_states = ACCOUNTS.with(|accounts| PreState::capture(metas, &accounts.borrow()));
+    let (pre_states, post) = if *program_id == system_program::ID {
+        let mut post: Vec<StoredAccount> = pre_states
+            .iter()
+            .map(|state| state.account.clone())
+            .collect();
+        process_system(metas, &pre_states, &mut post, data)?;
+        (pre_states, post)
+    } else {
+        execute(program_id, metas, pre_states, data)?
+    };
+    let (mut lamports_before, mut lamports_after) = (0u128, 0u128);
+    for (state, account) in pre_states.iter().zip(&post) {
+        verify_change(program_id, state, account)?;
+        lamports_before += state.account.lamports as u128;
+        lamports_after += account.lamports as u128;
+    }
+    if lamports_before != lamports_after {
+        return Err(privilege_violation("unbalanced lamports", program_id));
+    }
+    ACCOUNTS.with(|accounts| {
+        let mut accounts = accounts.borrow_mut();
+        for (state, account) in pre_states.iter().zip(post) {
+            accounts.insert(state.key, account);
+        }
+    });
+    Ok(())
+}
+
+// Serializes the accounts for a program, runs it and reads them back.
+// Returns the accounts as they were before the program's last CPI returned
+// and as it left them.
+fn execute(
+    program_id: &Pubkey,
+    metas: &[AccountMeta],
+    pre_states: Vec<PreState>,
+    data: &[u8],
+) -> std::result::Result<(Vec<PreState>, Vec<StoredAccount>), ProgramError> {
+    let (mut buffer, offsets) = serialize(program_id, metas, &pre_states, data);
+    let input = buffer.as_mut_ptr() as *mut u8;
+    FRAMES.with(|frames| {
+        frames.borrow_mut().push(Frame {
+            program_id: *program_id,
+            input,
+            offsets,
+            pre_states,
+        })
+    });
+    let result = {
+        let (program_id, accounts, data) = unsafe { deserialize(input) };
+        process(program_id, &accounts, data)
+    };
+    let frame = FRAMES.with(|frames| frames.borrow_mut().pop().unwrap());
+    result?;
+    let mut post = Vec::with_capacity(frame.offsets.len());
+    for ((_, offset), state) in frame.offsets.iter().zip(&frame.pre_states) {
+        post.push(unsafe { read_account(input, *offset, state.account.data.len()) });
+    }
+    Ok((frame.pre_states, post))
+}
+
+fn process<'a>(
+    program_id: &'a Pubkey,
+    accounts: &'a [AccountInfo<'a>],
+    data: &[u8],
+) -> ProgramResult {
+    if *program_id == ido_pool::ID {
+        ido_pool::process_instruction(program_id, accounts, data)
+    } else if *program_id == spl_token::ID {
+        spl_token::processor::Processor::process(program_id, accounts, data)
+    } else if *program_id == spl_associated_token_account::ID {
+        spl_associated_token_account::processor::process_instruction(program_id, accounts, data)
+    } else if *program_id == spl_token_metadata::ID {
+        spl_token_metadata::processor::process_instruction(program_id, accounts, data)
+    } else {
+        Err(ProgramError::IncorrectProgramId)
+    }
+}
+
+// Before a CPI the runtime checks what the caller changed so far in the
+// accounts it passes, and the callee starts from there.
+fn sync_caller_changes(infos: &[&AccountInfo]) -> ProgramResult {
+    FRAMES.with(|frames| {
+        let mut frames = frames.borrow_mut();
+        let frame = frames.last_mut().unwrap();
+        for info in infos {
+            let account = StoredAccount::from_info(info);
+            let program_id = frame.program_id;
+            verify_change(&program_id, frame.pre_state(info.key), &account)?;
+            ACCOUNTS.with(|accounts| accounts.borrow_mut().insert(*info.key, account));
+        }
+        Ok(())
+    })
+}
+
+// Once the CPI returns, the accounts the caller passed are updated in its
+// input and their state becomes the caller's new starting point. Like the
+// 1.8 loader this only covers writable accounts, and their data can only
+// change size if it was empty, i.e. when the callee created the account.
+fn update_caller(infos: &[&AccountInfo]) -> ProgramResult {
+    FRAMES.with(|frames| {
+        let mut frames = frames.borrow_mut();
+        let frame = frames.last_mut().unwrap();
+        for info in infos {
+            let account = ACCOUNTS.with(|accounts| accounts.borrow()[info.key].clone());
+            if info.is_writable && !account.executable {
+                let header = unsafe { frame.input.add(frame.offset(info.key)) };
+                **info.try_borrow_mut_lamports()? = account.lamports;
+                let mut data = info.try_borrow_mut_data()?;
+                if data.len() != account.data.len() {
+                    if !data.is_empty() || account.data.len() > MAX_PERMITTED_DATA_INCREASE {
+                        return Err(privilege_violation(
+                            "inner instructions do not support realloc",
+                            info.key,
+                        ));
+                    }
+                    unsafe {
+                        *(header.add(DATA_LEN_OFFSET) as *mut u64) = account.data.len() as u64;
+                        *data = std::slice::from_raw_parts_mut(
+                            header.add(DATA_OFFSET),
+                            account.data.len(),
+                        );
+                    }
+                }
+                data.copy_from_slice(&account.data);
+                if *info.owner != account.owner {
+                    // The caller's `AccountInfo`s point into its input, where
+                    // the loader writes the new owner.
+                    unsafe { *(header.add(OWNER_OFFSET) as *mut Pubkey) = account.owner };
+                }
+            }
+            frame.pre_state(info.key).account = account;
+        }
+        Ok(())
+    })
+}
+
+// Where the fields of an account serialized by `serialize` are, from the
+// start of its entry.
+const OWNER_OFFSET: usize = 8 + 32;
+const LAMPORTS_OFFSET: usize = OWNER_OFFSET + 32;
+const DATA_LEN_OFFSET: usize = LAMPORTS_OFFSET + 8;
+const DATA_OFFSET: usize = DATA_LEN_OFFSET + 8;
+
+// Lays out a program's input like the 1.8 loader's aligned serialization:
+// the distinct accounts with `MAX_PERMITTED_DATA_INCREASE` bytes of headroom
+// after their data, duplicates as the index of the first occurrence, then the
+// instruction data and the program id. Also returns where each distinct
+// account starts.
+fn serialize(
+    program_id: &Pubkey,
+    metas: &[AccountMeta],
+    states: &[PreState],
+    data: &[u8],
+) -> (Vec<u64>, Vec<(Pubkey, usize)>) {
+    let mut bytes = Vec::new();
+    let mut offsets = Vec::new();
+    bytes.extend_from_slice(&(metas.len() as u64).to_le_bytes());
+    for (position, meta) in metas.iter().enumerate() {
+        if let Some(first) = metas[..position]
+            .iter()
+            .position(|other| other.pubkey == meta.pubkey)
+        {
+            bytes.push(first as u8);
+            bytes.extend_from_slice(&[0; 7]);
+            continue;
+        }
+        let state = states
+            .iter()
+            .find(|state| state.key == meta.pubkey)
+            .unwrap();
+        offsets.push((meta.pubkey, bytes.len()));
+        bytes.extend_from_slice(&[
+            u8::MAX,
+            state.is_signer as u8,
+            state.is_writable as u8,
+            state.account.executable as u8,
+            0,
+            0,
+            0,
+            0,
+        ]);
+        bytes.extend_from_slice(meta.pubkey.as_ref());
+        bytes.extend_from_slice(state.account.owner.as_ref());
+        bytes.extend_from_slice(&state.account.lamports.to_le_bytes());
+        bytes.extend_from_slice(&(state.account.data.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&state.account.data);
+        let padding = (bytes.len() + MAX_PERMITTED_DATA_INCREASE) % BPF_ALIGN_OF_U128;
+        let padding = (BPF_ALIGN_OF_U128 - padding) % BPF_ALIGN_OF_U128;
+        bytes.resize(bytes.len() + MAX_PERMITTED_DATA_INCREASE + padding, 0);
+        // Rent epoch.
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+    }
+    bytes.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(data);
+    bytes.extend_from_slice(program_id.as_ref());
+
+    // Backed by `u64`s for the alignment the entrypoint expects.
+    bytes.resize((bytes.len() + 7) / 8 * 8, 0);
+    let buffer = bytes
+        .chunks(8)
+        .map(|word| u64::from_ne_bytes(word.try_into().unwrap()))
+        .collect();
+    (buffer, offsets)
+}
+
+// Reads an account back from a program's input like the 1.8 loader does,
+// going by the length in front of the data rather than the slice in the
+// `AccountInfo`s. A new length is taken if it grows the data by at most
+// `MAX_PERMITTED_DATA_INCREASE` bytes, see `verify_change` for whether the
+// runtime accepts it.
+unsafe fn read_account(input: *const u8, offset: usize, pre_len: usize) -> StoredAccount {
+    let header = input.add(offset);
+    let post_len = *(header.add(DATA_LEN_OFFSET) as *const u64) as usize;
+    let len = if post_len.saturating_sub(pre_len) <= MAX_PERMITTED_DATA_INCREASE {
+        post_len
+    } else {
+        pre_len
+    };
+    StoredAccount {
+        lamports: *(header.add(LAMPORTS_OFFSET) as *const u64),
+        data: std::slice::from_raw_parts(header.add(DATA_OFFSET), len).to_vec(),
+        owner: *(header.add(OWNER_OFFSET) as *const Pubkey),
+        executable: *header.add(3) != 0,
+    }
+}
+
+// State of the distinct accounts of an instruction before it runs.
+struct PreState {
+    key: Pubkey,
+    account: StoredAccount,
+    is_signer: bool,
+    is_writable: bool,
+}
+
+impl PreState {
+    fn capture(metas: &[AccountMeta], accounts: &HashMap<Pubkey, StoredAccount>) -> Vec<PreState> {
+        let mut states: Vec<PreState> = Vec::new();
+        for meta in metas {
+            match states.iter_mut().find(|state| state.key == meta.pubkey) {
+                Some(state) => {
+                    state.is_signer |= meta.is_signer;
+                    state.is_writable |= meta.is_writable;
+                }
+                None => states.push(PreState {
+                    key: meta.pubkey,
+                    account: accounts.get(&meta.pubkey).cloned().unwrap_or_default(),
+                    is_signer: meta.is_signer,
+                    is_writable: meta.is_writable,
+                }),
+            }
+        }
+        states
+    }
+}
+
+// What the runtime enforces once a program returns: read-only accounts are
+// left untouched, only the owner of an account can change its data or take
+// lamports from it and owners only hand over zeroed accounts. No lamports can
+// be created or destroyed either, see `invoke`.
+fn verify_change(program_id: &Pubkey, state: &PreState, post: &StoredAccount) -> ProgramResult {
+    let pre = &state.account;
+    let data_changed = post.data != pre.data;
+    let owner_changed = post.owner != pre.owner;
+    if !state.is_writable && (post.lamports != pre.lamports || data_changed || owner_changed) {
+        return Err(privilege_violation(
+            "read-only account modified",
+            &state.key,
+        ));
+    }
+    if pre.owner != *program_id {
+        if data_changed {
+            return Err(privilege_violation(
+                "external account data modified",
+                &state.key,
+            ));
+        }
+        if post.lamports < pre.lamports {
+            return Err(privilege_violation(
+                "external account lamports spent",
+                &state.key,
+            ));
+        }
+        if owner_changed {
+            return Err(privilege_violation(
+                "external account reassigned",
+                &state.key,
+            ));
+        }
+    } else if owner_changed && post.data.iter().any(|byte| *byte != 0) {
+        return Err(privilege_violation(
+            "initialized account reassigned",
+            &state.key,
+        ));
+    }
+    // Only the system program resizes accounts, and only its own: the 1.8
+    // loader hands a program room to grow into, but the change is rejected
+    // with `AccountDataSizeChanged` afterwards.
+    if post.data.len() != pre.data.len()
+        && (*program_id != system_program::ID || pre.owner != system_program::ID)
+    {
+        return Err(privilege_violation("account data resized", &state.key));
+    }
+    Ok(())
+}
+
+// The runtime's `InstructionError`s for these have no `ProgramError`
+// counterpart, the reason is logged instead.
+fn privilege_violation(reason: &str, key: &Pubkey) -> ProgramError {
+    Stubs.sol_log(&format!("{}: {}", reason, key));
+    ProgramError::InvalidArgument
+}
+
+// The subset of the system program used by anchor's `init` constraints,
+// applied to `post`, the accounts of `states` in the same order.
+fn process_system(
+    metas: &[AccountMeta],
+    states: &[PreState],
+    post: &mut [StoredAccount],
+    data: &[u8],
+) -> ProgramResult {
+    let instruction: SystemInstruction =
+        bincode::deserialize(data).map_err(|_| ProgramError::InvalidInstructionData)?;
+    let index = |meta: &AccountMeta| {
+        states
+            .iter()
+            .position(|state| state.key == meta.pubkey)
+            .unwrap()
+    };
+    match instruction {
+        SystemInstruction::CreateAccount {
+            lamports,
+            space,
+            owner,
+        } => {
+            let (from, to) = (&metas[0], &metas[1]);
+            if !from.is_signer || !to.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            let to = index(to);
+            if post[to].lamports > 0 || !post[to].data.is_empty() {
+                // SystemError::AccountAlreadyInUse
+                return Err(ProgramError::Custom(0));
+            }
+            move_lamports(post, index(from), to, lamports)?;
+            allocate(&mut post[to], space)?;
+            post[to].owner = owner;
+            Ok(())
+        }
+        SystemInstruction::Transfer { lamports } => {
+            if !metas[0].is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            move_lamports(post, index(&metas[0]), index(&metas[1]), lamports)
+        }
+        SystemInstruction::Allocate { space } => {
+            if !metas[0].is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            allocate(&mut post[index(&metas[0])], space)
+        }
+        SystemInstruction::Assign { owner } => {
+            if !metas[0].is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            post[index(&metas[0])].owner = owner;
+            Ok(())
+        }
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+fn move_lamports(
+    accounts: &mut [StoredAccount],
+    from: usize,
+    to: usize,
+    lamports: u64,
+) -> ProgramResult {
+    if accounts[from].lamports < lamports {
+        // SystemError::ResultWithNegativeLamports
+        return Err(ProgramError::Custom(1));
+    }
+    accounts[from].lamports -= lamports;
+    accounts[to].lamports += lamports;
+    Ok(())
+}
+
+fn allocate(account: &mut StoredAccount, space: u64) -> ProgramResult {
+    if !account.data.is_empty() {
+        // SystemError::AccountAlreadyInUse
+        return Err(ProgramError::Custom(0));
+    }
+    account.data = vec![0; space as usize];
+    Ok(())
+}
+
+pub struct ProgramTest {
+    accounts: HashMap<Pubkey, StoredAccount>,
+    clock: Clock,
+    slot_hashes: SlotHashes,
+}
+
+impl ProgramTest {
+    pub fn new() -> Self {
+        static STUBS: Once = Once::new();
+        STUBS.call_once(|| {
+            set_syscall_stubs(Box::new(Stubs));
+        });
+
+        let mut test = ProgramTest {
+            accounts: HashMap::new(),
+            clock: Clock::default(),
+            slot_hashes: SlotHashes::default(),
+        };
+        for program in [
+            ido_pool::ID,
+            spl_token::ID,
+            spl_associated_token_account::ID,
+            spl_token_metadata::ID,
+            system_program::ID,
+        ] {
+            test.accounts.insert(
+                program,
+                StoredAccount {
+                    lamports: 1,
+                    executable: true,
+                    owner: Pubkey::default(),
+                    ..StoredAccount::default()
+                },
+            );
+        }
+        test.set_sysvar(&sysvar::rent::ID, &Rent::default());
+        test.warp_to(1_640_995_200);
+        test
+    }
+
+    fn set_sysvar<T: serde::Serialize>(&mut self, id: &Pubkey, sysvar: &T) {
+        self.accounts.insert(
+            *id,
+            StoredAccount {
+                lamports: 1,
+                data: bincode::serialize(sysvar).unwrap(),
+                owner: sysvar::ID,
+                executable: false,
+            },
+        );
+    }
+
+    pub fn now(&self) -> i64 {
+        self.clock.unix_timestamp
+    }
+
+    pub fn slot(&self) -> u64 {
+        self.clock.slot
+    }
+
+    // Moves the clock sysvar to `unix_timestamp`, in either direction, in
+    // the next slot.
+    pub fn warp_to(&mut self, unix_timestamp: i64) {
+        self.clock.unix_timestamp = unix_timestamp;
+        self.advance_slots(1);
+    }
+
+    // Moves the clock `slots` slots ahead without changing the time. Each
+    // slot passed gets an entry in the slot hashes sysvar, which keeps the
+    // most recent `MAX_ENTRIES` like the runtime does.
+    pub fn advance_slots(&mut self, slots: u64) {
+        for _ in 0..slots {
+            let previous = self.clock.slot;
+            self.slot_hashes
+                .add(previous, ProgramTest::slot_hash(previous));
+            self.clock.slot += 1;
+        }
+        let clock = self.clock.clone();
+        self.set_sysvar(&sysvar::clock::ID, &clock);
+        let slot_hashes = std::mem::take(&mut self.slot_hashes);
+        self.set_sysvar(&sysvar::slot_hashes::ID, &slot_hashes);
+        self.slot_hashes = slot_hashes;
+    }
+
+    // Moves the clock `slots` slots ahead as if their leaders had not
+    // produced blocks, they get no slot hashes entry.
+    pub fn skip_slots(&mut self, slots: u64) {
+        self.clock.slot += slots;
+        let clock = self.clock.clone();
+        self.set_sysvar(&sysvar::clock::ID, &clock);
+    }
+
+    // Hash the slot hashes sysvar records for `slot`.
+    pub fn slot_hash(slot: u64) -> hash::Hash {
+        hash::hash(&slot.to_le_bytes())
+    }
+
+    pub fn fund(&mut self, address: &Pubkey, lamports: u64) {
+        self.accounts.entry(*address).or_default().lamports += lamports;
+    }
+
+    pub fn create_mint(&mut self, authority: &Pubkey, decimals: u8) -> Pubkey {
+        let address = Pubkey::new_unique();
+        let mint = Mint {
+            mint_authority: COption::Some(*authority),
+            supply: 0,
+            decimals,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        };
+        self.store_packed(&address, mint, Mint::LEN);
+        address
+    }
+
+    // Creates a token account holding `amount` freshly minted tokens.
+    pub fn create_token_account(&mut self, mint: &Pubkey, owner: &Pubkey, amount: u64) -> Pubkey {
+        let address = Pubkey::new_unique();
+        let account = TokenAccount {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            state: AccountState::Initialized,
+            ..TokenAccount::default()
+        };
+        self.store_packed(&address, account, TokenAccount::LEN);
+        let mut mint_state = self.mint(mint);
+        mint_state.supply += amount;
+        self.store_packed(mint, mint_state, Mint::LEN);
+        address
+    }
+
+    fn store_packed<T: Pack>(&mut self, address: &Pubkey, state: T, len: usize) {
+        let mut data = vec![0u8; len];
+        T::pack(state, &mut data).unwrap();
+        self.accounts.insert(
+            *address,
+            StoredAccount {
+                lamports: Rent::default().minimum_balance(len),
+                data,
+                owner: spl_token::ID,
+                executable: false,
+            },
+        );
+    }
+
+    pub fn mint(&self, address: &Pubkey) -> Mint {
+        Mint::unpack(&self.accounts[address].data).unwrap()
+    }
+
+    pub fn token_account(&self, address: &Pubkey) -> TokenAccount {
+        TokenAccount::unpack(&self.accounts[address].data).unwrap()
+    }
+
+    pub fn balance(&self, address: &Pubkey) -> u64 {
+        self.token_account(address).amount
+    }
+
+    pub fn exists(&self, address: &Pubkey) -> bool {
+        self.accounts
+            .get(address)
+            .map_or(false, |a| a.lamports > 0 || !a.data.is_empty())
+    }
+
+    pub fn lamports(&self, address: &Pubkey) -> u64 {
+        self.accounts.get(address).map_or(0, |a| a.lamports)
+    }
+
+    pub fn data(&self, address: &Pubkey) -> &[u8] {
+        &self.accounts[address].data
+    }
+
+    // Overwrites the data of an existing account, e.g. to recreate a layout
+    // written by an older version of the program.
+    pub fn set_data(&mut self, address: &Pubkey, data: Vec<u8>) {
+        self.accounts.get_mut(address).unwrap().data = data;
+    }
+
+    // Deletes an account, e.g. one an older version of the program did not
+    // create.
+    pub fn remove(&mut self, address: &Pubkey) {
+        self.accounts.remove(address);
+    }
+
+    // Moves an account to another address, e.g. back to where an older
+    // version of the program kept it.
+    pub fn move_account(&mut self, from: &Pubkey, to: &Pubkey) {
+        let account = self.accounts.remove(from).unwrap();
+        self.accounts.insert(*to, account);
+    }
+
+    pub fn account<T: AccountDeserialize>(&self, address: &Pubkey) -> T {
+        let mut data: &[u8] = &self.accounts[address].data;
+        T::try_deserialize(&mut data).unwrap()
+    }
+
+    // Data returned by the last processed instruction, if any.
+    pub fn return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        RETURN_DATA.with(|return_data| return_data.borrow().clone())
+    }
+
+    // Runs `instruction` as if it was the only instruction of a transaction
+    // signed by `signers`. Account changes are only kept if it succeeds.
+    pub fn process(&mut self, instruction: Instruction, signers: &[&Pubkey]) -> ProgramResult {
+        self.process_transaction(&[instruction], signers)
+    }
+
+    // Runs `instructions` as one transaction signed by `signers`, account
+    // changes are only kept if all of them succeed. Programs can read the
+    // transaction from the instructions sysvar.
+    pub fn process_transaction(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Pubkey],
+    ) -> ProgramResult {
+        let snapshot = if instructions.len() > 1 {
+            Some(self.accounts.clone())
+        } else {
+            None
+        };
+        let writable: Vec<Pubkey> = instructions
+            .iter()
+            .flat_map(|instruction| &instruction.accounts)
+            .filter(|meta| meta.is_writable)
+            .map(|meta| meta.pubkey)
+            .collect();
+        let mut instructions_sysvar =
+            Message::new(instructions, None).serialize_instructions(false);
+        instructions_sysvar.extend_from_slice(&[0, 0]);
+        for (index, instruction) in instructions.iter().enumerate() {
+            store_current_index(&mut instructions_sysvar, index as u16);
+            self.accounts.insert(
+                sysvar::instructions::ID,
+                StoredAccount {
+                    lamports: 1,
+                    data: instructions_sysvar.clone(),
+                    owner: sysvar::ID,
+                    executable: false,
+                },
+            );
+            let result = if instruction.program_id == ed25519_program::ID {
+                verify_ed25519(instructions, &instruction.data)
+            } else {
+                self.process_instruction(instruction, signers, &writable)
+            };
+            if result.is_err() {
+                if let Some(snapshot) = snapshot {
+                    self.accounts = snapshot;
+                }
+                return result;
+            }
+        }
+        Ok(())
+    }
+
+    fn process_instruction(
+        &mut self,
+        instruction: &Instruction,
+        signers: &[&Pubkey],
+        writable: &[Pubkey],
+    ) -> ProgramResult {
+        for meta in &instruction.accounts {
+            if meta.is_signer && !signers.contains(&&meta.pubkey) {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+        }
+
+        RETURN_DATA.with(|return_data| return_data.borrow_mut().take());
+        let metas: Vec<AccountMeta> = instruction
+            .accounts
+            .iter()
+            .map(|meta| AccountMeta {
+                pubkey: meta.pubkey,
+                is_signer: signers.contains(&&meta.pubkey),
+                is_writable: writable.contains(&meta.pubkey),
+            })
+            .collect();
+        ACCOUNTS.with(|accounts| {
+            let mut accounts = accounts.borrow_mut();
+            accounts.clear();
+            for meta in &metas {
+                if let Some(account) = self.accounts.get(&meta.pubkey) {
+                    accounts.insert(meta.pubkey, account.clone());
+                }
+            }
+        });
+
+        let result = invoke(&instruction.program_id, &metas, &instruction.data);
+
+        let accounts = ACCOUNTS.with(|accounts| std::mem::take(&mut *accounts.borrow_mut()));
+        if result.is_ok() {
+            for (key, account) in accounts {
+                if account.lamports == 0 {
+                    // Like the runtime, accounts left without lamports are gone.
+                    self.accounts.remove(&key);
+                } else {
+                    self.accounts.insert(key, account);
+                }
+            }
+        }
+        result
+    }
+}
+
+// What the ed25519 precompile checks: every signature in `data` is valid for
+// its public key and message, which can live in any instruction of the
+// transaction (`u16::MAX` being the ed25519 instruction itself).
+fn verify_ed25519(instructions: &[Instruction], data: &[u8]) -> ProgramResult {
+    use ed25519_dalek::{PublicKey, Signature, Verifier};
+
+    let num_signatures = *data.first().ok_or(ProgramError::InvalidInstructionData)? as usize;
+    for i in 0..num_signatures {
+        let offsets = data
+            .get(2 + 14 * i..2 + 14 * (i + 1))
+            .ok_or(ProgramError::InvalidInstructionData)?;
+        let offset = |j: usize| u16::from_le_bytes([offsets[2 * j], offsets[2 * j + 1]]);
+        let slice = |instruction: u16, start: u16, len: usize| {
+            let source = match instruction {
+                u16::MAX => data,
+                index => {
+                    &instructions
+                        .get(index as usize)
+                        .ok_or(ProgramError::InvalidInstructionData)?
+                        .data[..]
+                }
+            };
+            source
+                .get(start as usize..start as usize + len)
+                .ok_or(ProgramError::InvalidInstructionData)
+        };
+        let signature = Signature::from_bytes(slice(offset(1), offset(0), 64)?)
+            .map_err(|_| ProgramError::InvalidArgument)?;
+        let public_key = PublicKey::from_bytes(slice(offset(3), offset(2), 32)?)
+            .map_err(|_| ProgramError::InvalidArgument)?;
+        let message = slice(offset(6), offset(4), offset(5) as usize)?;
+        public_key
+            .verify(message, &signature)
+            .map_err(|_| ProgramError::InvalidArgument)?;
+    }
+    Ok(())
+}
@@ -1,7 +1,8 @@
 export USDC_MINT=Hazq9KKNU7fh2SLm5jr5Pzm3sZjYGuYN65rhWSTtZ5eG
 export AURY_MINT=Ba2KG8Q1cXfH2CvSK9Pf9jBCZXNLYJ3Bk5Qj9anPzkvT
 export AURY_ACCOUNT=6rgixquHLNirJm7hqMNeo4i65k8fPCUEayEvFrbPswju
-export AUTHORITY=9urEjHV3Wm4Pv4Da8uuufRoAuLT9FNAm97wHy3qF9pYy
+# keypair of 9urEjHV3Wm4Pv4Da8uuufRoAuLT9FNAm97wHy3qF9pYy
+export AUTHORITY_KEYPAIR=~/.config/solana/authority.json
 export IDO_AMOUNT=50000000
 export IDO_START_TS=1639350480
 export IDO_DEPOSIT_PERIOD_SEC=1200
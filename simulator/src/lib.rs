@@ -0,0 +1,77 @@
+//! Off-chain model of an ido-pool sale.
+//!
+//! Given the watermelon put up for sale and the deposits, works out the final
+//! price, what every user receives and what is left for the creator. Payouts
+//! go through `ido_pool::redemption_amount`, the function the program uses in
+//! `exchange_redeemable_for_watermelon`, so the results match the chain down
+//! to the rounding.
+
+use serde::Serialize;
+
+pub use ido_pool::FLOOR_PRICE;
+
+// The program's price math assumes these decimals.
+pub const USDC_DECIMALS: u8 = 6;
+pub const WATERMELON_DECIMALS: u8 = 9;
+
+pub struct Deposit {
+    pub user: String,
+    pub usdc: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Allocation {
+    pub user: String,
+    pub usdc: u64,
+    pub watermelon: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Outcome {
+    pub total_usdc: u64,
+    // USDC per watermelon scaled by 10^9, as computed on-chain.
+    pub price: f64,
+    pub floor_applied: bool,
+    pub allocations: Vec<Allocation>,
+    // Watermelon left in the pool once everybody has redeemed, i.e. what the
+    // floor price withheld plus rounding dust.
+    pub creator_leftover: u64,
+}
+
+// Runs a sale of `num_ido_tokens` with the given deposits. Deposits of the
+// same user are merged and users redeem their whole balance in the order
+// they first deposited, each against the live pool balances like on-chain.
+pub fn simulate(num_ido_tokens: u64, deposits: &[Deposit]) -> Outcome {
+    let mut allocations: Vec<Allocation> = Vec::new();
+    for deposit in deposits {
+        match allocations.iter_mut().find(|a| a.user == deposit.user) {
+            Some(allocation) => allocation.usdc += deposit.usdc,
+            None => allocations.push(Allocation {
+                user: deposit.user.clone(),
+                usdc: deposit.usdc,
+                watermelon: 0,
+            }),
+        }
+    }
+
+    // Redeemable tokens are minted 1:1 for USDC.
+    let total_usdc: u64 = allocations.iter().map(|a| a.usdc).sum();
+    let price = ido_pool::token_price(total_usdc, num_ido_tokens);
+
+    let mut redeemable_supply = total_usdc;
+    let mut pool_watermelon = num_ido_tokens;
+    for allocation in allocations.iter_mut().filter(|a| a.usdc > 0) {
+        allocation.watermelon =
+            ido_pool::redemption_amount(allocation.usdc, redeemable_supply, pool_watermelon);
+        redeemable_supply -= allocation.usdc;
+        pool_watermelon -= allocation.watermelon;
+    }
+
+    Outcome {
+        total_usdc,
+        price,
+        floor_applied: price < FLOOR_PRICE,
+        allocations,
+        creator_leftover: pool_watermelon,
+    }
+}
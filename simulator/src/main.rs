@@ -0,0 +1,136 @@
+//! Models the outcome of a sale from a file listing the watermelon for sale
+//! and the expected deposits, TOML or JSON depending on the extension:
+//!
+//! ```toml
+//! watermelon_amount = "1000"
+//!
+//! [[deposits]]
+//! user = "alice"
+//! usdc = "250.5"
+//! ```
+
+use clap::{crate_description, crate_name, App, Arg};
+use ido_pool_simulator::{Deposit, Outcome, USDC_DECIMALS, WATERMELON_DECIMALS};
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+// Amounts are decimal strings in UI units.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SaleSpec {
+    watermelon_amount: String,
+    #[serde(default)]
+    deposits: Vec<DepositSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DepositSpec {
+    user: String,
+    usdc: String,
+}
+
+fn main() {
+    let matches = App::new(crate_name!())
+        .about(crate_description!())
+        .arg(
+            Arg::with_name("sale")
+                .required(true)
+                .help("TOML or JSON file with the sale parameters and deposits"),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .help("print the outcome as JSON, amounts in base units"),
+        )
+        .get_matches();
+
+    let result = run(
+        matches.value_of("sale").unwrap(),
+        matches.is_present("json"),
+    );
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run(path: &str, json: bool) -> Result<()> {
+    let contents = fs::read_to_string(path)?;
+    let spec: SaleSpec = match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(&contents)?,
+        _ => toml::from_str(&contents)?,
+    };
+    let num_ido_tokens = parse_amount(&spec.watermelon_amount, WATERMELON_DECIMALS)?;
+    let deposits = spec
+        .deposits
+        .iter()
+        .map(|deposit| {
+            Ok(Deposit {
+                user: deposit.user.clone(),
+                usdc: parse_amount(&deposit.usdc, USDC_DECIMALS)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let outcome = ido_pool_simulator::simulate(num_ido_tokens, &deposits);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&outcome)?);
+    } else {
+        print_outcome(num_ido_tokens, &outcome);
+    }
+    Ok(())
+}
+
+fn print_outcome(num_ido_tokens: u64, outcome: &Outcome) {
+    println!(
+        "Raised: {} USDC for {} 🍉",
+        format_amount(outcome.total_usdc, USDC_DECIMALS),
+        format_amount(num_ido_tokens, WATERMELON_DECIMALS)
+    );
+    println!("Price: {:.6} USDC per 🍉", outcome.price / 1e9);
+    if outcome.floor_applied {
+        println!("Below the 0.50 floor price, tokens are withheld");
+    }
+    println!();
+    println!("  {:<24} {:>24} {:>24}", "user", "usdc", "watermelon");
+    for allocation in &outcome.allocations {
+        println!(
+            "  {:<24} {:>24} {:>24}",
+            allocation.user,
+            format_amount(allocation.usdc, USDC_DECIMALS),
+            format_amount(allocation.watermelon, WATERMELON_DECIMALS)
+        );
+    }
+    println!();
+    println!(
+        "Left for the creator: {} 🍉",
+        format_amount(outcome.creator_leftover, WATERMELON_DECIMALS)
+    );
+}
+
+fn parse_amount(amount: &str, decimals: u8) -> Result<u64> {
+    let (whole, fraction) = match amount.find('.') {
+        Some(i) => (&amount[..i], &amount[i + 1..]),
+        None => (amount, ""),
+    };
+    if fraction.len() > decimals as usize {
+        return Err(format!("{} has more than {} decimals", amount, decimals).into());
+    }
+    let padded = format!("{}{:0<width$}", whole, fraction, width = decimals as usize);
+    Ok(padded.parse()?)
+}
+
+fn format_amount(amount: u64, decimals: u8) -> String {
+    let unit = 10u64.pow(decimals as u32);
+    format!(
+        "{}.{:0>width$}",
+        amount / unit,
+        amount % unit,
+        width = decimals as usize
+    )
+}
@@ -2,6 +2,8 @@ const anchor = require("@project-serum/anchor");
 const assert = require("assert");
 const {
     TOKEN_PROGRAM_ID,
+    ASSOCIATED_TOKEN_PROGRAM_ID,
+    TOKEN_METADATA_PROGRAM_ID,
     sleep,
     getTokenAccount,
     createMint,
@@ -58,9 +60,10 @@ describe("ido-pool", () => {
     // These are all variables the client will have to create to initialize the
     // IDO pool
     let poolSigner = null; //pda of(watermelon mint)
-    let redeemableMint = null; //owner: poolSigner
-    let poolWatermelon = null; //owner: poolSigner
-    let poolUsdc = null; //owner: poolSigner
+    let redeemableMint = null; //pda of("redeemable_mint", pool account)
+    let redeemableMetadata = null; //metadata pda of(redeemable mint)
+    let poolWatermelon = null; //pda of("pool_watermelon", pool account)
+    let poolUsdc = null; //pda of("pool_usdc", pool account)
     let poolAccount = null; //generated keypair
 
     let startIdoTs = null;
@@ -76,17 +79,29 @@ describe("ido-pool", () => {
             );
         poolSigner = _poolSigner;
 
-        // Pool doesn't need a Redeemable SPL token account because it only
-        // burns and mints redeemable tokens, it never stores them.
-        redeemableMint = await createMint(provider, poolSigner);
-        poolWatermelon = await createTokenAccount(
-            provider,
-            watermelonMint,
-            poolSigner
-        );
-        poolUsdc = await createTokenAccount(provider, usdcMint, poolSigner);
-
         poolAccount = anchor.web3.Keypair.generate();
+        // The program creates the pool vaults and the redeemable mint, which
+        // it names with the token metadata program.
+        [poolWatermelon] = await anchor.web3.PublicKey.findProgramAddress(
+            [Buffer.from("pool_watermelon"), poolAccount.publicKey.toBuffer()],
+            program.programId
+        );
+        [poolUsdc] = await anchor.web3.PublicKey.findProgramAddress(
+            [Buffer.from("pool_usdc"), poolAccount.publicKey.toBuffer()],
+            program.programId
+        );
+        [redeemableMint] = await anchor.web3.PublicKey.findProgramAddress(
+            [Buffer.from("redeemable_mint"), poolAccount.publicKey.toBuffer()],
+            program.programId
+        );
+        [redeemableMetadata] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from("metadata"),
+                TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+                redeemableMint.toBuffer(),
+            ],
+            TOKEN_METADATA_PROGRAM_ID
+        );
         const nowBn = new anchor.BN(Date.now() / 1000);
         startIdoTs = nowBn.add(new anchor.BN(5));
         endDepositsTs = nowBn.add(new anchor.BN(10));
@@ -97,10 +112,13 @@ describe("ido-pool", () => {
         await program.rpc.initializePool(
             watermelonIdoAmount,
             nonce,
-            startIdoTs,
-            endDepositsTs,
-            endIdoTs,
-            withdrawTs,
+            {
+                startIdoTs,
+                endDepositsTs,
+                endIdoTs,
+                withdrawMelonTs: withdrawTs,
+            },
+            "melon",
             {
                 accounts: {
                     poolAccount: poolAccount.publicKey,
@@ -108,12 +126,15 @@ describe("ido-pool", () => {
                     distributionAuthority: provider.wallet.publicKey,
                     payer: provider.wallet.publicKey,
                     creatorWatermelon,
-                    redeemableMint,
+                    creatorUsdc,
                     usdcMint,
+                    redeemableMint,
+                    redeemableMetadata,
                     watermelonMint,
                     poolWatermelon,
                     poolUsdc,
                     tokenProgram: TOKEN_PROGRAM_ID,
+                    tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
                     rent: anchor.web3.SYSVAR_RENT_PUBKEY,
                     clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
                     systemProgram: anchor.web3.SystemProgram.programId,
@@ -127,6 +148,11 @@ describe("ido-pool", () => {
             creatorWatermelon
         );
         assert.ok(creators_watermelon_account.amount.eq(new anchor.BN(0)));
+
+        const metadata = await provider.connection.getAccountInfo(
+            redeemableMetadata
+        );
+        assert.ok(metadata.owner.equals(TOKEN_METADATA_PROGRAM_ID));
     });
 
     // We're going to need to start using the associated program account for creating token accounts
@@ -134,6 +160,8 @@ describe("ido-pool", () => {
 
     let userUsdc = null; //token account
     let userRedeemable = null; //token account
+    let userPosition = null; //pda of("position", pool account, user)
+    let blocklistEntry = null; //pda of("blocklist", user)
     // 10 usdc
     const firstDeposit = new anchor.BN(10_000_349);
 
@@ -160,6 +188,18 @@ describe("ido-pool", () => {
             redeemableMint,
             provider.wallet.publicKey
         );
+        [userPosition] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from("position"),
+                poolAccount.publicKey.toBuffer(),
+                provider.wallet.publicKey.toBuffer(),
+            ],
+            program.programId
+        );
+        [blocklistEntry] = await anchor.web3.PublicKey.findProgramAddress(
+            [Buffer.from("blocklist"), provider.wallet.publicKey.toBuffer()],
+            program.programId
+        );
 
         try {
             const tx = await program.rpc.exchangeUsdcForRedeemable(
@@ -173,8 +213,14 @@ describe("ido-pool", () => {
                         userAuthority: provider.wallet.publicKey,
                         userUsdc,
                         userRedeemable,
+                        userPosition,
                         tokenProgram: TOKEN_PROGRAM_ID,
                         clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
+                        systemProgram: anchor.web3.SystemProgram.programId,
+                        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+                        instructions: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+                        blocklistEntry,
                     },
                 }
             );
@@ -219,8 +265,14 @@ describe("ido-pool", () => {
                 userAuthority: provider.wallet.publicKey,
                 userUsdc: secondUserUsdc,
                 userRedeemable: secondUserRedeemable,
+                userPosition,
                 tokenProgram: TOKEN_PROGRAM_ID,
                 clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+                instructions: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+                blocklistEntry,
             },
         });
 
@@ -232,6 +284,10 @@ describe("ido-pool", () => {
             secondUserRedeemable
         );
         assert.ok(secondUserRedeemableAccount.amount.eq(secondDeposit));
+
+        // Both deposits were made by the same wallet.
+        const position = await program.account.userPosition.fetch(userPosition);
+        assert.ok(position.usdcDeposited.eq(totalPoolUsdc));
     });
 
     // const firstWithdrawal = new anchor.BN(2_000_000);
@@ -276,11 +332,16 @@ describe("ido-pool", () => {
                 poolSigner,
                 redeemableMint,
                 poolWatermelon,
+                watermelonMint,
                 userAuthority: provider.wallet.publicKey,
                 userWatermelon,
                 userRedeemable,
                 tokenProgram: TOKEN_PROGRAM_ID,
                 clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+                blocklistEntry,
             },
         });
 
@@ -307,11 +368,16 @@ describe("ido-pool", () => {
                 poolSigner,
                 redeemableMint,
                 poolWatermelon,
+                watermelonMint,
                 userAuthority: provider.wallet.publicKey,
                 userWatermelon: secondUserWatermelon,
                 userRedeemable: secondUserRedeemable,
                 tokenProgram: TOKEN_PROGRAM_ID,
                 clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
+                systemProgram: anchor.web3.SystemProgram.programId,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+                blocklistEntry,
             },
         });
 
@@ -323,6 +389,20 @@ describe("ido-pool", () => {
         );
     });
 
+    it("Persists the pool status", async () => {
+        await program.rpc.advancePoolStatus({
+            accounts: {
+                poolAccount: poolAccount.publicKey,
+                clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
+            },
+        });
+
+        const pool = await program.account.poolAccount.fetch(
+            poolAccount.publicKey
+        );
+        assert.ok(pool.status.claimable !== undefined);
+    });
+
     it("Withdraws total USDC from pool account", async () => {
         const acc = await getTokenAccount(provider, poolUsdc);
         await program.rpc.withdrawPoolUsdc(new anchor.BN(acc.amount), {
@@ -344,23 +424,64 @@ describe("ido-pool", () => {
         assert.ok(creatorUsdcAccount.amount.eq(totalPoolUsdc));
     });
 
-    it("Modify ido time", async () => {
-        await program.rpc.modifyIdoTime(
-            new anchor.BN(1),
-            new anchor.BN(2),
-            new anchor.BN(3),
-            new anchor.BN(4),
-            {
+    it("Refuses halt votes once every tranche is released", async () => {
+        // Pools without tranches release all proceeds when claims open.
+        const [haltVote] = await anchor.web3.PublicKey.findProgramAddress(
+            [
+                Buffer.from("halt_vote"),
+                poolAccount.publicKey.toBuffer(),
+                Buffer.from([0]),
+            ],
+            program.programId
+        );
+        await assert.rejects(
+            program.rpc.voteHalt(0, {
                 accounts: {
                     poolAccount: poolAccount.publicKey,
-                    distributionAuthority: provider.wallet.publicKey,
-                    payer: provider.wallet.publicKey,
+                    haltVote,
+                    userPosition,
+                    userAuthority: provider.wallet.publicKey,
+                    clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
+                    systemProgram: anchor.web3.SystemProgram.programId,
                 },
+            }),
+            (err) => {
+                assert.equal(
+                    err.msg,
+                    "Only the next tranche to be released can be voted on"
+                );
+                return true;
+            }
+        );
+    });
+
+    it("Refuses to modify ido times once the IDO is over", async () => {
+        await assert.rejects(
+            program.rpc.modifyIdoTime(
+                {
+                    startIdoTs: new anchor.BN(1),
+                    endDepositsTs: new anchor.BN(2),
+                    endIdoTs: new anchor.BN(3),
+                    withdrawMelonTs: new anchor.BN(4),
+                },
+                {
+                    accounts: {
+                        poolAccount: poolAccount.publicKey,
+                        distributionAuthority: provider.wallet.publicKey,
+                        payer: provider.wallet.publicKey,
+                        clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
+                    },
+                }
+            ),
+            (err) => {
+                assert.equal(err.msg, "IDO has ended");
+                return true;
             }
         );
         const pool = await program.account.poolAccount.fetch(
             poolAccount.publicKey
         );
-        assert.equal(pool.startIdoTs.toString(), "1");
+        assert.ok(pool.schedule.startIdoTs.eq(startIdoTs));
+        assert.equal(pool.scheduleModifications, 0);
     });
 });
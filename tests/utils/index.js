@@ -10,6 +10,14 @@ const TOKEN_PROGRAM_ID = new anchor.web3.PublicKey(
   TokenInstructions.TOKEN_PROGRAM_ID.toString()
 );
 
+const ASSOCIATED_TOKEN_PROGRAM_ID = new anchor.web3.PublicKey(
+  "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL"
+);
+
+const TOKEN_METADATA_PROGRAM_ID = new anchor.web3.PublicKey(
+  "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s"
+);
+
 // Our own sleep function.
 function sleep(ms) {
   return new Promise((resolve) => setTimeout(resolve, ms));
@@ -131,6 +139,8 @@ async function createMintToAccountInstrs(
 
 module.exports = {
   TOKEN_PROGRAM_ID,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  TOKEN_METADATA_PROGRAM_ID,
   sleep,
   getTokenAccount,
   createMint,